@@ -0,0 +1,66 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{
+    Form, FormFinder, Harmony, HarmonyAnalyzer, KeyAnalysis, KeyFinder, Motif, MotifFinder, Rhythm,
+    RhythmAnalyzer, VoiceSeparator,
+};
+use crate::{Score, TempoMap};
+use midly::Smf;
+
+/// Everything the composer learns about an input score before generating.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub score: Score,
+    pub tempo_map: TempoMap,
+    pub key: KeyAnalysis,
+    pub harmony: Harmony,
+    pub rhythm: Rhythm,
+    pub motifs: Vec<Motif>,
+    pub form: Form,
+}
+
+impl Analysis {
+    pub fn new(smf: &Smf) -> Self {
+        Self::from_score(Score::from_smf(smf), TempoMap::new(smf))
+    }
+
+    /// Like [`Analysis::new`], on a score where every polyphonic track was
+    /// split into one track per voice.
+    pub fn with_voices(smf: &Smf) -> Self {
+        let tempo_map = TempoMap::new(smf);
+        let score = VoiceSeparator::split(&Score::from_smf(smf), &tempo_map);
+        Self::from_score(score, tempo_map)
+    }
+
+    pub fn from_score(score: Score, tempo_map: TempoMap) -> Self {
+        let key = KeyFinder::analyze(&score, &tempo_map);
+        let harmony = HarmonyAnalyzer::analyze(&score, &tempo_map, &key);
+        let rhythm = RhythmAnalyzer::analyze(&score, &tempo_map);
+        let motifs = MotifFinder::analyze(&score, &tempo_map, &key);
+        let form = FormFinder::analyze(&score, &tempo_map, &rhythm);
+
+        Self {
+            score,
+            tempo_map,
+            key,
+            harmony,
+            rhythm,
+            motifs,
+            form,
+        }
+    }
+}
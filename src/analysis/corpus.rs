@@ -0,0 +1,296 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Analysis, Key, Reader, Result, Score, ScoreEvent, Source};
+use midly::{MetaMessage, TrackEventKind};
+use std::collections::BTreeMap;
+
+/// One analyzed file of a corpus, with its share of the training.
+#[derive(Debug, Clone)]
+pub struct Piece {
+    pub analysis: Analysis,
+    /// Share of the statistics this piece contributes, the shares of a
+    /// corpus adding up to `1.0`.
+    pub weight: f64,
+}
+
+/// Files a composition is trained on.
+///
+/// The first piece is the reference: its tracks, tempo, meter, keys and form
+/// frame the new piece, and every other piece is transposed onto its scale so
+/// the statistics of pieces in different keys line up.
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    pub pieces: Vec<Piece>,
+}
+
+impl Corpus {
+    /// Builds a corpus from analyzed files and their weights, the first one
+    /// being the reference. Weights are normalized so they add up to `1.0`.
+    pub fn new(analyses: Vec<(Analysis, f64)>) -> Self {
+        let total: f64 = analyses.iter().map(|(_, weight)| weight).sum();
+        let reference = analyses.first().map(|(analysis, _)| analysis.key.global);
+
+        let pieces = analyses
+            .into_iter()
+            .map(|(analysis, weight)| Piece {
+                analysis: match reference {
+                    Some(reference) => Self::transpose(analysis, reference),
+                    None => analysis,
+                },
+                weight: if total > 0.0 { weight / total } else { 0.0 },
+            })
+            .collect();
+
+        Self { pieces }
+    }
+
+    /// Reads and analyzes every file of `sources`. A directory or pattern
+    /// shares its weight between the files it stands for.
+    pub fn load(sources: &[Source], split_voices: bool) -> Result<Self> {
+        let mut analyses = Vec::new();
+        for source in sources {
+            let files = source.files()?;
+            let weight = source.weight / files.len() as f64;
+            for file in files {
+                let bytes = Reader::read_file(&file)?;
+                let smf = Reader::parse(&bytes)?;
+                let analysis = if split_voices {
+                    Analysis::with_voices(&smf)
+                } else {
+                    Analysis::new(&smf)
+                };
+                analyses.push((analysis, weight));
+            }
+        }
+
+        Ok(Self::new(analyses))
+    }
+
+    /// Analysis framing the new piece.
+    pub fn reference(&self) -> &Analysis {
+        &self.pieces[0].analysis
+    }
+
+    /// Index of every piece with a part matching the part played on `track`
+    /// and `channel` of the reference, and that part.
+    ///
+    /// Parts are matched by register: pitched parts are ranked from highest
+    /// to lowest and the rank of the reference part is scaled to the number
+    /// of pitched parts of the piece, so melodies learn from melodies and
+    /// basses from basses. Percussion parts are matched the same way among
+    /// themselves. Pieces with no such part are left out.
+    pub fn parts(&self, track: usize, channel: u8) -> Vec<(usize, (usize, u8))> {
+        let reference = Self::ranked_parts(&self.reference().score);
+        let Some((group, rank)) = reference.iter().find_map(|(percussion, parts)| {
+            let rank = parts.iter().position(|&part| part == (track, channel))?;
+            Some((*percussion, (rank, parts.len())))
+        }) else {
+            return Vec::new();
+        };
+
+        self.pieces
+            .iter()
+            .enumerate()
+            .filter_map(|(index, piece)| {
+                let ranked = Self::ranked_parts(&piece.analysis.score);
+                let parts = ranked.get(&group)?;
+                let (rank, count) = rank;
+                let part = if count > 1 {
+                    (rank * (parts.len() - 1) + (count - 1) / 2) / (count - 1)
+                } else {
+                    0
+                };
+                Some((index, parts[part]))
+            })
+            .collect()
+    }
+
+    /// Parts of `score`, grouped by whether they are percussion and ranked
+    /// by mean pitch, highest first.
+    fn ranked_parts(score: &Score) -> BTreeMap<bool, Vec<(usize, u8)>> {
+        let mut pitches: BTreeMap<(usize, u8), (bool, u64, u64)> = BTreeMap::new();
+        for note in &score.notes {
+            let entry =
+                pitches
+                    .entry((note.track, note.channel))
+                    .or_insert((note.is_percussion(), 0, 0));
+            entry.1 += u64::from(note.pitch);
+            entry.2 += 1;
+        }
+
+        let mut means: Vec<(bool, f64, (usize, u8))> = pitches
+            .into_iter()
+            .map(|(part, (percussion, sum, count))| (percussion, sum as f64 / count as f64, part))
+            .collect();
+        means.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut ranked: BTreeMap<bool, Vec<(usize, u8)>> = BTreeMap::new();
+        for (percussion, _, part) in means {
+            ranked.entry(percussion).or_default().push(part);
+        }
+        ranked
+    }
+
+    /// Moves every pitched note and key signature of the analyzed score by
+    /// the smallest interval that brings its global key onto the scale of
+    /// `reference`, then analyzes it again. A piece in the same mode lands on
+    /// the reference tonic, one in the other mode on its relative key. Notes
+    /// pushed out of the MIDI range are moved back by an octave.
+    fn transpose(analysis: Analysis, reference: Key) -> Analysis {
+        let (accidentals, _) = analysis.key.global.signature();
+        let (target, _) = reference.signature();
+        let shift = (7 * (i16::from(target) - i16::from(accidentals)) + 6).rem_euclid(12) - 6;
+        if shift == 0 {
+            return analysis;
+        }
+
+        let Analysis {
+            mut score,
+            tempo_map,
+            ..
+        } = analysis;
+
+        for note in score.notes.iter_mut().filter(|note| !note.is_percussion()) {
+            let pitch = i16::from(note.pitch) + shift;
+            note.pitch = match pitch {
+                128.. => pitch - 12,
+                ..0 => pitch + 12,
+                _ => pitch,
+            } as u8;
+        }
+        for event in &mut score.events {
+            if let TrackEventKind::Meta(MetaMessage::KeySignature(accidentals, minor)) =
+                event.kind()
+            {
+                let key = Key::from_signature(accidentals, minor);
+                let key = Key::new(
+                    (i16::from(key.tonic) + shift).rem_euclid(12) as u8,
+                    key.mode,
+                );
+                let (accidentals, minor) = key.signature();
+                let signature = MetaMessage::KeySignature(accidentals, minor);
+                *event = ScoreEvent::new(event.tick, event.track, TrackEventKind::Meta(signature));
+            }
+        }
+
+        Analysis::from_score(score, tempo_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Mode, Note, TempoMap};
+    use midly::{Format, Header, Timing, num::u15};
+
+    const QUARTER: u64 = 480;
+
+    /// Analysis of parts given as track, channel and pitches played one
+    /// quarter note after the other, under a key signature.
+    fn analysis(parts: &[(usize, u8, &[u8])], signature: (i8, bool)) -> Analysis {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(QUARTER as u16)));
+        let tracks = parts.iter().map(|(track, ..)| track + 1).max().unwrap_or(1);
+        let mut score = Score::new(header, tracks);
+        for &(track, channel, pitches) in parts {
+            score
+                .notes
+                .extend(pitches.iter().enumerate().map(|(index, &pitch)| Note {
+                    start: index as u64 * QUARTER,
+                    duration: QUARTER,
+                    pitch,
+                    velocity: 80,
+                    release: None,
+                    channel,
+                    track,
+                }));
+        }
+        score.sort_notes();
+        score.events.push(ScoreEvent::new(
+            0,
+            0,
+            TrackEventKind::Meta(MetaMessage::KeySignature(signature.0, signature.1)),
+        ));
+
+        let tempo_map = TempoMap::new(&score.to_smf());
+        Analysis::from_score(score, tempo_map)
+    }
+
+    #[test]
+    fn matches_parts_by_register_rank() {
+        let corpus = Corpus::new(vec![
+            (
+                analysis(
+                    &[
+                        (0, 0, &[72, 74]),
+                        (1, 0, &[60, 62]),
+                        (2, 0, &[48, 50]),
+                        (2, 9, &[36, 38]),
+                    ],
+                    (0, false),
+                ),
+                2.0,
+            ),
+            (
+                analysis(&[(0, 1, &[40, 43]), (1, 0, &[76, 79])], (0, false)),
+                1.0,
+            ),
+            (
+                analysis(
+                    &[
+                        (0, 0, &[84]),
+                        (1, 0, &[76]),
+                        (2, 0, &[67]),
+                        (3, 0, &[55]),
+                        (4, 0, &[43]),
+                        (4, 9, &[42]),
+                    ],
+                    (0, false),
+                ),
+                1.0,
+            ),
+        ]);
+
+        let weights: Vec<f64> = corpus.pieces.iter().map(|piece| piece.weight).collect();
+        assert_eq!(weights, [0.5, 0.25, 0.25]);
+
+        assert_eq!(corpus.parts(0, 0), [(0, (0, 0)), (1, (1, 0)), (2, (0, 0))]);
+        assert_eq!(corpus.parts(1, 0), [(0, (1, 0)), (1, (0, 1)), (2, (2, 0))]);
+        assert_eq!(corpus.parts(2, 0), [(0, (2, 0)), (1, (0, 1)), (2, (4, 0))]);
+        assert_eq!(corpus.parts(2, 9), [(0, (2, 9)), (2, (4, 9))]);
+        assert!(corpus.parts(3, 0).is_empty());
+    }
+
+    #[test]
+    fn transposes_onto_the_reference_scale() {
+        let corpus = Corpus::new(vec![
+            (analysis(&[(0, 0, &[60, 64, 67])], (0, false)), 1.0),
+            (analysis(&[(0, 0, &[62, 66, 69])], (2, false)), 1.0),
+            (analysis(&[(0, 0, &[64, 67, 71, 124])], (1, true)), 1.0),
+        ]);
+
+        let pitches = |index: usize| -> Vec<u8> {
+            let analysis = &corpus.pieces[index].analysis;
+            analysis.score.notes.iter().map(|note| note.pitch).collect()
+        };
+        let key = |index: usize| corpus.pieces[index].analysis.key.global;
+
+        assert_eq!(pitches(1), [60, 64, 67]);
+        assert_eq!(key(1), Key::new(0, Mode::Major));
+        assert_eq!(pitches(2), [69, 72, 76, 117]);
+        assert_eq!(key(2), Key::new(9, Mode::Minor));
+    }
+}
@@ -0,0 +1,338 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::Rhythm;
+use crate::{Score, TempoMap};
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+};
+
+/// Phrase lengths tried, in bars, the first being the usual one.
+const PHRASE_LENGTHS: [u32; 4] = [4, 2, 8, 3];
+/// Repetition strength another phrase length needs over the usual one to be
+/// preferred.
+const PHRASE_MARGIN: f64 = 0.05;
+/// Similarity above which a phrase restates an earlier section.
+const SAME_SECTION: f64 = 0.9;
+/// Similarity above which a phrase varies an earlier section.
+const VARIED_SECTION: f64 = 0.7;
+
+/// A span of whole bars, labelled by the material it states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Section {
+    /// `0` for the first material, shown as `A`, `1` for the next
+    /// contrasting one, shown as `B`, and so on. Labels past `Z` are shown
+    /// with two letters, `AA` following `Z`.
+    pub label: u32,
+    /// Number of primes: `A'` varies `A`, `A''` varies it again.
+    pub variant: u8,
+    /// First bar, counted from zero.
+    pub start_bar: u32,
+    pub bars: u32,
+}
+
+impl Display for Section {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut letters = Vec::new();
+        let mut rest = self.label + 1;
+        while rest > 0 {
+            rest -= 1;
+            letters.push(char::from(b'A' + (rest % 26) as u8));
+            rest /= 26;
+        }
+        let label: String = letters.into_iter().rev().collect();
+        write!(f, "{label}{}", "'".repeat(usize::from(self.variant)))
+    }
+}
+
+/// Large-scale structure of a score.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Form {
+    pub phrase_bars: u32,
+    pub sections: Vec<Section>,
+}
+
+impl Display for Form {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let labels: Vec<String> = self.sections.iter().map(Section::to_string).collect();
+        f.write_str(&labels.join(" "))
+    }
+}
+
+/// Finds the form of a score from the self-similarity of its bars.
+///
+/// Two bars are compared on the pitches they sound, weighted by duration,
+/// and on the onsets of every part. The piece is cut into phrases of the
+/// length that repeats best, and each phrase is labelled after the earlier
+/// section it resembles, if any.
+#[derive(Debug)]
+pub struct FormFinder;
+
+impl FormFinder {
+    pub fn analyze(score: &Score, tempo_map: &TempoMap, rhythm: &Rhythm) -> Form {
+        let bar_count = tempo_map.bar_count();
+        if bar_count == 0 {
+            return Form::default();
+        }
+
+        let pitches = Self::pitch_profiles(score, tempo_map, bar_count);
+        let onsets: Vec<BTreeSet<(usize, u32)>> = (0..bar_count as usize)
+            .map(|bar| {
+                rhythm
+                    .parts
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(part, rhythm)| {
+                        rhythm.bars[bar]
+                            .hits
+                            .iter()
+                            .map(move |hit| (part, hit.onset))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let similarity = |a: usize, b: usize| {
+            0.5 * cosine(&pitches[a], &pitches[b]) + 0.5 * jaccard(&onsets[a], &onsets[b])
+        };
+
+        let phrase_bars = Self::phrase_length(bar_count, &similarity);
+        let phrases: Vec<(u32, u32)> = (0..bar_count)
+            .step_by(phrase_bars as usize)
+            .map(|start| (start, phrase_bars.min(bar_count - start)))
+            .collect();
+
+        let mut sections: Vec<Section> = Vec::new();
+        for &(start_bar, bars) in &phrases {
+            let closest = sections
+                .iter()
+                .filter(|section| section.variant == 0)
+                .map(|section| {
+                    let score = segment_similarity(section.start_bar, start_bar, bars, &similarity);
+                    (section.label, score)
+                })
+                .fold(None, |best: Option<(u32, f64)>, candidate| match best {
+                    Some(best) if best.1 >= candidate.1 => Some(best),
+                    _ => Some(candidate),
+                });
+
+            let (label, variant) = match closest {
+                Some((label, score)) if score >= SAME_SECTION => (label, 0),
+                Some((label, score)) if score >= VARIED_SECTION => {
+                    let variants = sections
+                        .iter()
+                        .filter(|section| section.label == label)
+                        .map(|section| section.variant)
+                        .max()
+                        .unwrap_or(0);
+                    (label, variants + 1)
+                }
+                _ => {
+                    let labels = sections.iter().map(|section| section.label).max();
+                    (labels.map_or(0, |label| label + 1), 0)
+                }
+            };
+
+            sections.push(Section {
+                label,
+                variant,
+                start_bar,
+                bars,
+            });
+        }
+
+        Form {
+            phrase_bars,
+            sections,
+        }
+    }
+
+    /// Sounding time of each pitch in every bar.
+    fn pitch_profiles(score: &Score, tempo_map: &TempoMap, bar_count: u32) -> Vec<[f64; 128]> {
+        let starts: Vec<u64> = (0..=bar_count)
+            .map(|bar| tempo_map.bar_start(bar))
+            .collect();
+        let mut profiles = vec![[0.0; 128]; bar_count as usize];
+
+        for note in score.pitched_notes() {
+            let first = tempo_map.position(note.start).bar as usize;
+            for (bar, profile) in profiles.iter_mut().enumerate().skip(first) {
+                if starts[bar] >= note.end() {
+                    break;
+                }
+                let overlap = note.end().min(starts[bar + 1]) - note.start.max(starts[bar]);
+                profile[usize::from(note.pitch)] += overlap as f64;
+            }
+        }
+
+        profiles
+    }
+
+    /// Phrase length whose phrases best restate one another.
+    fn phrase_length(bar_count: u32, similarity: &impl Fn(usize, usize) -> f64) -> u32 {
+        let strength = |length: u32| {
+            let starts: Vec<u32> = (0..bar_count - length + 1)
+                .step_by(length as usize)
+                .collect();
+            let best: Vec<f64> = starts
+                .iter()
+                .map(|&a| {
+                    starts
+                        .iter()
+                        .filter(|&&b| b != a)
+                        .map(|&b| segment_similarity(a, b, length, similarity))
+                        .fold(0.0, f64::max)
+                })
+                .collect();
+            best.iter().sum::<f64>() / best.len().max(1) as f64
+        };
+
+        let usual = PHRASE_LENGTHS[0];
+        let candidates: Vec<(u32, f64)> = PHRASE_LENGTHS
+            .iter()
+            .filter(|&&length| length * 2 <= bar_count)
+            .map(|&length| (length, strength(length)))
+            .collect();
+        let Some(&(_, baseline)) = candidates.iter().find(|(length, _)| *length == usual) else {
+            return candidates
+                .first()
+                .map_or(bar_count.min(usual), |(length, _)| *length);
+        };
+
+        candidates
+            .into_iter()
+            .filter(|(_, strength)| *strength > baseline + PHRASE_MARGIN)
+            .fold((usual, baseline), |best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            })
+            .0
+    }
+}
+
+fn segment_similarity(a: u32, b: u32, bars: u32, similarity: &impl Fn(usize, usize) -> f64) -> f64 {
+    let total: f64 = (0..bars)
+        .map(|offset| similarity((a + offset) as usize, (b + offset) as usize))
+        .sum();
+    total / f64::from(bars.max(1))
+}
+
+fn cosine(a: &[f64; 128], b: &[f64; 128]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm =
+        a.iter().map(|x| x * x).sum::<f64>().sqrt() * b.iter().map(|y| y * y).sum::<f64>().sqrt();
+    match (norm > 0.0, a == b) {
+        (true, _) => dot / norm,
+        (false, equal) => f64::from(u8::from(equal)),
+    }
+}
+
+fn jaccard<T: Ord>(a: &BTreeSet<T>, b: &BTreeSet<T>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Note, analysis::RhythmAnalyzer};
+    use midly::{Format, Header, Timing, num::u15};
+
+    const QUARTER: u64 = 480;
+
+    /// Phrases of four bars on track 0, channel 0, each bar holding the
+    /// given pitches as notes of equal length.
+    fn score(phrases: &[[&[u8]; 4]]) -> Score {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(QUARTER as u16)));
+        let mut score = Score::new(header, 1);
+        for (bar, pitches) in phrases.iter().flatten().enumerate() {
+            let length = 4 * QUARTER / pitches.len() as u64;
+            score
+                .notes
+                .extend(pitches.iter().enumerate().map(|(index, &pitch)| Note {
+                    start: bar as u64 * 4 * QUARTER + index as u64 * length,
+                    duration: length,
+                    pitch,
+                    velocity: 80,
+                    release: None,
+                    channel: 0,
+                    track: 0,
+                }));
+        }
+        score
+    }
+
+    fn form(score: &Score) -> Form {
+        let tempo_map = TempoMap::new(&score.to_smf());
+        let rhythm = RhythmAnalyzer::analyze(score, &tempo_map);
+        FormFinder::analyze(score, &tempo_map, &rhythm)
+    }
+
+    const A: [&[u8]; 4] = [
+        &[60, 64, 67, 72],
+        &[65, 69, 72, 69],
+        &[67, 71, 74, 71],
+        &[72, 67, 64, 60],
+    ];
+    const B: [&[u8]; 4] = [
+        &[57, 59, 61, 62, 64, 66, 68, 69],
+        &[70, 58],
+        &[63, 75, 51],
+        &[56],
+    ];
+
+    #[test]
+    fn finds_a_rounded_binary_form() {
+        let form = form(&score(&[A, A, B, A]));
+
+        assert_eq!(form.phrase_bars, 4);
+        assert_eq!(form.to_string(), "A A B A");
+        let starts: Vec<u32> = form
+            .sections
+            .iter()
+            .map(|section| section.start_bar)
+            .collect();
+        assert_eq!(starts, [0, 4, 8, 12]);
+    }
+
+    #[test]
+    fn finds_nothing_in_an_empty_score() {
+        assert_eq!(form(&score(&[])), Form::default());
+    }
+
+    #[test]
+    fn names_labels_past_z_with_two_letters() {
+        let section = |label, variant| Section {
+            label,
+            variant,
+            start_bar: 0,
+            bars: 4,
+        };
+
+        assert_eq!(section(0, 0).to_string(), "A");
+        assert_eq!(section(25, 1).to_string(), "Z'");
+        assert_eq!(section(26, 0).to_string(), "AA");
+        assert_eq!(section(27, 2).to_string(), "AB''");
+        assert_eq!(section(26 * 27, 0).to_string(), "AAA");
+    }
+}
@@ -0,0 +1,442 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::key::{Key, KeyAnalysis};
+use crate::{Note, Score, TempoMap};
+use serde::{Deserialize, Serialize};
+
+/// Penalty for each chord tone missing from a segment, relative to the
+/// segment's total weight.
+const MISSING_PENALTY: f64 = 0.15;
+/// Penalty keeping seventh chords from absorbing plain triads.
+const SEVENTH_PENALTY: f64 = 0.05;
+/// Bonus when the lowest sounding note is the root.
+const ROOT_IN_BASS_BONUS: f64 = 0.1;
+/// Bonus when every chord tone belongs to the local key.
+const DIATONIC_BONUS: f64 = 0.05;
+/// Score a new chord must gain over the sounding one to replace it, which
+/// keeps passing and neighbour tones from being read as chord changes.
+const CHANGE_MARGIN: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Dominant7,
+    Major7,
+    Minor7,
+    HalfDiminished7,
+    Diminished7,
+}
+
+impl ChordQuality {
+    const ALL: [Self; 9] = [
+        Self::Major,
+        Self::Minor,
+        Self::Diminished,
+        Self::Augmented,
+        Self::Dominant7,
+        Self::Major7,
+        Self::Minor7,
+        Self::HalfDiminished7,
+        Self::Diminished7,
+    ];
+
+    /// Intervals of the chord tones above the root, in semitones.
+    pub fn intervals(self) -> &'static [u8] {
+        match self {
+            Self::Major => &[0, 4, 7],
+            Self::Minor => &[0, 3, 7],
+            Self::Diminished => &[0, 3, 6],
+            Self::Augmented => &[0, 4, 8],
+            Self::Dominant7 => &[0, 4, 7, 10],
+            Self::Major7 => &[0, 4, 7, 11],
+            Self::Minor7 => &[0, 3, 7, 10],
+            Self::HalfDiminished7 => &[0, 3, 6, 10],
+            Self::Diminished7 => &[0, 3, 6, 9],
+        }
+    }
+
+    pub fn is_seventh(self) -> bool {
+        self.intervals().len() == 4
+    }
+
+    /// Triad under a seventh chord, the quality itself for triads.
+    pub fn triad(self) -> Self {
+        match self {
+            Self::Dominant7 | Self::Major7 => Self::Major,
+            Self::Minor7 => Self::Minor,
+            Self::HalfDiminished7 | Self::Diminished7 => Self::Diminished,
+            triad => triad,
+        }
+    }
+
+    /// Whether the Roman numeral of the chord is written in lower case.
+    fn is_minor(self) -> bool {
+        matches!(
+            self,
+            Self::Minor
+                | Self::Diminished
+                | Self::Minor7
+                | Self::HalfDiminished7
+                | Self::Diminished7
+        )
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Major => "",
+            Self::Minor => "m",
+            Self::Diminished => "dim",
+            Self::Augmented => "aug",
+            Self::Dominant7 => "7",
+            Self::Major7 => "maj7",
+            Self::Minor7 => "m7",
+            Self::HalfDiminished7 => "m7b5",
+            Self::Diminished7 => "dim7",
+        }
+    }
+
+    fn numeral_suffix(self) -> &'static str {
+        match self {
+            Self::Major | Self::Minor | Self::Dominant7 | Self::Minor7 => "",
+            Self::Diminished | Self::Diminished7 => "°",
+            Self::Augmented => "+",
+            Self::Major7 => "maj",
+            Self::HalfDiminished7 => "ø",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Chord {
+    /// Pitch class of the root, `0` being C.
+    pub root: u8,
+    pub quality: ChordQuality,
+    /// Pitch class of the lowest sounding note.
+    pub bass: u8,
+}
+
+impl Chord {
+    pub fn new(root: u8, quality: ChordQuality, bass: u8) -> Self {
+        Self {
+            root: root % 12,
+            quality,
+            bass: bass % 12,
+        }
+    }
+
+    /// Pitch classes of the chord tones, starting from the root.
+    pub fn pitch_classes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.quality
+            .intervals()
+            .iter()
+            .map(|interval| (self.root + interval) % 12)
+    }
+
+    /// `0` in root position, `1` with the third in the bass, and so on. A
+    /// bass outside the chord counts as root position.
+    pub fn inversion(&self) -> usize {
+        self.pitch_classes()
+            .position(|pitch_class| pitch_class == self.bass)
+            .unwrap_or(0)
+    }
+
+    /// Chord symbol such as `Am`, `G7` or `C/E`, spelled for `key`.
+    pub fn name(&self, key: &Key) -> String {
+        let mut name = format!("{}{}", key.spell(self.root), self.quality.symbol());
+        if self.bass != self.root {
+            name.push('/');
+            name.push_str(key.spell(self.bass));
+        }
+        name
+    }
+
+    /// Roman numeral of the chord in `key`, such as `V7`, `ii6` or `bVI`,
+    /// with the figured bass of its inversion. Degrees are counted on the
+    /// major scale of the tonic in both modes, so the relative major of a
+    /// minor key is `bIII`, and chromatic roots are spelled flat except for
+    /// `#IV`.
+    pub fn numeral(&self, key: &Key) -> String {
+        const NUMERALS: [&str; 7] = ["I", "II", "III", "IV", "V", "VI", "VII"];
+        /// Accidental and degree of every interval above the tonic.
+        const DEGREES: [(&str, usize); 12] = [
+            ("", 0),
+            ("b", 1),
+            ("", 1),
+            ("b", 2),
+            ("", 2),
+            ("", 3),
+            ("#", 3),
+            ("", 4),
+            ("b", 5),
+            ("", 5),
+            ("b", 6),
+            ("", 6),
+        ];
+
+        let (accidental, degree) = DEGREES[usize::from((self.root + 12 - key.tonic) % 12)];
+        let numeral = if self.quality.is_minor() {
+            NUMERALS[degree].to_lowercase()
+        } else {
+            NUMERALS[degree].to_string()
+        };
+
+        let figures = match (self.quality.is_seventh(), self.inversion()) {
+            (false, 0) => "",
+            (false, 1) => "6",
+            (false, _) => "64",
+            (true, 0) => "7",
+            (true, 1) => "65",
+            (true, 2) => "43",
+            (true, _) => "42",
+        };
+
+        format!(
+            "{accidental}{numeral}{}{figures}",
+            self.quality.numeral_suffix()
+        )
+    }
+
+    fn all() -> impl Iterator<Item = Chord> {
+        ChordQuality::ALL
+            .into_iter()
+            .flat_map(|quality| (0..12).map(move |root| Chord::new(root, quality, root)))
+    }
+}
+
+/// A span of the score, in absolute ticks, over which one chord sounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChordSpan {
+    pub start: u64,
+    pub end: u64,
+    pub chord: Chord,
+    /// Local key the chord is heard in.
+    pub key: Key,
+}
+
+impl ChordSpan {
+    pub fn name(&self) -> String {
+        self.chord.name(&self.key)
+    }
+
+    pub fn numeral(&self) -> String {
+        self.chord.numeral(&self.key)
+    }
+}
+
+/// Chord timeline of a score.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Harmony {
+    /// Successive chords, in time order. Silent stretches extend the chord
+    /// before them.
+    pub chords: Vec<ChordSpan>,
+}
+
+/// Template-matching chord recognizer.
+///
+/// The score is cut into beats, each beat is reduced to a duration-weighted
+/// pitch-class profile, so arpeggiated figures count like block chords, and
+/// the profile is matched against every triad and seventh chord. A triad
+/// followed by its seventh within the same bar is reported as one seventh
+/// chord.
+#[derive(Debug)]
+pub struct HarmonyAnalyzer;
+
+impl HarmonyAnalyzer {
+    pub fn analyze(score: &Score, tempo_map: &TempoMap, keys: &KeyAnalysis) -> Harmony {
+        let notes: Vec<&Note> = score.pitched_notes().collect();
+        let end = notes.iter().map(|note| note.end()).max().unwrap_or(0);
+
+        let mut chords: Vec<ChordSpan> = Vec::new();
+        let mut start = 0_u64;
+        while start < end {
+            let next = tempo_map.next_beat(start).min(end);
+            let key = keys.key_at(start);
+
+            if let Some(segment) = Segment::new(&notes, start, next) {
+                let current = chords.last().map(|span| span.chord);
+                let chord = segment.best_chord(&key, current);
+
+                match chords.last_mut() {
+                    Some(last)
+                        if last.chord.root == chord.root
+                            && last.chord.quality.triad() == chord.quality.triad()
+                            && (last.chord.quality == chord.quality
+                                || !chord.quality.is_seventh()
+                                || tempo_map.position(last.start).bar
+                                    == tempo_map.position(start).bar) =>
+                    {
+                        if chord.quality.is_seventh() {
+                            last.chord.quality = chord.quality;
+                        }
+                        last.end = next;
+                    }
+                    _ => chords.push(ChordSpan {
+                        start,
+                        end: next,
+                        chord,
+                        key,
+                    }),
+                }
+            } else if let Some(last) = chords.last_mut() {
+                last.end = next;
+            }
+
+            start = next;
+        }
+
+        Harmony { chords }
+    }
+}
+
+/// Pitch-class content of one beat.
+struct Segment {
+    /// Sounding time of each pitch class, as a share of the whole segment.
+    weights: [f64; 12],
+    bass: u8,
+}
+
+impl Segment {
+    fn new(notes: &[&Note], start: u64, end: u64) -> Option<Self> {
+        let mut weights = [0.0; 12];
+        let mut bass: Option<u8> = None;
+
+        for note in notes
+            .iter()
+            .take_while(|note| note.start < end)
+            .filter(|note| note.end() > start)
+        {
+            let overlap = note.end().min(end) - note.start.max(start);
+            weights[usize::from(note.pitch % 12)] += overlap as f64;
+            bass = Some(bass.map_or(note.pitch, |bass| bass.min(note.pitch)));
+        }
+
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        weights.iter_mut().for_each(|weight| *weight /= total);
+
+        Some(Self {
+            weights,
+            bass: bass? % 12,
+        })
+    }
+
+    /// Best matching chord, keeping `current` unless another one fits
+    /// clearly better.
+    fn best_chord(&self, key: &Key, current: Option<Chord>) -> Chord {
+        let (best, best_score) = Chord::all()
+            .filter(|chord| self.weights[usize::from(chord.root)] > 0.0)
+            .map(|chord| (chord, self.score(&chord, key)))
+            .fold(
+                (
+                    Chord::new(self.bass, ChordQuality::Major, self.bass),
+                    f64::MIN,
+                ),
+                |best, candidate| {
+                    if candidate.1 > best.1 {
+                        candidate
+                    } else {
+                        best
+                    }
+                },
+            );
+
+        if let Some(current) = current
+            && self.score(&current, key) + CHANGE_MARGIN >= best_score
+        {
+            return Chord::new(current.root, current.quality, current.bass);
+        }
+
+        Chord::new(best.root, best.quality, self.bass)
+    }
+
+    fn score(&self, chord: &Chord, key: &Key) -> f64 {
+        let mut inside = 0.0;
+        let mut missing = 0;
+        for pitch_class in chord.pitch_classes() {
+            let weight = self.weights[usize::from(pitch_class)];
+            inside += weight;
+            if weight <= 0.0 {
+                missing += 1;
+            }
+        }
+
+        let mut score = inside - (1.0 - inside) - MISSING_PENALTY * f64::from(missing);
+        if chord.quality.is_seventh() {
+            score -= SEVENTH_PENALTY;
+        }
+        if chord.root == self.bass {
+            score += ROOT_IN_BASS_BONUS;
+        }
+        if chord
+            .pitch_classes()
+            .all(|pitch_class| key.contains(pitch_class))
+        {
+            score += DIATONIC_BONUS;
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Mode;
+
+    fn numeral(root: u8, quality: ChordQuality, key: Key) -> String {
+        Chord::new(root, quality, root).numeral(&key)
+    }
+
+    #[test]
+    fn spells_chromatic_roots_flat_except_the_raised_fourth() {
+        let c_major = Key::new(0, Mode::Major);
+        assert_eq!(numeral(3, ChordQuality::Major, c_major), "bIII");
+        assert_eq!(numeral(8, ChordQuality::Major, c_major), "bVI");
+        assert_eq!(numeral(10, ChordQuality::Major, c_major), "bVII");
+        assert_eq!(numeral(1, ChordQuality::Major, c_major), "bII");
+        assert_eq!(numeral(6, ChordQuality::Diminished, c_major), "#iv°");
+
+        let a_minor = Key::new(9, Mode::Minor);
+        assert_eq!(numeral(0, ChordQuality::Major, a_minor), "bIII");
+        assert_eq!(numeral(5, ChordQuality::Major, a_minor), "bVI");
+        assert_eq!(numeral(7, ChordQuality::Major, a_minor), "bVII");
+        assert_eq!(numeral(3, ChordQuality::Diminished7, a_minor), "#iv°7");
+    }
+
+    #[test]
+    fn names_diatonic_chords_with_their_inversion() {
+        let g_major = Key::new(7, Mode::Major);
+        assert_eq!(numeral(2, ChordQuality::Dominant7, g_major), "V7");
+        assert_eq!(
+            Chord::new(9, ChordQuality::Minor, 0).numeral(&g_major),
+            "ii6"
+        );
+        assert_eq!(
+            Chord::new(2, ChordQuality::Dominant7, 0).numeral(&g_major),
+            "V42"
+        );
+
+        let a_minor = Key::new(9, Mode::Minor);
+        assert_eq!(numeral(9, ChordQuality::Minor, a_minor), "i");
+        assert_eq!(numeral(11, ChordQuality::HalfDiminished7, a_minor), "iiø7");
+        assert_eq!(numeral(4, ChordQuality::Major, a_minor), "V");
+    }
+}
@@ -0,0 +1,548 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Note, SHARP_NAMES, Score, TempoMap};
+use midly::MetaMessage;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// Krumhansl-Kessler probe-tone ratings, starting from the tonic.
+const MAJOR_PROFILE: [f64; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f64; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+const FLAT_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+
+const WINDOW_BARS: u32 = 2;
+/// Most windows a score is split into. Longer scores, or scores in a very
+/// short meter, move their windows several bars at a time.
+const MAX_WINDOWS: u32 = 1024;
+/// Correlation a new key must gain over the current one to be reported as a
+/// modulation, which keeps passing chromaticism from flipping the key.
+const CHANGE_PENALTY: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    /// Pitch class of the tonic, `0` being C.
+    pub tonic: u8,
+    pub mode: Mode,
+}
+
+impl Key {
+    pub fn new(tonic: u8, mode: Mode) -> Self {
+        Self {
+            tonic: tonic % 12,
+            mode,
+        }
+    }
+
+    /// Builds the key described by a `KeySignature` meta event.
+    pub fn from_signature(accidentals: i8, minor: bool) -> Self {
+        let major_tonic = (i32::from(accidentals) * 7).rem_euclid(12) as u8;
+        if minor {
+            Self::new(major_tonic + 9, Mode::Minor)
+        } else {
+            Self::new(major_tonic, Mode::Major)
+        }
+    }
+
+    /// Sharps (positive) or flats (negative) of the key signature and whether
+    /// it is minor, as stored in a `KeySignature` meta event.
+    pub fn signature(&self) -> (i8, bool) {
+        let major_tonic = match self.mode {
+            Mode::Major => self.tonic,
+            Mode::Minor => (self.tonic + 3) % 12,
+        };
+        let accidentals = (i32::from(major_tonic) * 7).rem_euclid(12);
+        let accidentals = if accidentals > 6 {
+            accidentals - 12
+        } else {
+            accidentals
+        };
+
+        (accidentals as i8, self.mode == Mode::Minor)
+    }
+
+    /// Pitch classes of the natural scale, starting from the tonic.
+    pub fn scale(&self) -> [u8; 7] {
+        let steps = match self.mode {
+            Mode::Major => [0, 2, 4, 5, 7, 9, 11],
+            Mode::Minor => [0, 2, 3, 5, 7, 8, 10],
+        };
+        steps.map(|step| (self.tonic + step) % 12)
+    }
+
+    /// Whether `pitch_class` belongs to the key, counting the raised leading
+    /// tone of harmonic minor as diatonic.
+    pub fn contains(&self, pitch_class: u8) -> bool {
+        let pitch_class = pitch_class % 12;
+        self.scale().contains(&pitch_class)
+            || (self.mode == Mode::Minor && pitch_class == (self.tonic + 11) % 12)
+    }
+
+    /// Moves `pitch` to the closest pitch of the key, searching in the
+    /// direction given by `upwards` first.
+    pub fn snap(&self, pitch: u8, upwards: bool) -> u8 {
+        for distance in 0..12_i16 {
+            let candidates = if upwards {
+                [distance, -distance]
+            } else {
+                [-distance, distance]
+            };
+            for offset in candidates {
+                let candidate = i16::from(pitch) + offset;
+                if (0..=127).contains(&candidate) && self.contains(candidate as u8) {
+                    return candidate as u8;
+                }
+            }
+        }
+        pitch
+    }
+
+    /// Spells a pitch class with sharps or flats depending on the signature.
+    pub fn spell(&self, pitch_class: u8) -> &'static str {
+        let (accidentals, _) = self.signature();
+        if accidentals < 0 {
+            FLAT_NAMES[usize::from(pitch_class % 12)]
+        } else {
+            SHARP_NAMES[usize::from(pitch_class % 12)]
+        }
+    }
+
+    fn profile(&self) -> [f64; 12] {
+        let profile = match self.mode {
+            Mode::Major => &MAJOR_PROFILE,
+            Mode::Minor => &MINOR_PROFILE,
+        };
+        std::array::from_fn(|pc| profile[(pc + 12 - usize::from(self.tonic)) % 12])
+    }
+
+    fn all() -> impl Iterator<Item = Key> {
+        [Mode::Major, Mode::Minor]
+            .into_iter()
+            .flat_map(|mode| (0..12).map(move |tonic| Key::new(tonic, mode)))
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        };
+        write!(f, "{} {mode}", self.spell(self.tonic))
+    }
+}
+
+/// Where the keys of a [`KeyAnalysis`] come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    /// Read from the file's `KeySignature` meta events.
+    Signature,
+    /// Estimated from the pitch-class distribution of the notes.
+    Estimated,
+}
+
+/// A span of the score, in absolute ticks, that stays in one key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRegion {
+    pub start: u64,
+    pub end: u64,
+    pub key: Key,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyAnalysis {
+    /// Key covering most of the score.
+    pub global: Key,
+    /// Correlation between the global key profile and the whole score.
+    pub confidence: f64,
+    pub source: KeySource,
+    /// Successive local keys, tracking modulations.
+    pub regions: Vec<KeyRegion>,
+}
+
+impl KeyAnalysis {
+    /// Local key in effect at `tick`, the last one past the end of the score.
+    pub fn key_at(&self, tick: u64) -> Key {
+        self.regions
+            .iter()
+            .take_while(|region| region.start <= tick)
+            .last()
+            .map_or(self.global, |region| region.key)
+    }
+}
+
+/// Krumhansl-Schmuckler key finder working on duration-weighted pitch-class
+/// profiles.
+#[derive(Debug)]
+pub struct KeyFinder;
+
+impl KeyFinder {
+    pub fn analyze(score: &Score, tempo_map: &TempoMap) -> KeyAnalysis {
+        let notes: Vec<&Note> = score.pitched_notes().collect();
+        let end = score.end();
+        let histogram = pitch_class_histogram(&notes, 0, end);
+
+        let signatures = key_signatures(score);
+        if !signatures.is_empty() {
+            return Self::from_signatures(&signatures, &histogram, end);
+        }
+
+        let (global, confidence) = Self::best_key(&histogram);
+        let regions = Self::track_keys(&notes, end, tempo_map);
+
+        KeyAnalysis {
+            global,
+            confidence,
+            source: KeySource::Estimated,
+            regions,
+        }
+    }
+
+    /// Key whose profile correlates best with `histogram`, with that
+    /// correlation. An empty histogram yields C major with no confidence.
+    pub fn best_key(histogram: &[f64; 12]) -> (Key, f64) {
+        Key::all()
+            .map(|key| (key, correlation(&key.profile(), histogram)))
+            .fold((Key::new(0, Mode::Major), 0.0), |best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            })
+    }
+
+    fn from_signatures(signatures: &[(u64, Key)], histogram: &[f64; 12], end: u64) -> KeyAnalysis {
+        let mut regions: Vec<KeyRegion> = Vec::new();
+        for (index, &(start, key)) in signatures.iter().enumerate() {
+            let start = if index == 0 { 0 } else { start };
+            let next = signatures.get(index + 1).map_or(end, |(tick, _)| *tick);
+            match regions.last_mut() {
+                Some(last) if last.key == key => last.end = next.max(start),
+                _ => regions.push(KeyRegion {
+                    start,
+                    end: next.max(start),
+                    key,
+                }),
+            }
+        }
+
+        let mut coverage: HashMap<Key, u64> = HashMap::new();
+        for region in &regions {
+            *coverage.entry(region.key).or_default() += region.end - region.start;
+        }
+        let global = regions
+            .iter()
+            .map(|region| region.key)
+            .max_by_key(|key| coverage[key])
+            .unwrap_or(regions[0].key);
+
+        KeyAnalysis {
+            global,
+            confidence: correlation(&global.profile(), histogram),
+            source: KeySource::Signature,
+            regions,
+        }
+    }
+
+    /// Finds the best key sequence over windows of a few bars, moving one
+    /// bar at a time, or a few for the longest scores, and penalizing every
+    /// key change.
+    fn track_keys(notes: &[&Note], end: u64, tempo_map: &TempoMap) -> Vec<KeyRegion> {
+        let keys: Vec<Key> = Key::all().collect();
+
+        let bars = tempo_map.position(end.saturating_sub(1)).bar + 1;
+        let step = bars.div_ceil(MAX_WINDOWS);
+        let windows = bars.div_ceil(step);
+        let bar_starts: Vec<u64> = (0..=windows)
+            .map(|window| tempo_map.bar_start(window * step))
+            .collect();
+
+        let scores: Vec<Vec<f64>> = (0..windows as usize)
+            .map(|window| {
+                let start = bar_starts[window];
+                let window_end = tempo_map.bar_start((window as u32 + WINDOW_BARS) * step);
+                let histogram = pitch_class_histogram(notes, start, window_end);
+                keys.iter()
+                    .map(|key| correlation(&key.profile(), &histogram))
+                    .collect()
+            })
+            .collect();
+
+        let mut totals = scores[0].clone();
+        let mut backtrack = vec![vec![0_usize; keys.len()]; scores.len()];
+
+        for (index, window_scores) in scores.iter().enumerate().skip(1) {
+            let (best_previous, best_total) = argmax(&totals);
+            totals = window_scores
+                .iter()
+                .enumerate()
+                .map(|(key, score)| {
+                    let stay = totals[key];
+                    let change = best_total - CHANGE_PENALTY;
+                    if stay >= change {
+                        backtrack[index][key] = key;
+                        stay + score
+                    } else {
+                        backtrack[index][key] = best_previous;
+                        change + score
+                    }
+                })
+                .collect();
+        }
+
+        let mut path = vec![argmax(&totals).0; scores.len()];
+        for index in (1..scores.len()).rev() {
+            path[index - 1] = backtrack[index][path[index]];
+        }
+
+        let mut regions: Vec<KeyRegion> = Vec::new();
+        for (window, &key) in path.iter().enumerate() {
+            let start = bar_starts[window];
+            let region_end = bar_starts[window + 1].min(end);
+            match regions.last_mut() {
+                Some(last) if last.key == keys[key] => last.end = region_end,
+                _ => regions.push(KeyRegion {
+                    start,
+                    end: region_end,
+                    key: keys[key],
+                }),
+            }
+        }
+
+        regions
+    }
+}
+
+fn key_signatures(score: &Score) -> Vec<(u64, Key)> {
+    let mut signatures: Vec<_> = score
+        .meta_events()
+        .filter_map(|(tick, message)| match message {
+            MetaMessage::KeySignature(accidentals, minor) => {
+                Some((tick, Key::from_signature(accidentals, minor)))
+            }
+            _ => None,
+        })
+        .collect();
+
+    signatures.dedup_by_key(|(tick, _)| *tick);
+    signatures
+}
+
+fn pitch_class_histogram(notes: &[&Note], start: u64, end: u64) -> [f64; 12] {
+    let mut histogram = [0.0; 12];
+
+    for note in notes {
+        let overlap = note.end().min(end).saturating_sub(note.start.max(start));
+        histogram[usize::from(note.pitch % 12)] += overlap as f64;
+    }
+
+    histogram
+}
+
+fn correlation(a: &[f64; 12], b: &[f64; 12]) -> f64 {
+    let mean_a = a.iter().sum::<f64>() / 12.0;
+    let mean_b = b.iter().sum::<f64>() / 12.0;
+
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a).powi(2);
+        variance_b += (y - mean_b).powi(2);
+    }
+
+    if variance_a == 0.0 || variance_b == 0.0 {
+        0.0
+    } else {
+        covariance / (variance_a * variance_b).sqrt()
+    }
+}
+
+fn argmax(values: &[f64]) -> (usize, f64) {
+    values
+        .iter()
+        .copied()
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |best, (index, value)| {
+            if value > best.1 { (index, value) } else { best }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScoreEvent;
+    use midly::{Format, Header, Timing, TrackEventKind, num::u15};
+
+    const QUARTER: u64 = 480;
+
+    /// Bars of four quarter notes on track 0, channel 0.
+    fn score(bars: &[[u8; 4]]) -> Score {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(QUARTER as u16)));
+        let mut score = Score::new(header, 1);
+        score.notes = bars
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, &pitch)| Note {
+                start: index as u64 * QUARTER,
+                duration: QUARTER,
+                pitch,
+                velocity: 80,
+                release: None,
+                channel: 0,
+                track: 0,
+            })
+            .collect();
+        score
+    }
+
+    fn analyze(score: &Score) -> KeyAnalysis {
+        KeyFinder::analyze(score, &TempoMap::new(&score.to_smf()))
+    }
+
+    const C_MAJOR: [[u8; 4]; 4] = [
+        [60, 64, 67, 72],
+        [65, 69, 72, 67],
+        [67, 71, 74, 67],
+        [60, 64, 67, 60],
+    ];
+
+    fn transposed(bars: &[[u8; 4]], semitones: u8) -> Vec<[u8; 4]> {
+        bars.iter()
+            .map(|bar| bar.map(|pitch| pitch + semitones))
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_every_key_signature() {
+        for key in Key::all() {
+            let (accidentals, minor) = key.signature();
+            assert!((-6..=6).contains(&accidentals), "{key}");
+            assert_eq!(Key::from_signature(accidentals, minor), key);
+        }
+        assert_eq!(Key::new(2, Mode::Major).signature(), (2, false));
+        assert_eq!(Key::new(0, Mode::Minor).signature(), (-3, true));
+    }
+
+    #[test]
+    fn spells_with_the_signature_accidentals() {
+        assert_eq!(Key::new(5, Mode::Major).to_string(), "F major");
+        assert_eq!(Key::new(5, Mode::Major).spell(10), "Bb");
+        assert_eq!(Key::new(4, Mode::Major).spell(8), "G#");
+        assert_eq!(Key::new(3, Mode::Minor).to_string(), "D# minor");
+    }
+
+    #[test]
+    fn counts_the_raised_leading_tone_in_minor() {
+        let a_minor = Key::new(9, Mode::Minor);
+        assert!(a_minor.contains(68));
+        assert!(!a_minor.contains(66));
+        assert_eq!(a_minor.snap(66, true), 67);
+        assert_eq!(a_minor.snap(66, false), 65);
+    }
+
+    #[test]
+    fn estimates_the_key_of_a_major_piece() {
+        let analysis = analyze(&score(&C_MAJOR));
+
+        assert_eq!(analysis.source, KeySource::Estimated);
+        assert_eq!(analysis.global, Key::new(0, Mode::Major));
+        assert!(analysis.confidence > 0.5);
+    }
+
+    #[test]
+    fn estimates_the_key_of_a_minor_piece() {
+        let a_minor = [
+            [57, 60, 64, 69],
+            [62, 65, 69, 65],
+            [64, 68, 71, 68],
+            [57, 60, 64, 57],
+        ];
+        let analysis = analyze(&score(&a_minor));
+
+        assert_eq!(analysis.global, Key::new(9, Mode::Minor));
+    }
+
+    #[test]
+    fn follows_a_modulation() {
+        let mut bars = [C_MAJOR, C_MAJOR].concat();
+        bars.extend(transposed(&[C_MAJOR, C_MAJOR].concat(), 4));
+        let analysis = analyze(&score(&bars));
+
+        let keys: Vec<Key> = analysis.regions.iter().map(|region| region.key).collect();
+        assert_eq!(keys.first(), Some(&Key::new(0, Mode::Major)));
+        assert_eq!(keys.last(), Some(&Key::new(4, Mode::Major)));
+        assert_eq!(analysis.key_at(0), Key::new(0, Mode::Major));
+        assert_eq!(analysis.key_at(15 * 4 * QUARTER), Key::new(4, Mode::Major));
+        assert_eq!(analysis.regions.last().unwrap().end, 16 * 4 * QUARTER);
+    }
+
+    #[test]
+    fn prefers_key_signatures_over_the_notes() {
+        let mut score = score(&C_MAJOR);
+        score.events.push(ScoreEvent::new(
+            0,
+            0,
+            TrackEventKind::Meta(MetaMessage::KeySignature(-3, true)),
+        ));
+        let analysis = analyze(&score);
+
+        assert_eq!(analysis.source, KeySource::Signature);
+        assert_eq!(analysis.global, Key::new(0, Mode::Minor));
+        assert_eq!(
+            analysis.regions,
+            [KeyRegion {
+                start: 0,
+                end: 16 * QUARTER,
+                key: Key::new(0, Mode::Minor),
+            }]
+        );
+    }
+
+    #[test]
+    fn bounds_the_windows_of_a_very_short_meter() {
+        let mut score = score(&C_MAJOR);
+        score.events.push(ScoreEvent::new(
+            0,
+            0,
+            TrackEventKind::Meta(MetaMessage::TimeSignature(1, 15, 24, 8)),
+        ));
+        let analysis = analyze(&score);
+
+        assert_eq!(analysis.global, Key::new(0, Mode::Major));
+        assert_eq!(analysis.regions.first().map(|region| region.start), Some(0));
+        assert_eq!(
+            analysis.regions.last().map(|region| region.end),
+            Some(16 * QUARTER)
+        );
+    }
+}
@@ -0,0 +1,35 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod analyzer;
+mod corpus;
+mod form;
+mod harmony;
+mod key;
+mod motif;
+mod report;
+mod rhythm;
+mod voices;
+
+pub use analyzer::Analysis;
+pub use corpus::Corpus;
+pub use form::{Form, FormFinder, Section};
+pub use harmony::{Chord, ChordQuality, ChordSpan, Harmony, HarmonyAnalyzer};
+pub use key::{Key, KeyAnalysis, KeyFinder, KeySource, Mode};
+pub use motif::{Motif, MotifFinder, Variation};
+pub use report::{Report, ReportFormat};
+pub use rhythm::{Hit, Rhythm, RhythmAnalyzer, RhythmPattern, STEPS_PER_QUARTER};
+pub use voices::VoiceSeparator;
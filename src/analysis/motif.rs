@@ -0,0 +1,411 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{KeyAnalysis, STEPS_PER_QUARTER};
+use crate::{Key, Note, Score, TempoMap};
+use std::collections::BTreeMap;
+
+/// Shortest and longest motifs searched for, in notes.
+const MIN_LENGTH: usize = 4;
+const MAX_LENGTH: usize = 8;
+/// Motifs kept per score, the most salient first.
+const MAX_MOTIFS: usize = 8;
+/// Share of a candidate's notes that may already belong to a better motif.
+const MAX_OVERLAP: f64 = 0.5;
+
+/// How an occurrence relates to the first statement of its motif.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variation {
+    /// Same pitches and rhythm.
+    Exact,
+    /// Same intervals and rhythm from another pitch.
+    Transposed,
+    /// Same scale steps and rhythm from another degree of the key, the
+    /// intervals following the scale.
+    Sequence,
+    /// Same intervals on another rhythm.
+    Rhythmic,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    pub start: u64,
+    pub pitch: u8,
+    pub variation: Variation,
+}
+
+/// A melodic cell that recurs in one part.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Motif {
+    pub track: usize,
+    pub channel: u8,
+    /// Intervals between successive notes, in semitones.
+    pub intervals: Vec<i16>,
+    /// Length of each note of the first statement, in grid steps, measured
+    /// to the next onset and to the note's own end for the last one.
+    pub rhythm: Vec<u32>,
+    /// Statements of the motif in time order, the first being the model
+    /// the others are compared with.
+    pub occurrences: Vec<Occurrence>,
+}
+
+impl Motif {
+    pub fn len(&self) -> usize {
+        self.rhythm.len()
+    }
+
+    pub fn count(&self, variation: Variation) -> usize {
+        self.occurrences
+            .iter()
+            .filter(|occurrence| occurrence.variation == variation)
+            .count()
+    }
+}
+
+/// What the windows of a melody are grouped by. Interval groups come first,
+/// so a motif found both ways is reported with its exact intervals.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Shape {
+    /// Intervals in semitones.
+    Intervals(Vec<i16>),
+    /// Intervals in steps of the scale of the local key, for windows whose
+    /// notes all belong to it.
+    Steps(Vec<i16>),
+}
+
+/// One window of a melody.
+struct Window {
+    index: usize,
+    intervals: Vec<i16>,
+    rhythm: Vec<u32>,
+}
+
+/// Finds recurring motifs by grouping every window of every melodic line
+/// by its interval sequence, which matches transposed statements, and by
+/// its scale steps in the local key, which matches diatonic sequences, then
+/// telling exact, transposed, sequential and rhythmically varied statements
+/// apart.
+#[derive(Debug)]
+pub struct MotifFinder;
+
+impl MotifFinder {
+    pub fn analyze(score: &Score, tempo_map: &TempoMap, key: &KeyAnalysis) -> Vec<Motif> {
+        let grid = (tempo_map.ticks_per_quarter(0) / STEPS_PER_QUARTER).max(1.0);
+
+        let mut candidates: Vec<(usize, Motif, Vec<usize>)> = Vec::new();
+        for ((track, channel), melody) in score.melodies(grid) {
+            if melody.first().is_none_or(|note| note.is_percussion()) {
+                continue;
+            }
+            let rhythm: Vec<u32> = melody
+                .iter()
+                .enumerate()
+                .map(|(index, note)| {
+                    let end = melody.get(index + 1).map_or(note.end(), |next| next.start);
+                    ((end - note.start) as f64 / grid).round().max(1.0) as u32
+                })
+                .collect();
+
+            let steps: Vec<Option<i16>> = melody
+                .iter()
+                .map(|note| scale_step(&key.key_at(note.start), note.pitch))
+                .collect();
+
+            for length in MIN_LENGTH..=MAX_LENGTH.min(melody.len()) {
+                let mut groups: BTreeMap<Shape, Vec<Window>> = BTreeMap::new();
+                for index in 0..=melody.len() - length {
+                    let notes = &melody[index..index + length];
+                    let intervals: Vec<i16> = notes
+                        .windows(2)
+                        .map(|pair| i16::from(pair[1].pitch) - i16::from(pair[0].pitch))
+                        .collect();
+                    if intervals.iter().all(|&interval| interval == 0) {
+                        continue;
+                    }
+
+                    let mut rhythm = rhythm[index..index + length].to_vec();
+                    let last = notes[length - 1];
+                    rhythm[length - 1] = ((last.duration as f64 / grid).round() as u32).max(1);
+
+                    let window_steps: Option<Vec<i16>> =
+                        steps[index..index + length].iter().copied().collect();
+                    if let Some(window_steps) = window_steps {
+                        let steps = window_steps.windows(2).map(|pair| pair[1] - pair[0]);
+                        groups
+                            .entry(Shape::Steps(steps.collect()))
+                            .or_default()
+                            .push(Window {
+                                index,
+                                intervals: intervals.clone(),
+                                rhythm: rhythm.clone(),
+                            });
+                    }
+                    groups
+                        .entry(Shape::Intervals(intervals.clone()))
+                        .or_default()
+                        .push(Window {
+                            index,
+                            intervals,
+                            rhythm,
+                        });
+                }
+
+                for windows in groups.into_values() {
+                    let windows = Self::non_overlapping(windows, length);
+                    if windows.len() < 2 {
+                        continue;
+                    }
+                    let indices: Vec<usize> = windows.iter().map(|window| window.index).collect();
+                    let motif = Self::motif(track, channel, &windows, &melody);
+                    candidates.push((length * windows.len(), motif, indices));
+                }
+            }
+        }
+
+        Self::select(candidates)
+    }
+
+    fn non_overlapping(windows: Vec<Window>, length: usize) -> Vec<Window> {
+        let mut kept: Vec<Window> = Vec::new();
+        for window in windows {
+            if kept
+                .last()
+                .is_none_or(|last| window.index >= last.index + length)
+            {
+                kept.push(window);
+            }
+        }
+        kept
+    }
+
+    fn motif(track: usize, channel: u8, windows: &[Window], melody: &[&Note]) -> Motif {
+        let model = &windows[0];
+        let first_pitch = melody[model.index].pitch;
+        let occurrences = windows
+            .iter()
+            .map(|window| {
+                let note = melody[window.index];
+                let variation = if window.rhythm != model.rhythm {
+                    Variation::Rhythmic
+                } else if window.intervals != model.intervals {
+                    Variation::Sequence
+                } else if note.pitch != first_pitch {
+                    Variation::Transposed
+                } else {
+                    Variation::Exact
+                };
+                Occurrence {
+                    start: note.start,
+                    pitch: note.pitch,
+                    variation,
+                }
+            })
+            .collect();
+
+        Motif {
+            track,
+            channel,
+            intervals: model.intervals.clone(),
+            rhythm: model.rhythm.clone(),
+            occurrences,
+        }
+    }
+
+    /// Keeps the motifs covering the most notes, dropping those mostly made
+    /// of notes already claimed by a better one.
+    fn select(mut candidates: Vec<(usize, Motif, Vec<usize>)>) -> Vec<Motif> {
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.len().cmp(&a.1.len())));
+
+        let mut claimed: BTreeMap<(usize, u8), Vec<bool>> = BTreeMap::new();
+        let mut motifs = Vec::new();
+        for (_, motif, indices) in candidates {
+            if motifs.len() == MAX_MOTIFS {
+                break;
+            }
+
+            let length = motif.len();
+            let notes = claimed.entry((motif.track, motif.channel)).or_default();
+            let covered: Vec<usize> = indices
+                .iter()
+                .flat_map(|&index| index..index + length)
+                .collect();
+            if let Some(&last) = covered.iter().max()
+                && notes.len() <= last
+            {
+                notes.resize(last + 1, false);
+            }
+
+            let overlap = covered.iter().filter(|&&index| notes[index]).count();
+            if overlap as f64 > MAX_OVERLAP * covered.len() as f64 {
+                continue;
+            }
+
+            covered.iter().for_each(|&index| notes[index] = true);
+            motifs.push(motif);
+        }
+
+        motifs
+    }
+}
+
+/// Scale step of `pitch` in `key`, so the difference between two steps is
+/// the interval between the pitches counted along the scale, or `None`
+/// when the key does not contain it. The raised leading tone of minor counts
+/// as the seventh degree.
+fn scale_step(key: &Key, pitch: u8) -> Option<i16> {
+    if !key.contains(pitch) {
+        return None;
+    }
+
+    let relative = i16::from(pitch) + 12 - i16::from(key.tonic);
+    let offset = (relative % 12) as u8;
+    let degree = key
+        .scale()
+        .map(|pitch_class| (pitch_class + 12 - key.tonic) % 12)
+        .iter()
+        .rposition(|&step| step <= offset)?;
+    Some(relative / 12 * 7 + degree as i16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KeyFinder, Mode, ScoreEvent};
+    use midly::{Format, Header, MetaMessage, Timing, TrackEventKind, num::u15};
+
+    const QUARTER: u64 = 480;
+
+    /// Motifs of a melody given as pitches and lengths in quarter notes,
+    /// in C major.
+    fn motifs(melody: &[(u8, f64)]) -> Vec<Motif> {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(QUARTER as u16)));
+        let mut score = Score::new(header, 1);
+        let mut start = 0;
+        for &(pitch, quarters) in melody {
+            let duration = (quarters * QUARTER as f64) as u64;
+            score.notes.push(Note {
+                start,
+                duration,
+                pitch,
+                velocity: 80,
+                release: None,
+                channel: 0,
+                track: 0,
+            });
+            start += duration;
+        }
+        score.events.push(ScoreEvent::new(
+            0,
+            0,
+            TrackEventKind::Meta(MetaMessage::KeySignature(0, false)),
+        ));
+
+        let tempo_map = TempoMap::new(&score.to_smf());
+        let key = KeyFinder::analyze(&score, &tempo_map);
+        MotifFinder::analyze(&score, &tempo_map, &key)
+    }
+
+    fn quarters(pitches: &[u8]) -> Vec<(u8, f64)> {
+        pitches.iter().map(|&pitch| (pitch, 1.0)).collect()
+    }
+
+    fn variations(motif: &Motif) -> Vec<(u64, u8, Variation)> {
+        motif
+            .occurrences
+            .iter()
+            .map(|occurrence| (occurrence.start, occurrence.pitch, occurrence.variation))
+            .collect()
+    }
+
+    #[test]
+    fn finds_exact_restatements() {
+        let motifs = motifs(&quarters(&[60, 62, 64, 60, 60, 62, 64, 60]));
+
+        assert_eq!(motifs[0].intervals, [2, 2, -4]);
+        assert_eq!(motifs[0].rhythm, [4, 4, 4, 4]);
+        assert_eq!(
+            variations(&motifs[0]),
+            [
+                (0, 60, Variation::Exact),
+                (4 * QUARTER, 60, Variation::Exact)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_transposed_restatements() {
+        let motifs = motifs(&quarters(&[60, 62, 64, 60, 65, 67, 69, 65]));
+
+        assert_eq!(motifs[0].intervals, [2, 2, -4]);
+        assert_eq!(
+            variations(&motifs[0]),
+            [
+                (0, 60, Variation::Exact),
+                (4 * QUARTER, 65, Variation::Transposed)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_diatonic_sequences() {
+        let motifs = motifs(&quarters(&[60, 62, 64, 65, 62, 64, 65, 67]));
+
+        assert_eq!(motifs[0].intervals, [2, 2, 1]);
+        assert_eq!(
+            variations(&motifs[0]),
+            [
+                (0, 60, Variation::Exact),
+                (4 * QUARTER, 62, Variation::Sequence)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_restatements_on_another_rhythm() {
+        let mut melody = quarters(&[60, 62, 64, 60]);
+        melody.extend([(60, 0.5), (62, 0.5), (64, 1.0), (60, 2.0)]);
+        let motifs = motifs(&melody);
+
+        assert_eq!(motifs[0].intervals, [2, 2, -4]);
+        assert_eq!(
+            variations(&motifs[0]),
+            [
+                (0, 60, Variation::Exact),
+                (4 * QUARTER, 60, Variation::Rhythmic)
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_sequences_leaving_the_key() {
+        assert!(motifs(&quarters(&[60, 62, 64, 65, 61, 63, 66, 68])).is_empty());
+    }
+
+    #[test]
+    fn counts_scale_steps_across_octaves() {
+        let c_major = Key::new(0, Mode::Major);
+        let a_minor = Key::new(9, Mode::Minor);
+        let steps =
+            |key: &Key, from: u8, to: u8| Some(scale_step(key, to)? - scale_step(key, from)?);
+
+        assert_eq!(steps(&c_major, 60, 72), Some(7));
+        assert_eq!(steps(&c_major, 71, 72), Some(1));
+        assert_eq!(steps(&c_major, 60, 57), Some(-2));
+        assert_eq!(steps(&c_major, 60, 61), None);
+        assert_eq!(steps(&a_minor, 68, 69), Some(1));
+        assert_eq!(steps(&a_minor, 65, 68), Some(1));
+    }
+}
@@ -0,0 +1,670 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{Analysis, KeySource, Variation};
+use crate::{Error, Note, Result, instrument_name, pitch_name};
+use clap::ValueEnum;
+use midly::{MetaMessage, MidiMessage, Timing, TrackEventKind};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// Bars printed on each line of the density table.
+const DENSITY_BARS_PER_LINE: usize = 8;
+
+/// How `compose analyze` prints its report.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Human-readable tables
+    #[default]
+    Text,
+    /// A single JSON object
+    Json,
+}
+
+/// Lowest and highest pitched notes of a set.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct PitchRange {
+    pub lowest: u8,
+    pub highest: u8,
+}
+
+impl PitchRange {
+    fn of<'a>(notes: impl Iterator<Item = &'a Note>) -> Option<Self> {
+        notes
+            .filter(|note| !note.is_percussion())
+            .fold(None, |range: Option<Self>, note| {
+                Some(match range {
+                    Some(range) => Self {
+                        lowest: range.lowest.min(note.pitch),
+                        highest: range.highest.max(note.pitch),
+                    },
+                    None => Self {
+                        lowest: note.pitch,
+                        highest: note.pitch,
+                    },
+                })
+            })
+    }
+}
+
+impl Display for PitchRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            pitch_name(self.lowest),
+            pitch_name(self.highest)
+        )
+    }
+}
+
+/// One channel used by a track.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChannelReport {
+    /// Channel number as shown by sequencers, from 1 to 16.
+    pub channel: u8,
+    /// General MIDI instruments selected on the channel, in order.
+    pub instruments: Vec<&'static str>,
+    pub notes: usize,
+    pub pitch_range: Option<PitchRange>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TrackReport {
+    pub index: usize,
+    pub name: Option<String>,
+    pub notes: usize,
+    pub channels: Vec<ChannelReport>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TempoChange {
+    pub tick: u64,
+    pub position: String,
+    pub bpm: f64,
+    /// Whether the file leaves this tempo unstated.
+    pub default: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TimeSignatureChange {
+    pub tick: u64,
+    /// Bar number, counted from 1.
+    pub bar: u32,
+    pub signature: String,
+    /// Whether the file leaves this meter unstated.
+    pub default: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct KeyChange {
+    pub tick: u64,
+    pub position: String,
+    pub key: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChordChange {
+    pub tick: u64,
+    pub position: String,
+    pub chord: String,
+    pub numeral: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MotifReport {
+    pub track: usize,
+    /// Channel number, from 1 to 16.
+    pub channel: u8,
+    pub intervals: Vec<i16>,
+    /// Length of each note, in sixteenth notes.
+    pub rhythm: Vec<u32>,
+    pub first: String,
+    pub exact: usize,
+    pub transposed: usize,
+    pub sequences: usize,
+    pub rhythmic: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SectionReport {
+    pub label: String,
+    /// First bar, counted from 1.
+    pub bar: u32,
+    pub bars: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FormReport {
+    pub phrase_bars: u32,
+    pub sections: Vec<SectionReport>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct KeyReport {
+    pub global: String,
+    pub source: &'static str,
+    pub confidence: f64,
+    pub regions: Vec<KeyChange>,
+}
+
+/// Summary of an analyzed MIDI file, printed by `compose analyze`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Report {
+    pub format: u8,
+    pub timing: String,
+    pub duration: f64,
+    pub bars: u32,
+    pub notes: usize,
+    pub pitch_range: Option<PitchRange>,
+    pub key: KeyReport,
+    pub form: FormReport,
+    pub chords: Vec<ChordChange>,
+    /// Recurring motifs, the most salient first.
+    pub motifs: Vec<MotifReport>,
+    pub tracks: Vec<TrackReport>,
+    pub tempo_changes: Vec<TempoChange>,
+    pub time_signatures: Vec<TimeSignatureChange>,
+    /// Notes starting in each bar.
+    pub density: Vec<usize>,
+}
+
+impl Report {
+    pub fn new(analysis: &Analysis) -> Self {
+        let score = &analysis.score;
+        let tempo_map = &analysis.tempo_map;
+
+        let timing = match score.header.timing {
+            Timing::Metrical(ticks) => format!("{} ticks per quarter", ticks.as_int()),
+            Timing::Timecode(fps, ticks) => {
+                format!("{} fps, {ticks} ticks per frame", fps.as_f32())
+            }
+        };
+
+        let bars = tempo_map.bar_count();
+        let mut density = vec![0; bars as usize];
+        for note in &score.notes {
+            let bar = tempo_map.position(note.start).bar as usize;
+            if let Some(count) = density.get_mut(bar) {
+                *count += 1;
+            }
+        }
+
+        let key = &analysis.key;
+        let key = KeyReport {
+            global: key.global.to_string(),
+            source: match key.source {
+                KeySource::Signature => "signature",
+                KeySource::Estimated => "estimated",
+            },
+            confidence: key.confidence,
+            regions: key
+                .regions
+                .iter()
+                .map(|region| KeyChange {
+                    tick: region.start,
+                    position: tempo_map.position(region.start).to_string(),
+                    key: region.key.to_string(),
+                })
+                .collect(),
+        };
+
+        Self {
+            format: score.header.format as u8,
+            timing,
+            duration: tempo_map.duration(),
+            bars,
+            notes: score.notes.len(),
+            pitch_range: PitchRange::of(score.notes.iter()),
+            key,
+            form: FormReport {
+                phrase_bars: analysis.form.phrase_bars,
+                sections: analysis
+                    .form
+                    .sections
+                    .iter()
+                    .map(|section| SectionReport {
+                        label: section.to_string(),
+                        bar: section.start_bar + 1,
+                        bars: section.bars,
+                    })
+                    .collect(),
+            },
+            chords: analysis
+                .harmony
+                .chords
+                .iter()
+                .map(|span| ChordChange {
+                    tick: span.start,
+                    position: tempo_map.position(span.start).to_string(),
+                    chord: span.name(),
+                    numeral: span.numeral(),
+                })
+                .collect(),
+            motifs: analysis
+                .motifs
+                .iter()
+                .map(|motif| MotifReport {
+                    track: motif.track,
+                    channel: motif.channel + 1,
+                    intervals: motif.intervals.clone(),
+                    rhythm: motif.rhythm.clone(),
+                    first: tempo_map.position(motif.occurrences[0].start).to_string(),
+                    exact: motif.count(Variation::Exact),
+                    transposed: motif.count(Variation::Transposed),
+                    sequences: motif.count(Variation::Sequence),
+                    rhythmic: motif.count(Variation::Rhythmic),
+                })
+                .collect(),
+            tracks: (0..score.track_count)
+                .map(|track| Self::track(analysis, track))
+                .collect(),
+            tempo_changes: tempo_map
+                .tempo_changes()
+                .enumerate()
+                .map(|(index, (tick, bpm))| TempoChange {
+                    tick,
+                    position: tempo_map.position(tick).to_string(),
+                    bpm,
+                    default: index == 0 && !tempo_map.states_tempo(),
+                })
+                .collect(),
+            time_signatures: tempo_map
+                .time_signature_changes()
+                .enumerate()
+                .map(|(index, (tick, bar, signature))| TimeSignatureChange {
+                    tick,
+                    bar: bar + 1,
+                    signature: signature.to_string(),
+                    default: index == 0 && !tempo_map.states_meter(),
+                })
+                .collect(),
+            density,
+        }
+    }
+
+    pub fn render(&self, format: ReportFormat) -> Result<String> {
+        match format {
+            ReportFormat::Text => Ok(self.to_string()),
+            ReportFormat::Json => serde_json::to_string_pretty(self)
+                .map(|json| json + "\n")
+                .map_err(Error::SerializeReport),
+        }
+    }
+
+    /// Channels are listed when they play notes or select an instrument;
+    /// channels playing without a program change use the General MIDI
+    /// default, program 0.
+    fn track(analysis: &Analysis, track: usize) -> TrackReport {
+        let mut name = None;
+        let mut programs: BTreeMap<u8, Vec<&'static str>> = BTreeMap::new();
+        for event in analysis
+            .score
+            .events
+            .iter()
+            .filter(|event| event.track == track)
+        {
+            match event.kind() {
+                TrackEventKind::Meta(MetaMessage::TrackName(bytes)) if name.is_none() => {
+                    name = Some(String::from_utf8_lossy(bytes).trim().to_string());
+                }
+                TrackEventKind::Midi {
+                    channel,
+                    message: MidiMessage::ProgramChange { program },
+                } => {
+                    let channel = channel.as_int();
+                    let instrument = instrument_name(program.as_int(), channel);
+                    let instruments = programs.entry(channel).or_default();
+                    if instruments.last() != Some(&instrument) {
+                        instruments.push(instrument);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let notes: Vec<&Note> = analysis
+            .score
+            .notes
+            .iter()
+            .filter(|note| note.track == track)
+            .collect();
+        for note in &notes {
+            programs.entry(note.channel).or_default();
+        }
+
+        let channels = programs
+            .into_iter()
+            .map(|(channel, mut instruments)| {
+                if instruments.is_empty() {
+                    instruments.push(instrument_name(0, channel));
+                }
+                let played = || {
+                    notes
+                        .iter()
+                        .copied()
+                        .filter(move |note| note.channel == channel)
+                };
+                ChannelReport {
+                    channel: channel + 1,
+                    instruments,
+                    notes: played().count(),
+                    pitch_range: PitchRange::of(played()),
+                }
+            })
+            .collect();
+
+        TrackReport {
+            index: track,
+            name: name.filter(|name| !name.is_empty()),
+            notes: notes.len(),
+            channels,
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let seconds = self.duration.round() as u64;
+        writeln!(
+            f,
+            "Format:     {} ({} tracks, {})",
+            self.format,
+            self.tracks.len(),
+            self.timing
+        )?;
+        writeln!(
+            f,
+            "Duration:   {}:{:02} ({} bars)",
+            seconds / 60,
+            seconds % 60,
+            self.bars
+        )?;
+        match self.pitch_range {
+            Some(range) => writeln!(f, "Notes:      {} (range {range})", self.notes)?,
+            None => writeln!(f, "Notes:      {}", self.notes)?,
+        }
+        writeln!(
+            f,
+            "Key:        {} ({}, confidence {:.2})",
+            self.key.global, self.key.source, self.key.confidence
+        )?;
+
+        let labels: Vec<&str> = self
+            .form
+            .sections
+            .iter()
+            .map(|section| section.label.as_str())
+            .collect();
+        writeln!(
+            f,
+            "Form:       {} ({}-bar phrases)",
+            labels.join(" "),
+            self.form.phrase_bars
+        )?;
+
+        writeln!(f, "\nTracks:")?;
+        for track in &self.tracks {
+            let name = track.name.as_deref().unwrap_or("-");
+            writeln!(f, "  {:>2}  {name}  ({} notes)", track.index, track.notes)?;
+            for channel in &track.channels {
+                let range = channel
+                    .pitch_range
+                    .map_or_else(String::new, |range| format!(", {range}"));
+                writeln!(
+                    f,
+                    "        ch {:>2}  {}  ({} notes{range})",
+                    channel.channel,
+                    channel.instruments.join(", "),
+                    channel.notes
+                )?;
+            }
+        }
+
+        writeln!(f, "\nTempo:")?;
+        for change in &self.tempo_changes {
+            let default = if change.default { " (default)" } else { "" };
+            writeln!(
+                f,
+                "  {:>10}  {:.2} bpm{default}",
+                change.position, change.bpm
+            )?;
+        }
+
+        writeln!(f, "\nTime signatures:")?;
+        for change in &self.time_signatures {
+            let default = if change.default { " (default)" } else { "" };
+            writeln!(f, "  bar {:>4}  {}{default}", change.bar, change.signature)?;
+        }
+
+        writeln!(f, "\nKeys:")?;
+        for change in &self.key.regions {
+            writeln!(f, "  {:>10}  {}", change.position, change.key)?;
+        }
+
+        writeln!(f, "\nChords:")?;
+        for change in &self.chords {
+            writeln!(
+                f,
+                "  {:>10}  {:<8}  {}",
+                change.position, change.chord, change.numeral
+            )?;
+        }
+
+        writeln!(f, "\nMotifs:")?;
+        for (index, motif) in self.motifs.iter().enumerate() {
+            let intervals: Vec<String> = motif
+                .intervals
+                .iter()
+                .map(|interval| format!("{interval:+}"))
+                .collect();
+            writeln!(
+                f,
+                "  {:>2}  track {} ch {:>2}  from {:<8}  {}  ({} exact, {} transposed, {} sequences, {} rhythmic)",
+                index + 1,
+                motif.track,
+                motif.channel,
+                motif.first,
+                intervals.join(" "),
+                motif.exact,
+                motif.transposed,
+                motif.sequences,
+                motif.rhythmic
+            )?;
+        }
+
+        writeln!(f, "\nNotes per bar:")?;
+        for (line, counts) in self.density.chunks(DENSITY_BARS_PER_LINE).enumerate() {
+            let counts: Vec<String> = counts.iter().map(|count| format!("{count:>4}")).collect();
+            writeln!(
+                f,
+                "  bar {:>4} {}",
+                line * DENSITY_BARS_PER_LINE + 1,
+                counts.join("")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Score, ScoreEvent, TempoMap};
+    use midly::{
+        Format, Header,
+        num::{u4, u7, u15, u24},
+    };
+    use serde_json::Value;
+
+    /// Three notes in the first bar and one in the second on a violin, with
+    /// a drum hit in the first bar, optionally stating tempo and meter.
+    fn analysis(stated: bool) -> Analysis {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(480)));
+        let mut score = Score::new(header, 1);
+        let note = |start: u64, pitch: u8, channel: u8| Note {
+            start,
+            duration: 480,
+            pitch,
+            velocity: 80,
+            release: None,
+            channel,
+            track: 0,
+        };
+        score.notes = vec![
+            note(0, 60, 0),
+            note(480, 64, 0),
+            note(960, 67, 0),
+            note(960, 36, 9),
+            note(1920, 72, 0),
+        ];
+        score.events = vec![
+            ScoreEvent::new(
+                0,
+                0,
+                TrackEventKind::Meta(MetaMessage::TrackName(b"Melody")),
+            ),
+            ScoreEvent::new(
+                0,
+                0,
+                TrackEventKind::Midi {
+                    channel: u4::new(0),
+                    message: MidiMessage::ProgramChange {
+                        program: u7::new(40),
+                    },
+                },
+            ),
+        ];
+        if stated {
+            score.events.extend([
+                ScoreEvent::new(
+                    0,
+                    0,
+                    TrackEventKind::Meta(MetaMessage::Tempo(u24::new(600_000))),
+                ),
+                ScoreEvent::new(
+                    0,
+                    0,
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(3, 2, 24, 8)),
+                ),
+            ]);
+        }
+
+        let tempo_map = TempoMap::new(&score.to_smf());
+        Analysis::from_score(score, tempo_map)
+    }
+
+    fn json(analysis: &Analysis) -> Value {
+        let json = Report::new(analysis).render(ReportFormat::Json).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn renders_json_with_stable_field_names() {
+        let json = json(&analysis(false));
+
+        assert_eq!(
+            keys(&json),
+            [
+                "bars",
+                "chords",
+                "density",
+                "duration",
+                "form",
+                "format",
+                "key",
+                "motifs",
+                "notes",
+                "pitch_range",
+                "tempo_changes",
+                "time_signatures",
+                "timing",
+                "tracks",
+            ]
+        );
+        assert_eq!(json["format"], 1);
+        assert_eq!(json["timing"], "480 ticks per quarter");
+        assert_eq!(json["notes"], 5);
+        assert_eq!(keys(&json["pitch_range"]), ["highest", "lowest"]);
+        assert_eq!(
+            keys(&json["key"]),
+            ["confidence", "global", "regions", "source"]
+        );
+        assert_eq!(keys(&json["form"]), ["phrase_bars", "sections"]);
+
+        let track = &json["tracks"][0];
+        assert_eq!(keys(track), ["channels", "index", "name", "notes"]);
+        assert_eq!(track["name"], "Melody");
+        assert_eq!(track["notes"], 5);
+        let channels = track["channels"].as_array().unwrap();
+        assert_eq!(
+            keys(&channels[0]),
+            ["channel", "instruments", "notes", "pitch_range"]
+        );
+        assert_eq!(channels[0]["channel"], 1);
+        assert_eq!(channels[0]["instruments"], serde_json::json!(["Violin"]));
+        assert_eq!(channels[0]["notes"], 4);
+        assert_eq!(channels[1]["channel"], 10);
+        assert_eq!(channels[1]["instruments"], serde_json::json!(["Drum Kit"]));
+    }
+
+    #[test]
+    fn flags_the_default_tempo_and_meter() {
+        let json = json(&analysis(false));
+
+        let tempo = &json["tempo_changes"][0];
+        assert_eq!(keys(tempo), ["bpm", "default", "position", "tick"]);
+        assert_eq!(tempo["bpm"], 120.0);
+        assert_eq!(tempo["default"], true);
+        let meter = &json["time_signatures"][0];
+        assert_eq!(keys(meter), ["bar", "default", "signature", "tick"]);
+        assert_eq!(meter["signature"], "4/4");
+        assert_eq!(meter["default"], true);
+
+        let json = self::json(&analysis(true));
+
+        assert_eq!(json["tempo_changes"][0]["bpm"], 100.0);
+        assert_eq!(json["tempo_changes"][0]["default"], false);
+        assert_eq!(json["time_signatures"][0]["signature"], "3/4");
+        assert_eq!(json["time_signatures"][0]["default"], false);
+    }
+
+    #[test]
+    fn counts_the_notes_starting_in_each_bar() {
+        let report = Report::new(&analysis(false));
+        assert_eq!(report.bars, 2);
+        assert_eq!(report.density, [4, 1]);
+
+        let report = Report::new(&analysis(true));
+        assert_eq!(report.bars, 2);
+        assert_eq!(report.density, [4, 1]);
+    }
+}
@@ -0,0 +1,139 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Note, Score, TempoMap, TimeSignature};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Grid steps per quarter note, a sixteenth-note grid.
+pub const STEPS_PER_QUARTER: f64 = 4.0;
+
+/// One note of a bar rhythm, in grid steps from the start of the bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Hit {
+    pub onset: u32,
+    pub duration: u32,
+}
+
+/// Onsets and durations of one bar, whatever the pitches.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RhythmPattern {
+    pub signature: TimeSignature,
+    /// Notes in onset order, at most one per step. An empty pattern is a
+    /// bar of rest.
+    pub hits: Vec<Hit>,
+}
+
+impl RhythmPattern {
+    /// Grid steps in one bar of `signature`.
+    pub fn steps_in(signature: &TimeSignature) -> u32 {
+        (signature.bar_quarters() * STEPS_PER_QUARTER)
+            .round()
+            .max(1.0) as u32
+    }
+
+    /// Grid steps in one beat of `signature`.
+    pub fn steps_per_beat(signature: &TimeSignature) -> u32 {
+        (signature.beat_quarters() * STEPS_PER_QUARTER)
+            .round()
+            .max(1.0) as u32
+    }
+}
+
+/// Bar-by-bar rhythm of one track and channel.
+#[derive(Debug, Clone)]
+pub struct PartRhythm {
+    pub track: usize,
+    pub channel: u8,
+    /// One pattern per bar of the score.
+    pub bars: Vec<RhythmPattern>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Rhythm {
+    pub parts: Vec<PartRhythm>,
+}
+
+/// Quantizes the onsets of every part to a sixteenth-note grid derived from
+/// the file's resolution, and cuts them into bars following the meter.
+#[derive(Debug)]
+pub struct RhythmAnalyzer;
+
+impl RhythmAnalyzer {
+    pub fn analyze(score: &Score, tempo_map: &TempoMap) -> Rhythm {
+        let mut parts: BTreeMap<(usize, u8), Vec<&Note>> = BTreeMap::new();
+        for note in &score.notes {
+            parts
+                .entry((note.track, note.channel))
+                .or_default()
+                .push(note);
+        }
+
+        let bar_count = tempo_map.bar_count();
+        let parts = parts
+            .into_iter()
+            .map(|((track, channel), notes)| PartRhythm {
+                track,
+                channel,
+                bars: Self::bars(&notes, tempo_map, bar_count),
+            })
+            .collect();
+
+        Rhythm { parts }
+    }
+
+    fn bars(notes: &[&Note], tempo_map: &TempoMap, bar_count: u32) -> Vec<RhythmPattern> {
+        let mut bars: Vec<RhythmPattern> = (0..bar_count)
+            .map(|bar| RhythmPattern {
+                signature: tempo_map.time_signature(tempo_map.bar_start(bar)),
+                hits: Vec::new(),
+            })
+            .collect();
+
+        for note in notes {
+            let position = tempo_map.position(note.start);
+            let Some(pattern) = bars.get(position.bar as usize) else {
+                continue;
+            };
+            let steps = RhythmPattern::steps_in(&pattern.signature);
+            let onset = (position.beat * pattern.signature.beat_quarters() * STEPS_PER_QUARTER)
+                .round() as u32;
+
+            let (bar, onset) = if onset >= steps {
+                (position.bar as usize + 1, 0)
+            } else {
+                (position.bar as usize, onset)
+            };
+            let Some(pattern) = bars.get_mut(bar) else {
+                continue;
+            };
+            let steps = RhythmPattern::steps_in(&pattern.signature);
+
+            let quarters = tempo_map.quarters(note.end()) - tempo_map.quarters(note.start);
+            let duration = ((quarters * STEPS_PER_QUARTER).round() as u32).clamp(1, steps - onset);
+
+            match pattern.hits.iter_mut().find(|hit| hit.onset == onset) {
+                Some(hit) => hit.duration = hit.duration.max(duration),
+                None => pattern.hits.push(Hit { onset, duration }),
+            }
+        }
+
+        for pattern in &mut bars {
+            pattern.hits.sort_by_key(|hit| hit.onset);
+        }
+        bars
+    }
+}
@@ -0,0 +1,264 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Note, Score, ScoreEvent, TempoMap};
+use midly::{Format, MetaMessage, TrackEventKind};
+use std::collections::BTreeMap;
+
+/// Cost of opening a new voice, in semitones of melodic leap.
+const NEW_VOICE_COST: f64 = 10.0;
+/// Cost of each quarter note of rest inside a voice, in semitones.
+const REST_COST: f64 = 2.0;
+/// Quarter notes of rest after which a voice may be picked up again by a
+/// note too far from it, rather than opening a new one.
+const IDLE_QUARTERS: f64 = 1.0;
+/// Share of a quarter note by which a note may overlap the next one of its
+/// voice, as legato playing does.
+const LEGATO_OVERLAP: f64 = 0.125;
+
+/// A monophonic stream of notes.
+#[derive(Debug, Clone)]
+struct Voice {
+    notes: Vec<Note>,
+}
+
+impl Voice {
+    fn last(&self) -> &Note {
+        &self.notes[self.notes.len() - 1]
+    }
+
+    fn mean_pitch(&self) -> f64 {
+        self.notes
+            .iter()
+            .map(|note| f64::from(note.pitch))
+            .sum::<f64>()
+            / self.notes.len() as f64
+    }
+}
+
+/// Splits polyphonic parts into monophonic voices.
+///
+/// Notes are taken onset by onset, highest first, and each one continues
+/// the free voice it is closest to in pitch, accounting for the rest that
+/// would come before it. A note too far from every free voice picks up one
+/// that has fallen silent, or opens a new one, so a chord of four notes
+/// yields four voices.
+#[derive(Debug)]
+pub struct VoiceSeparator;
+
+impl VoiceSeparator {
+    /// Rebuilds `score` with one track per voice. The first voice of each
+    /// track stays on it with every other event, the following ones get
+    /// tracks of their own right after it, on the same channel.
+    pub fn split(score: &Score, tempo_map: &TempoMap) -> Score {
+        let mut parts: BTreeMap<usize, BTreeMap<u8, Vec<Note>>> = BTreeMap::new();
+        for note in &score.notes {
+            parts
+                .entry(note.track)
+                .or_default()
+                .entry(note.channel)
+                .or_default()
+                .push(*note);
+        }
+
+        let mut split = Score::new(score.header, 0);
+        for track in 0..score.track_count {
+            let voices: Vec<Voice> = parts
+                .remove(&track)
+                .unwrap_or_default()
+                .into_values()
+                .flat_map(|notes| Self::separate(notes, tempo_map))
+                .collect();
+
+            let first = split.track_count;
+            let events = score.events.iter().filter(|event| event.track == track);
+            split
+                .events
+                .extend(events.clone().map(|event| event.on_track(first)));
+            split.track_count += voices.len().max(1);
+
+            let end = events
+                .filter(|event| event.is_end_of_track())
+                .map(|event| event.tick)
+                .max();
+            for (index, voice) in voices.into_iter().enumerate() {
+                let voice_track = first + index;
+                if index > 0 {
+                    let name = format!("Voice {}", index + 1);
+                    split.events.push(ScoreEvent::new(
+                        0,
+                        voice_track,
+                        TrackEventKind::Meta(MetaMessage::TrackName(name.as_bytes())),
+                    ));
+                    if let Some(end) = end {
+                        split.events.push(ScoreEvent::new(
+                            end,
+                            voice_track,
+                            TrackEventKind::Meta(MetaMessage::EndOfTrack),
+                        ));
+                    }
+                }
+                split.notes.extend(voice.notes.into_iter().map(|note| Note {
+                    track: voice_track,
+                    ..note
+                }));
+            }
+        }
+
+        if split.track_count > 1 && split.header.format == Format::SingleTrack {
+            split.header.format = Format::Parallel;
+        }
+        split.sort_notes();
+        split
+    }
+
+    /// Voices of the notes of one track and channel, highest first.
+    fn separate(mut notes: Vec<Note>, tempo_map: &TempoMap) -> Vec<Voice> {
+        notes.sort_by_key(|note| (note.start, std::cmp::Reverse(note.pitch)));
+
+        let mut voices: Vec<Voice> = Vec::new();
+        for note in notes {
+            let ticks_per_quarter = tempo_map.ticks_per_quarter(note.start);
+            let overlap = (ticks_per_quarter * LEGATO_OVERLAP) as u64;
+
+            let free: Vec<(usize, f64, f64)> = voices
+                .iter()
+                .enumerate()
+                .filter(|(_, voice)| {
+                    let last = voice.last();
+                    last.start < note.start && last.end() <= note.start + overlap
+                })
+                .map(|(index, voice)| {
+                    let last = voice.last();
+                    let leap = f64::from(last.pitch.abs_diff(note.pitch));
+                    let rest = note.start.saturating_sub(last.end()) as f64 / ticks_per_quarter;
+                    (index, leap + REST_COST * rest, rest)
+                })
+                .collect();
+
+            let closest = free
+                .iter()
+                .filter(|(_, cost, _)| *cost <= NEW_VOICE_COST)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let idle = free
+                .iter()
+                .filter(|(_, _, rest)| *rest >= IDLE_QUARTERS)
+                .max_by(|a, b| a.2.total_cmp(&b.2));
+
+            match closest.or(idle) {
+                Some(&(index, ..)) => voices[index].notes.push(note),
+                None => voices.push(Voice { notes: vec![note] }),
+            }
+        }
+
+        voices.sort_by(|a, b| b.mean_pitch().total_cmp(&a.mean_pitch()));
+        voices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{Header, Timing, num::u15};
+
+    const QUARTER: u64 = 480;
+
+    /// Notes on track 0, channel 0, as start and length in quarter notes
+    /// and pitch.
+    fn score(notes: &[(u64, u64, u8)]) -> Score {
+        let header = Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(QUARTER as u16)),
+        );
+        let mut score = Score::new(header, 1);
+        score.notes = notes
+            .iter()
+            .map(|&(start, duration, pitch)| Note {
+                start: start * QUARTER,
+                duration: duration * QUARTER,
+                pitch,
+                velocity: 80,
+                release: None,
+                channel: 0,
+                track: 0,
+            })
+            .collect();
+        score.sort_notes();
+        score
+    }
+
+    fn voices(score: &Score) -> Vec<Vec<u8>> {
+        let tempo_map = TempoMap::new(&score.to_smf());
+        VoiceSeparator::separate(score.notes.clone(), &tempo_map)
+            .into_iter()
+            .map(|voice| voice.notes.iter().map(|note| note.pitch).collect())
+            .collect()
+    }
+
+    #[test]
+    fn splits_chords_into_one_track_per_note() {
+        let chords = [[60, 64, 67, 72], [62, 65, 69, 74], [64, 67, 71, 76]];
+        let notes: Vec<(u64, u64, u8)> = chords
+            .iter()
+            .enumerate()
+            .flat_map(|(beat, chord)| chord.map(|pitch| (beat as u64, 1, pitch)))
+            .collect();
+        let score = score(&notes);
+        let split = VoiceSeparator::split(&score, &TempoMap::new(&score.to_smf()));
+
+        assert_eq!(split.track_count, 4);
+        assert_eq!(split.header.format, Format::Parallel);
+        for (track, line) in [[72, 74, 76], [67, 69, 71], [64, 65, 67], [60, 62, 64]]
+            .iter()
+            .enumerate()
+        {
+            let pitches: Vec<u8> = split
+                .notes
+                .iter()
+                .filter(|note| note.track == track)
+                .map(|note| note.pitch)
+                .collect();
+            assert_eq!(pitches, line);
+        }
+        assert!(split.events.iter().any(|event| event.track == 3
+            && event.kind() == TrackEventKind::Meta(MetaMessage::TrackName(b"Voice 4"))));
+    }
+
+    #[test]
+    fn follows_a_voice_crossing_a_held_note() {
+        let score = score(&[(0, 4, 64), (0, 1, 60), (1, 1, 62), (2, 1, 65), (3, 1, 67)]);
+
+        assert_eq!(voices(&score), [vec![64], vec![60, 62, 65, 67]]);
+    }
+
+    #[test]
+    fn follows_lines_crossing_in_contrary_motion() {
+        let upper = [(0, 1, 72), (1, 1, 69), (2, 2, 59)];
+        let lower = [(0, 2, 55), (2, 1, 61), (3, 1, 64)];
+        let score = score(&[upper, lower].concat());
+
+        assert_eq!(voices(&score), [vec![72, 69, 59], vec![55, 61, 64]]);
+    }
+
+    #[test]
+    fn keeps_legato_overlaps_in_one_voice() {
+        let mut score = score(&[(0, 1, 60), (1, 1, 62), (2, 1, 64)]);
+        score.notes[0].duration += QUARTER / 16;
+        score.notes[1].duration += QUARTER / 16;
+
+        assert_eq!(voices(&score), [vec![60, 62, 64]]);
+    }
+}
@@ -14,19 +14,92 @@
  * limitations under the License.
  */
 
-use crate::{Input, Output, Result};
+use crate::{
+    Accompaniment, Input, MelodyMode, MotifDeveloper, Output, Result, Score, ScoreEvent, Structure,
+};
+use midly::{MetaMessage, TrackEventKind};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Prefix of the text event recording the seed a composition was made with.
+const SEED_PREFIX: &str = "auto-composer seed: ";
 
 #[derive(Debug)]
 pub struct AutoComposer;
 
 impl AutoComposer {
     pub fn run(input: &Input) -> Result<Output> {
+        let analysis = input.model.reference()?;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(input.seed);
+
+        let sections = input
+            .form
+            .as_ref()
+            .map(|form| form.sections(analysis, input.bars))
+            .unwrap_or_default();
+        let bars = match sections.last() {
+            Some(last) => last.start_bar + last.bars,
+            None => input.bars,
+        };
+
+        let rhythm = input
+            .model
+            .rhythm
+            .clone()
+            .density(input.density)
+            .syncopation(input.syncopation);
+        let mut score = input
+            .model
+            .melody
+            .generate(analysis, &rhythm, bars, &mut rng);
+        if input.melody == MelodyMode::Motifs {
+            let developer = MotifDeveloper::new(analysis, &input.motifs)?;
+            developer.develop(analysis, bars, &mut rng, &mut score);
+        }
+
+        let progression = input.model.harmony.generate(analysis, bars, &mut rng);
+        Accompaniment::render(&progression, input.accompaniment, analysis, &mut score)?;
+
+        Structure::apply(&sections, analysis, &mut score);
+
+        Self::annotate_seed(&mut score, input.seed);
+
         let output = Output {
-            smf: input.smf.clone(),
+            score,
             output_file: input.output_file.clone(),
+            force_overwrite: input.force_overwrite,
+            writer: input.writer,
             run_with_sound: input.run_with_sound.clone(),
+            playback: input.playback,
+            device: input.device.clone(),
         };
 
         Ok(output)
     }
+
+    /// Stores the seed as a `Text` event at the start of the first track,
+    /// replacing the one carried over when the input was itself generated.
+    fn annotate_seed(score: &mut Score, seed: u64) {
+        if score.track_count == 0 {
+            return;
+        }
+
+        score.events.retain(|event| {
+            !matches!(
+                event.kind(),
+                TrackEventKind::Meta(MetaMessage::Text(text)) if text.starts_with(SEED_PREFIX.as_bytes())
+            )
+        });
+
+        let text = format!("{SEED_PREFIX}{seed}");
+        score.events.insert(
+            0,
+            ScoreEvent::new(
+                0,
+                0,
+                TrackEventKind::Meta(MetaMessage::Text(text.as_bytes())),
+            ),
+        );
+    }
 }
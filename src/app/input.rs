@@ -14,34 +14,132 @@
  * limitations under the License.
  */
 
-use crate::{Error, Generate, Result, SoundFont};
-use midly::Smf;
-use std::{fs, path::PathBuf};
+use crate::{
+    Corpus, DeviceSettings, Error, FormSpec, Generate, MelodyMode, Model, Playback, Result,
+    SoundFont, Voicing, Writer,
+};
+use midly::Format;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub struct Input {
-    pub smf: Smf<'static>,
+    pub model: Model,
+    pub bars: u32,
+    pub form: Option<FormSpec>,
+    pub melody: MelodyMode,
+    pub motifs: Vec<usize>,
+    pub density: f64,
+    pub syncopation: f64,
+    pub accompaniment: Voicing,
+    pub seed: u64,
     pub output_file: Option<PathBuf>,
+    pub force_overwrite: bool,
+    pub writer: Writer,
     pub run_with_sound: Option<SoundFont>,
+    pub playback: Playback,
+    pub device: DeviceSettings,
+    pub interactive: bool,
 }
 
 impl Input {
     pub fn build(args: &Generate) -> Result<Self> {
-        let bytes = fs::read(&args.file).map_err(Error::ReadInputFile)?;
-        let smf = Smf::parse(&bytes).map_err(Error::ParseInputFile)?;
+        let (density, syncopation) = Self::rhythm(args)?;
+        let model = match &args.model {
+            Some(path) => Model::read_file(path)?,
+            None => {
+                let corpus = Corpus::load(&args.files, args.split_voices)?;
+                Model::train(&corpus, usize::from(args.order))?
+            }
+        };
 
-        let soundfont = if let Some(file) = &args.custom_sound {
-            Some(SoundFont::new_from_file(file)?)
-        } else if args.run {
-            Some(SoundFont::new_from_name(&args.sound)?)
+        let soundfont = if args.run {
+            Some(SoundFont::new(&args.sound, args.custom_sound.as_ref())?)
         } else {
             None
         };
 
+        let playback = Self::playback(args)?;
+
+        let seed = args.seed.unwrap_or_else(|| {
+            let seed = rand::random();
+            println!("Seed: {seed}");
+            seed
+        });
+
+        let mut writer = Writer::new().running_status(!args.no_running_status);
+        match args.midi_format {
+            Some(0) => writer = writer.format(Format::SingleTrack),
+            Some(_) => writer = writer.format(Format::Parallel),
+            None => {}
+        }
+
         Ok(Self {
-            smf: smf.make_static(),
+            model,
+            bars: args.bars,
+            form: args.form.clone(),
+            melody: args.melody,
+            motifs: args
+                .motifs
+                .iter()
+                .map(|&number| usize::from(number))
+                .collect(),
+            density,
+            syncopation,
+            accompaniment: args.accompaniment,
+            seed,
             output_file: args.output.clone(),
+            force_overwrite: args.force,
+            writer,
             run_with_sound: soundfont,
+            playback,
+            device: DeviceSettings {
+                name: args.device.clone(),
+                sample_rate: args.sample_rate,
+                buffer_size: args.buffer_size,
+                channel_map: args.channel_map,
+            },
+            interactive: args.interactive,
+        })
+    }
+
+    /// Density factor and syncopation amount, checked to be usable chances.
+    fn rhythm(args: &Generate) -> Result<(f64, f64)> {
+        if !args.density.is_finite() || args.density < 0.0 {
+            return Err(Error::InvalidRhythm(format!(
+                "expected a density of zero or more, found {}",
+                args.density
+            )));
+        }
+        if !(0.0..=1.0).contains(&args.syncopation) {
+            return Err(Error::InvalidRhythm(format!(
+                "expected a syncopation between 0 and 1, found {}",
+                args.syncopation
+            )));
+        }
+
+        Ok((args.density, args.syncopation))
+    }
+
+    fn playback(args: &Generate) -> Result<Playback> {
+        if !args.speed.is_finite() || args.speed <= 0.0 {
+            return Err(Error::InvalidPlayback(format!(
+                "expected a positive speed, found {}",
+                args.speed
+            )));
+        }
+
+        let start_bar = args.start_bar.unwrap_or(1);
+        if let Some(end_bar) = args.end_bar.filter(|&end_bar| end_bar < start_bar) {
+            return Err(Error::InvalidPlayback(format!(
+                "the passage ends at bar {end_bar}, before it starts at bar {start_bar}"
+            )));
+        }
+
+        Ok(Playback {
+            start_bar: start_bar - 1,
+            end_bar: args.end_bar,
+            looping: args.looping,
+            speed: args.speed,
         })
     }
 }
@@ -14,24 +14,63 @@
  * limitations under the License.
  */
 
-use crate::{Player, Result, SoundFont};
-use midly::Smf;
+use crate::{
+    AutoComposer, DeviceSettings, Input, Playback, Player, Result, Score, SoundFont, Writer,
+    write_file,
+};
 use std::path::PathBuf;
 
 #[derive(Debug)]
 pub struct Output {
-    pub smf: Smf<'static>,
+    pub score: Score,
     pub output_file: Option<PathBuf>,
+    pub force_overwrite: bool,
+    pub writer: Writer,
     pub run_with_sound: Option<SoundFont>,
+    pub playback: Playback,
+    pub device: DeviceSettings,
 }
 
 impl Output {
     pub fn process(self) -> Result<()> {
+        self.save()?;
+
         if let Some(soundfont) = self.run_with_sound {
-            let player = Player::new(self.smf, soundfont)?;
+            let player = Player::new(self.score, soundfont)?
+                .playback(self.playback)
+                .device(self.device);
             player.run()?;
         }
 
         Ok(())
     }
+
+    /// Saves the piece, then plays it in the interactive player. Each new
+    /// piece asked for is composed from `input` with a random seed and saved
+    /// over the previous one.
+    pub fn interact(self, input: &mut Input) -> Result<()> {
+        self.save()?;
+        let Some(soundfont) = self.run_with_sound else {
+            return Ok(());
+        };
+
+        let player = Player::new(self.score, soundfont)?
+            .playback(self.playback)
+            .device(self.device);
+        player.run_interactive(input.seed, || {
+            input.seed = rand::random();
+            let mut output = AutoComposer::run(input)?;
+            output.force_overwrite = true;
+            output.save()?;
+            Ok((output.score, input.seed))
+        })
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(path) = &self.output_file {
+            let bytes = self.writer.write(&self.score.to_smf())?;
+            write_file(path, &bytes, self.force_overwrite)?;
+        }
+        Ok(())
+    }
 }
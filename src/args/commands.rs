@@ -14,7 +14,7 @@
  * limitations under the License.
  */
 
-use super::Generate;
+use super::{Analyze, Counterpoint, Generate, Harmonize, Render, SplitVoices, Train, Transform};
 use clap::Subcommand;
 
 #[derive(Subcommand, Debug, Clone)]
@@ -22,6 +22,30 @@ pub enum Commands {
     /// Display all available built-in soundfonts
     List,
 
+    /// List the audio hosts, their output devices and the configs they support
+    Devices,
+
     /// Analyze a MIDI file and generate a new procedural composition
     Generate(Generate),
+
+    /// Learn from a corpus of MIDI files and save the models for `generate --model`
+    Train(Train),
+
+    /// Report the layout, instruments, tempo, key and density of a MIDI file
+    Analyze(Analyze),
+
+    /// Render a MIDI file to a WAV file without an audio device
+    Render(Render),
+
+    /// Split polyphonic tracks of a MIDI file into one track per voice
+    SplitVoices(SplitVoices),
+
+    /// Write species counterpoint against a line of a MIDI file
+    Counterpoint(Counterpoint),
+
+    /// Harmonize a melody of a MIDI file in four parts
+    Harmonize(Harmonize),
+
+    /// Transpose, invert, reverse, stretch or change the mode of a MIDI file
+    Transform(Transform),
 }
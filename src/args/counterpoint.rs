@@ -0,0 +1,57 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::Species;
+use clap::Args;
+use std::path::PathBuf;
+
+#[derive(Args, Debug, Clone)]
+pub struct Counterpoint {
+    /// Path to the MIDI file holding the cantus firmus
+    #[arg(short, long, value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Track of the cantus firmus, numbered as listed by `compose analyze`
+    /// (defaults to the first track with pitched notes)
+    #[arg(long, value_name = "TRACK")]
+    pub track: Option<usize>,
+
+    /// Channel of the cantus firmus, from 1 to 16 (defaults to the first
+    /// channel of the track with pitched notes)
+    #[arg(long, value_name = "CHANNEL", value_parser = clap::value_parser!(u8).range(1..=16))]
+    pub channel: Option<u8>,
+
+    /// Species of counterpoint, from 1 to 5
+    #[arg(long, value_name = "SPECIES", default_value = "1")]
+    pub species: Species,
+
+    /// Write the counterpoint below the cantus firmus instead of above it
+    #[arg(long)]
+    pub below: bool,
+
+    /// Seed of the random generator choosing between equally good lines
+    /// (the same line is written every time without it)
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+
+    /// Path where the cantus firmus and its counterpoint will be saved
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// Overwrite the output file if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
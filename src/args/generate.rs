@@ -14,23 +14,96 @@
  * limitations under the License.
  */
 
+use crate::{FormSpec, MAX_ORDER, MelodyMode, Source, Voicing};
 use clap::Args;
 use std::path::PathBuf;
 
 #[derive(Args, Debug, Clone)]
 pub struct Generate {
-    /// Path to the input MIDI file to analyze and compose from
-    #[arg(short, long, value_name = "FILE")]
-    pub file: PathBuf,
+    /// MIDI file, directory or glob pattern to learn from, optionally
+    /// followed by a weight such as `bach/:0.7` (repeatable). The first file
+    /// sets the tracks, tempo, meter and key of the new piece
+    #[arg(
+        short,
+        long = "file",
+        value_name = "FILE[:WEIGHT]",
+        required_unless_present = "model"
+    )]
+    pub files: Vec<Source>,
+
+    /// Model saved by `compose train` to compose from instead of MIDI files
+    #[arg(short, long, value_name = "FILE", conflicts_with_all = ["files", "order", "split_voices"])]
+    pub model: Option<PathBuf>,
+
+    /// Length of the generated composition, in bars
+    #[arg(short, long, value_name = "BARS", default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..))]
+    pub bars: u32,
+
+    /// Number of previous notes the melody model looks at
+    #[arg(long, value_name = "ORDER", default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=MAX_ORDER as i64))]
+    pub order: u8,
+
+    /// Split polyphonic tracks into one track per voice before analyzing, so
+    /// each voice gets a melody of its own
+    #[arg(long)]
+    pub split_voices: bool,
+
+    /// Sections of the piece, such as AABA or ABA'CA, sharing the bars evenly,
+    /// or same-as-input to follow the form detected in the input
+    #[arg(long, value_name = "FORM")]
+    pub form: Option<FormSpec>,
+
+    /// How melodies are generated
+    #[arg(long, value_name = "MODE", default_value = "markov")]
+    pub melody: MelodyMode,
+
+    /// Motif to develop in motifs mode, numbered as listed by `compose analyze`
+    /// (repeatable, defaults to the three most salient)
+    #[arg(long = "motif", value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub motifs: Vec<u16>,
+
+    /// Scales how many notes each bar holds: below 1 thins the rhythms out,
+    /// above 1 fills them in
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    pub density: f64,
+
+    /// Chance, from 0 to 1, of anticipating a note that falls on the beat
+    #[arg(long, value_name = "AMOUNT", default_value_t = 0.0)]
+    pub syncopation: f64,
+
+    /// How the generated chord progression is played
+    #[arg(long, value_name = "STYLE", default_value = "block")]
+    pub accompaniment: Voicing,
+
+    /// Seed of the random generator, to reproduce a previous composition
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
 
     /// Path where the generated MIDI file will be saved
     #[arg(short, long, value_name = "FILE")]
     pub output: Option<PathBuf>,
 
+    /// Overwrite the output file if it already exists
+    #[arg(long)]
+    pub force: bool,
+
+    /// Standard MIDI File format of the output (0: single track, 1: parallel tracks)
+    #[arg(long, value_name = "FORMAT", value_parser = clap::value_parser!(u8).range(0..=1))]
+    pub midi_format: Option<u8>,
+
+    /// Repeat every status byte instead of using running status in the output
+    #[arg(long)]
+    pub no_running_status: bool,
+
     /// Play the generated composition immediately after creation
     #[arg(short, long)]
     pub run: bool,
 
+    /// Play in an interactive terminal player, with keys to pause, seek,
+    /// mute or solo channels and compose again with a new seed
+    #[arg(long)]
+    pub interactive: bool,
+
     /// Built-in soundfont to use for playback
     #[arg(short, long, value_name = "NAME", default_value = "piano")]
     pub sound: String,
@@ -38,11 +111,50 @@ pub struct Generate {
     /// Path to a custom SF2 soundfont file for playback
     #[arg(short, long, value_name = "FILE")]
     pub custom_sound: Option<PathBuf>,
+
+    /// First bar to play, counted from 1
+    #[arg(long, value_name = "BAR", value_parser = clap::value_parser!(u32).range(1..))]
+    pub start_bar: Option<u32>,
+
+    /// Last bar to play, counted from 1 and included
+    #[arg(long, value_name = "BAR", value_parser = clap::value_parser!(u32).range(1..))]
+    pub end_bar: Option<u32>,
+
+    /// Play the passage over and over until `q` is typed
+    #[arg(long = "loop")]
+    pub looping: bool,
+
+    /// Playback speed, 2 playing twice as fast
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    pub speed: f64,
+
+    /// Output device to play on, by name or id as listed by `devices`
+    #[arg(long, value_name = "NAME")]
+    pub device: Option<String>,
+
+    /// Sample rate to open the output device at, in hertz
+    #[arg(long, value_name = "HZ", value_parser = clap::value_parser!(u32).range(16000..=192000))]
+    pub sample_rate: Option<u32>,
+
+    /// Frames the output device asks for at a time
+    #[arg(long, value_name = "FRAMES", value_parser = clap::value_parser!(u32).range(16..))]
+    pub buffer_size: Option<u32>,
+
+    /// Device channels to play the left and right sides on, counted from 1,
+    /// or a single channel to play both mixed down
+    #[arg(long, value_name = "LEFT[,RIGHT]")]
+    pub channel_map: Option<crate::ChannelMap>,
 }
 
 impl Generate {
     pub fn resolve(&mut self) {
-        if self.custom_sound.is_some() {
+        let passage = self.start_bar.is_some() || self.end_bar.is_some() || self.looping;
+        let device = self.device.is_some()
+            || self.sample_rate.is_some()
+            || self.buffer_size.is_some()
+            || self.channel_map.is_some();
+        let playback = passage || device || self.speed != 1.0;
+        if self.custom_sound.is_some() || self.interactive || playback {
             self.run = true;
         }
     }
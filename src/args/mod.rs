@@ -14,10 +14,24 @@
  * limitations under the License.
  */
 
+mod analyze;
 mod cli;
 mod commands;
+mod counterpoint;
 mod generate;
+mod harmonize;
+mod render;
+mod split_voices;
+mod train;
+mod transform;
 
+pub use analyze::Analyze;
 pub use cli::Cli;
 pub use commands::Commands;
+pub use counterpoint::Counterpoint;
 pub use generate::Generate;
+pub use harmonize::Harmonize;
+pub use render::Render;
+pub use split_voices::SplitVoices;
+pub use train::Train;
+pub use transform::Transform;
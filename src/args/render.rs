@@ -0,0 +1,54 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::WavFormat;
+use clap::Args;
+use std::path::PathBuf;
+
+#[derive(Args, Debug, Clone)]
+pub struct Render {
+    /// Path to the MIDI file to render
+    #[arg(short, long, value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Path where the rendered WAV file will be saved
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// Overwrite the output file if it already exists
+    #[arg(long)]
+    pub force: bool,
+
+    /// Sample rate of the rendered audio, in hertz
+    #[arg(long, value_name = "HZ", default_value_t = 44100, value_parser = clap::value_parser!(u32).range(16000..=192000))]
+    pub sample_rate: u32,
+
+    /// Sample encoding of the rendered audio
+    #[arg(short, long, value_name = "DEPTH", default_value = "16")]
+    pub bit_depth: WavFormat,
+
+    /// Maximum length of the reverb and chorus tail after the last event, in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 10.0)]
+    pub max_tail: f64,
+
+    /// Built-in soundfont to use for rendering
+    #[arg(short, long, value_name = "NAME", default_value = "piano")]
+    pub sound: String,
+
+    /// Path to a custom SF2 soundfont file for rendering
+    #[arg(short, long, value_name = "FILE")]
+    pub custom_sound: Option<PathBuf>,
+}
@@ -0,0 +1,45 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{MAX_ORDER, Source};
+use clap::Args;
+use std::path::PathBuf;
+
+#[derive(Args, Debug, Clone)]
+pub struct Train {
+    /// MIDI file, directory or glob pattern to learn from, optionally
+    /// followed by a weight such as `bach/:0.7` (repeatable). The first file
+    /// sets the tracks, tempo, meter and key of the pieces generated from the
+    /// model
+    #[arg(short, long = "file", value_name = "FILE[:WEIGHT]", required = true)]
+    pub files: Vec<Source>,
+
+    /// Number of previous notes, bars and chords the models look at
+    #[arg(long, value_name = "ORDER", default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=MAX_ORDER as i64))]
+    pub order: u8,
+
+    /// Split polyphonic tracks into one track per voice before analyzing
+    #[arg(long)]
+    pub split_voices: bool,
+
+    /// Path where the trained model will be saved
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// Overwrite the output file if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
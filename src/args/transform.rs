@@ -0,0 +1,48 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use clap::Args;
+use std::path::PathBuf;
+
+#[derive(Args, Debug, Clone)]
+pub struct Transform {
+    /// Path to the MIDI file to transform
+    #[arg(short, long, value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Transformations applied in order: `transpose:N` (semitones),
+    /// `diatonic:N` (scale steps), `invert[:PITCH]`, `retrograde`,
+    /// `stretch:FACTOR`, `tempo:FACTOR` and `mode:major` or `mode:minor`
+    #[arg(value_name = "TRANSFORM", required = true)]
+    pub transforms: Vec<crate::Transform>,
+
+    /// Only transform the notes of this track, numbered as listed by
+    /// `compose analyze`
+    #[arg(long, value_name = "TRACK")]
+    pub track: Option<usize>,
+
+    /// Only transform the notes of this channel, from 1 to 16
+    #[arg(long, value_name = "CHANNEL", value_parser = clap::value_parser!(u8).range(1..=16))]
+    pub channel: Option<u8>,
+
+    /// Path where the transformed file will be saved
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// Overwrite the output file if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
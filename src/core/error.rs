@@ -18,28 +18,91 @@ use std::{
     error,
     fmt::{self, Display, Formatter},
     io,
+    path::PathBuf,
 };
 
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Error {
     ReadInputFile(io::Error),
+    InvalidFilePattern(glob::PatternError),
+    NoInputFiles(String),
     ParseInputFile(midly::Error),
     BuiltInSound(String),
     ReadSoundFontFile(io::Error),
     ParseSoundFontFile(soundfont::Error),
+    Synthesizer(String),
     AudioPlayback(String),
+    DeviceNotFound(String),
+    AmbiguousDevice(String, Vec<String>),
+    UnsupportedDeviceConfig(String),
+    InvalidPlayback(String),
+    InvalidRhythm(String),
+    Terminal(io::Error),
+    InvalidRender(String),
+    EncodeAudio(String),
+    OutputFileExists(PathBuf),
+    WriteOutputFile(io::Error),
+    WriteMidi(String),
+    EncodeModel(bincode::error::EncodeError),
+    DecodeModel(bincode::error::DecodeError),
+    CorruptModel(String),
+    UnsupportedModelVersion(u16, u16),
+    NoMelody(String),
+    NoFreeChannel(String),
+    UnknownMotif(usize, usize),
+    SerializeReport(serde_json::Error),
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Self::ReadInputFile(err) => write!(f, "Failed to read input file: {err}"),
+            Self::InvalidFilePattern(err) => write!(f, "Invalid file pattern: {err}"),
+            Self::NoInputFiles(pattern) => write!(f, "No MIDI file found for: {pattern}"),
             Self::ParseInputFile(err) => write!(f, "Failed to parse MIDI file: {err}"),
             Self::BuiltInSound(err) => write!(f, "Invalid built-in soundfont name: {err}"),
             Self::ReadSoundFontFile(err) => write!(f, "Failed to read soundfont file: {err}"),
             Self::ParseSoundFontFile(err) => write!(f, "Failed to parse soundfont file: {err}"),
+            Self::Synthesizer(err) => write!(f, "Synthesizer error: {err}"),
             Self::AudioPlayback(err) => write!(f, "Audio playback error: {err}"),
+            Self::DeviceNotFound(err) => write!(
+                f,
+                "Output device not found: {err} (run `compose devices` to list them)"
+            ),
+            Self::AmbiguousDevice(name, devices) => write!(
+                f,
+                "Output device name `{name}` matches several devices: {} (use an id listed by \
+                 `compose devices`)",
+                devices.join(", ")
+            ),
+            Self::UnsupportedDeviceConfig(err) => write!(f, "Unsupported output config: {err}"),
+            Self::InvalidPlayback(err) => write!(f, "Invalid playback settings: {err}"),
+            Self::InvalidRhythm(err) => write!(f, "Invalid rhythm settings: {err}"),
+            Self::Terminal(err) => write!(f, "Terminal error: {err}"),
+            Self::InvalidRender(err) => write!(f, "Invalid render settings: {err}"),
+            Self::EncodeAudio(err) => write!(f, "Failed to encode audio: {err}"),
+            Self::OutputFileExists(path) => write!(
+                f,
+                "Output file already exists: {} (use --force to overwrite)",
+                path.display()
+            ),
+            Self::WriteOutputFile(err) => write!(f, "Failed to write output file: {err}"),
+            Self::WriteMidi(err) => write!(f, "Failed to encode MIDI file: {err}"),
+            Self::EncodeModel(err) => write!(f, "Failed to encode model: {err}"),
+            Self::DecodeModel(err) => write!(f, "Corrupt model file: {err}"),
+            Self::CorruptModel(err) => write!(f, "Corrupt model file: {err}"),
+            Self::UnsupportedModelVersion(found, supported) => write!(
+                f,
+                "Unsupported model version {found} (this build reads version {supported}, train the model again)"
+            ),
+            Self::NoMelody(err) => write!(f, "No melody to work from: {err}"),
+            Self::NoFreeChannel(err) => write!(f, "No free MIDI channel left for {err}"),
+            Self::UnknownMotif(number, count) => write!(
+                f,
+                "No motif {number}: the input has {count} (run `compose analyze` to list them)"
+            ),
+            Self::SerializeReport(err) => write!(f, "Failed to serialize report: {err}"),
         }
     }
 }
@@ -48,11 +111,33 @@ impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
             Self::ReadInputFile(err) => Some(err),
+            Self::InvalidFilePattern(err) => Some(err),
+            Self::NoInputFiles(_) => None,
             Self::ParseInputFile(err) => Some(err),
             Self::BuiltInSound(_) => None,
             Self::ReadSoundFontFile(err) => Some(err),
             Self::ParseSoundFontFile(err) => Some(err),
+            Self::Synthesizer(_) => None,
             Self::AudioPlayback(_) => None,
+            Self::DeviceNotFound(_) => None,
+            Self::AmbiguousDevice(..) => None,
+            Self::UnsupportedDeviceConfig(_) => None,
+            Self::InvalidPlayback(_) => None,
+            Self::InvalidRhythm(_) => None,
+            Self::Terminal(err) => Some(err),
+            Self::InvalidRender(_) => None,
+            Self::EncodeAudio(_) => None,
+            Self::OutputFileExists(_) => None,
+            Self::WriteOutputFile(err) => Some(err),
+            Self::WriteMidi(_) => None,
+            Self::EncodeModel(err) => Some(err),
+            Self::DecodeModel(err) => Some(err),
+            Self::CorruptModel(_) => None,
+            Self::UnsupportedModelVersion(..) => None,
+            Self::NoMelody(_) => None,
+            Self::NoFreeChannel(_) => None,
+            Self::UnknownMotif(..) => None,
+            Self::SerializeReport(err) => Some(err),
         }
     }
 }
@@ -0,0 +1,173 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{Error, Result};
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Temporary names tried before giving up, when left over files or other
+/// writers already hold them.
+const MAX_ATTEMPTS: u32 = 64;
+
+/// Source of the suffixes keeping the temporary files of one process apart.
+static NEXT_SUFFIX: AtomicU64 = AtomicU64::new(0);
+
+/// Atomically writes `bytes` to `path` through a temporary sibling file, so
+/// an interrupted write never leaves a truncated file behind. Without
+/// `overwrite`, the file is linked into place rather than renamed, which
+/// fails if `path` was created in the meantime instead of replacing it.
+pub fn write_file(path: &Path, bytes: &[u8], overwrite: bool) -> Result<()> {
+    if !overwrite && path.exists() {
+        return Err(Error::OutputFileExists(path.to_path_buf()));
+    }
+
+    let (tmp_path, mut file) = create_temporary(path).map_err(Error::WriteOutputFile)?;
+    let written = file.write_all(bytes).and_then(|_| file.sync_all());
+    drop(file);
+    let result = match written {
+        Err(err) => Err(Error::WriteOutputFile(err)),
+        Ok(()) if overwrite => fs::rename(&tmp_path, path).map_err(Error::WriteOutputFile),
+        Ok(()) => fs::hard_link(&tmp_path, path).map_err(|err| match err.kind() {
+            ErrorKind::AlreadyExists => Error::OutputFileExists(path.to_path_buf()),
+            _ => Error::WriteOutputFile(err),
+        }),
+    };
+    let _ = fs::remove_file(&tmp_path);
+
+    result
+}
+
+/// Creates a temporary sibling of `path` named after the process and a
+/// counter, trying the next name when one is already taken.
+fn create_temporary(path: &Path) -> io::Result<(PathBuf, File)> {
+    let mut attempts = 0;
+    loop {
+        let tmp_path = temporary_path(path, NEXT_SUFFIX.fetch_add(1, Ordering::Relaxed));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+        {
+            Ok(file) => return Ok((tmp_path, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists && attempts + 1 < MAX_ATTEMPTS => {
+                attempts += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn temporary_path(path: &Path, suffix: u64) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.{suffix}.tmp", process::id()));
+
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, thread};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("auto-composer-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entries(dir: &Path) -> Vec<OsString> {
+        let mut entries: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn writes_a_new_file_without_leftovers() {
+        let dir = scratch_dir("new");
+        let path = dir.join("piece.mid");
+
+        write_file(&path, b"first", false).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        assert_eq!(entries(&dir), [OsString::from("piece.mid")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_an_existing_file_unless_told_to_overwrite() {
+        let dir = scratch_dir("existing");
+        let path = dir.join("piece.mid");
+        fs::write(&path, b"first").unwrap();
+
+        let err = write_file(&path, b"second", false).unwrap_err();
+        assert!(matches!(err, Error::OutputFileExists(found) if found == path));
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+
+        write_file(&path, b"second", true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(entries(&dir), [OsString::from("piece.mid")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn skips_temporary_names_already_taken() {
+        let dir = scratch_dir("taken");
+        let path = dir.join("piece.mid");
+        let next = NEXT_SUFFIX.load(Ordering::Relaxed);
+        for suffix in next..next + 4 {
+            fs::write(temporary_path(&path, suffix), b"stale").unwrap();
+        }
+
+        write_file(&path, b"first", false).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        for suffix in next..next + 4 {
+            assert_eq!(fs::read(temporary_path(&path, suffix)).unwrap(), b"stale");
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lets_concurrent_writers_overwrite_each_other() {
+        let dir = scratch_dir("concurrent");
+        let path = dir.join("piece.mid");
+
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                thread::spawn(move || write_file(&path, b"piece", true))
+            })
+            .collect();
+        for writer in writers {
+            assert!(writer.join().unwrap().is_ok());
+        }
+
+        assert_eq!(fs::read(&path).unwrap(), b"piece");
+        assert_eq!(entries(&dir), [OsString::from("piece.mid")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -15,7 +15,9 @@
  */
 
 mod error;
+mod file;
 mod result;
 
 pub use error::Error;
+pub use file::write_file;
 pub use result::Result;
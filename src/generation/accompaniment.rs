@@ -0,0 +1,376 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Analysis, ChordSpan, Error, Harmony, Note, Result, Score, ScoreEvent};
+use clap::ValueEnum;
+use midly::{
+    Format, MetaMessage, MidiMessage, TrackEventKind,
+    num::{u4, u7},
+};
+
+/// Range of the bass note of each chord.
+const BASS_RANGE: (u8, u8) = (36, 50);
+/// Lowest note of the upper voices.
+const UPPER_LOWEST: u8 = 52;
+/// Pitch the first chord is voiced around.
+const UPPER_CENTER: u8 = 60;
+const TRACK_NAME: &[u8] = b"Accompaniment";
+
+/// How the chords of a progression are played.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Voicing {
+    /// Every chord tone struck together and held
+    #[default]
+    Block,
+    /// Chord tones played one after the other, from the bass up
+    Arpeggio,
+    /// Broken chord in the lowest, highest, middle, highest order
+    Alberti,
+}
+
+/// Renders a chord progression into notes on a track of its own.
+#[derive(Debug)]
+pub struct Accompaniment;
+
+impl Accompaniment {
+    /// Adds `progression` to `score` on a new track and the first channel
+    /// the score leaves free. Single-track files keep one track, so the
+    /// accompaniment joins it on its own channel instead.
+    pub fn render(
+        progression: &Harmony,
+        voicing: Voicing,
+        analysis: &Analysis,
+        score: &mut Score,
+    ) -> Result<()> {
+        let Some(last) = progression.chords.last() else {
+            return Ok(());
+        };
+
+        let channel = score
+            .free_channel()
+            .ok_or_else(|| Error::NoFreeChannel("the accompaniment".to_string()))?;
+        let own_track = score.header.format != Format::SingleTrack || score.track_count == 0;
+        let track = if own_track {
+            score.track_count += 1;
+            score.track_count - 1
+        } else {
+            0
+        };
+
+        let event = |tick: u64, kind| ScoreEvent::new(tick, track, kind);
+        if own_track {
+            score.events.push(event(
+                0,
+                TrackEventKind::Meta(MetaMessage::TrackName(TRACK_NAME)),
+            ));
+        }
+        score.events.push(event(
+            0,
+            TrackEventKind::Midi {
+                channel: u4::new(channel),
+                message: MidiMessage::ProgramChange {
+                    program: u7::new(0),
+                },
+            },
+        ));
+        if own_track {
+            score.events.push(event(
+                last.end,
+                TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            ));
+        }
+
+        let velocity = Self::velocity(analysis);
+
+        let mut previous: Option<(u8, Vec<u8>)> = None;
+        for span in &progression.chords {
+            let bass = Self::bass(span, previous.as_ref().map(|(bass, _)| *bass));
+            let upper = Self::upper(span, previous.as_ref().map(|(_, upper)| upper.as_slice()));
+
+            let step = (analysis.tempo_map.ticks_per_quarter(span.start) / 2.0)
+                .round()
+                .max(1.0) as u64;
+            let pattern: Vec<u8> = match voicing {
+                Voicing::Block => Vec::new(),
+                Voicing::Arpeggio => std::iter::once(bass).chain(upper.iter().copied()).collect(),
+                Voicing::Alberti => {
+                    let top = upper[upper.len() - 1];
+                    vec![upper[0], top, upper[upper.len() / 2], top]
+                }
+            };
+
+            let note = |start: u64, end: u64, pitch: u8| Note {
+                start,
+                duration: end - start,
+                pitch,
+                velocity,
+                release: Some(64),
+                channel,
+                track,
+            };
+
+            if pattern.is_empty() {
+                score.notes.push(note(span.start, span.end, bass));
+                score
+                    .notes
+                    .extend(upper.iter().map(|&pitch| note(span.start, span.end, pitch)));
+            } else {
+                let mut tick = span.start;
+                for &pitch in pattern.iter().cycle() {
+                    if tick >= span.end {
+                        break;
+                    }
+                    score
+                        .notes
+                        .push(note(tick, (tick + step).min(span.end), pitch));
+                    tick += step;
+                }
+            }
+
+            previous = Some((bass, upper));
+        }
+
+        score.sort_notes();
+        Ok(())
+    }
+
+    /// Root of the chord in the bass register, as close as possible to the
+    /// previous bass note.
+    fn bass(span: &ChordSpan, previous: Option<u8>) -> u8 {
+        let target = previous.unwrap_or((BASS_RANGE.0 + BASS_RANGE.1) / 2);
+        (BASS_RANGE.0..=BASS_RANGE.1)
+            .filter(|pitch| pitch % 12 == span.chord.root)
+            .min_by_key(|pitch| pitch.abs_diff(target))
+            .unwrap_or(BASS_RANGE.0 + span.chord.root)
+    }
+
+    /// Close-position chord, in ascending order, moving the upper voices as
+    /// little as possible from the previous chord.
+    fn upper(span: &ChordSpan, previous: Option<&[u8]>) -> Vec<u8> {
+        let tones: Vec<u8> = span.chord.pitch_classes().collect();
+
+        let candidates = (0..tones.len()).map(|rotation| {
+            let mut pitch = UPPER_LOWEST + (tones[rotation] + 12 - UPPER_LOWEST % 12) % 12;
+            let mut voicing = vec![pitch];
+            for offset in 1..tones.len() {
+                let tone = tones[(rotation + offset) % tones.len()];
+                pitch += (tone + 12 - pitch % 12) % 12;
+                voicing.push(pitch);
+            }
+            voicing
+        });
+
+        let distance = |voicing: &Vec<u8>| -> u32 {
+            match previous {
+                Some(previous) => voicing
+                    .iter()
+                    .map(|pitch| {
+                        previous
+                            .iter()
+                            .map(|other| u32::from(pitch.abs_diff(*other)))
+                            .min()
+                            .unwrap_or(0)
+                    })
+                    .sum(),
+                None => voicing
+                    .iter()
+                    .map(|pitch| u32::from(pitch.abs_diff(UPPER_CENTER)))
+                    .sum(),
+            }
+        };
+
+        candidates.min_by_key(distance).unwrap_or_default()
+    }
+
+    /// Somewhat softer than the average note of the input, so the
+    /// accompaniment sits under the melody.
+    fn velocity(analysis: &Analysis) -> u8 {
+        let notes = &analysis.score.notes;
+        if notes.is_empty() {
+            return 64;
+        }
+        let average = notes
+            .iter()
+            .map(|note| u32::from(note.velocity))
+            .sum::<u32>()
+            / notes.len() as u32;
+        (average * 4 / 5).clamp(1, 127) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chord, ChordQuality, Key, Mode, TempoMap};
+    use midly::{Header, Timing, num::u15};
+
+    const BAR: u64 = 1920;
+
+    /// A melody on the first track and channel, at a velocity of 100.
+    fn analysis() -> Analysis {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(480)));
+        let mut score = Score::new(header, 1);
+        score.notes = (0..4)
+            .map(|index| Note {
+                start: index * BAR,
+                duration: BAR,
+                pitch: 72,
+                velocity: 100,
+                release: None,
+                channel: 0,
+                track: 0,
+            })
+            .collect();
+
+        let tempo_map = TempoMap::new(&score.to_smf());
+        Analysis::from_score(score, tempo_map)
+    }
+
+    /// I, IV, V7 and I in C major, a bar each.
+    fn progression() -> Harmony {
+        let chords = [
+            (0, ChordQuality::Major),
+            (5, ChordQuality::Major),
+            (7, ChordQuality::Dominant7),
+            (0, ChordQuality::Major),
+        ];
+        Harmony {
+            chords: chords
+                .into_iter()
+                .zip(0..)
+                .map(|((root, quality), bar)| ChordSpan {
+                    start: bar * BAR,
+                    end: (bar + 1) * BAR,
+                    chord: Chord::new(root, quality, root),
+                    key: Key::new(0, Mode::Major),
+                })
+                .collect(),
+        }
+    }
+
+    /// Start, end and pitch of the accompaniment notes rendered with
+    /// `voicing`.
+    fn render(voicing: Voicing) -> Vec<(u64, u64, u8)> {
+        let analysis = analysis();
+        let mut score = analysis.score.clone();
+        Accompaniment::render(&progression(), voicing, &analysis, &mut score).unwrap();
+
+        assert_eq!(score.track_count, 2);
+        score
+            .notes
+            .iter()
+            .filter(|note| note.track == 1)
+            .inspect(|note| {
+                assert_eq!(note.channel, 1);
+                assert_eq!(note.velocity, 80);
+            })
+            .map(|note| (note.start, note.end(), note.pitch))
+            .collect()
+    }
+
+    /// Pitches of the notes starting at `start`, in ascending order.
+    fn chord_at(notes: &[(u64, u64, u8)], start: u64) -> Vec<u8> {
+        let mut pitches: Vec<u8> = notes
+            .iter()
+            .filter(|note| note.0 == start)
+            .map(|note| note.2)
+            .collect();
+        pitches.sort_unstable();
+        pitches
+    }
+
+    #[test]
+    fn holds_block_chords_for_their_whole_span() {
+        let notes = render(Voicing::Block);
+
+        for span in progression().chords {
+            let pitches = chord_at(&notes, span.start);
+            let tones: Vec<u8> = span.chord.pitch_classes().collect();
+            assert_eq!(pitches.len(), tones.len() + 1);
+            assert!((BASS_RANGE.0..=BASS_RANGE.1).contains(&pitches[0]));
+            assert_eq!(pitches[0] % 12, span.chord.root);
+            assert!(
+                pitches[1..]
+                    .iter()
+                    .all(|pitch| tones.contains(&(pitch % 12)))
+            );
+        }
+        assert!(
+            notes
+                .iter()
+                .all(|&(start, end, _)| end - start == BAR && start % BAR == 0)
+        );
+    }
+
+    #[test]
+    fn leads_the_upper_voices_in_close_position_by_small_steps() {
+        let notes = render(Voicing::Block);
+        let chords: Vec<Vec<u8>> = progression()
+            .chords
+            .iter()
+            .map(|span| chord_at(&notes, span.start)[1..].to_vec())
+            .collect();
+
+        for upper in &chords {
+            assert!(upper[0] >= UPPER_LOWEST);
+            assert!(upper[upper.len() - 1] - upper[0] < 12, "{upper:?}");
+        }
+        for pair in chords.windows(2) {
+            for pitch in &pair[1] {
+                let step = pair[0].iter().map(|other| pitch.abs_diff(*other)).min();
+                assert!(step <= Some(2), "{:?} to {:?}", pair[0], pair[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn arpeggiates_from_the_bass_up_in_eighth_notes() {
+        let block = render(Voicing::Block);
+        let notes = render(Voicing::Arpeggio);
+
+        for span in progression().chords {
+            let chord = chord_at(&block, span.start);
+            let arpeggio: Vec<_> = notes
+                .iter()
+                .filter(|note| (span.start..span.end).contains(&note.0))
+                .collect();
+            assert_eq!(arpeggio.len(), 8);
+            for (index, &&(start, end, pitch)) in arpeggio.iter().enumerate() {
+                assert_eq!(start, span.start + index as u64 * 240);
+                assert_eq!(end, start + 240);
+                assert_eq!(pitch, chord[index % chord.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn breaks_alberti_chords_low_high_middle_high() {
+        let block = render(Voicing::Block);
+        let notes = render(Voicing::Alberti);
+
+        for span in progression().chords {
+            let upper = &chord_at(&block, span.start)[1..];
+            let top = upper[upper.len() - 1];
+            let pattern = [upper[0], top, upper[upper.len() / 2], top];
+            let pitches: Vec<u8> = notes
+                .iter()
+                .filter(|note| (span.start..span.end).contains(&note.0))
+                .map(|note| note.2)
+                .collect();
+            assert_eq!(pitches, pattern.repeat(2));
+        }
+    }
+}
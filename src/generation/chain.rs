@@ -0,0 +1,198 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Longest context a chain may look at, keeping the history buffers of
+/// chains decoded from model files small.
+pub const MAX_ORDER: usize = 8;
+
+/// Variable-order Markov chain over symbols of type `T`.
+///
+/// Every context from length zero up to `order` is counted during training,
+/// so sampling can back off to a shorter context when the longest one was
+/// never seen. Counts are weighted, so sequences from several sources can be
+/// blended in chosen proportions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarkovChain<T: Ord + Clone> {
+    order: usize,
+    transitions: BTreeMap<Vec<T>, BTreeMap<T, f64>>,
+}
+
+impl<T: Ord + Clone> MarkovChain<T> {
+    pub fn new(order: usize) -> Self {
+        Self {
+            order,
+            transitions: BTreeMap::new(),
+        }
+    }
+
+    /// Counts every transition of `sequence`, each one adding `weight`.
+    pub fn train(&mut self, sequence: &[T], weight: f64) {
+        for (index, symbol) in sequence.iter().enumerate() {
+            for length in 0..=self.order.min(index) {
+                let context = sequence[index - length..index].to_vec();
+                *self
+                    .transitions
+                    .entry(context)
+                    .or_default()
+                    .entry(symbol.clone())
+                    .or_default() += weight;
+            }
+        }
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    /// Whether the order is at most [`MAX_ORDER`] and every count is finite
+    /// and not negative, as training leaves them.
+    pub fn is_valid(&self) -> bool {
+        self.order <= MAX_ORDER
+            && self
+                .transitions
+                .values()
+                .flat_map(BTreeMap::values)
+                .all(|count| count.is_finite() && *count >= 0.0)
+    }
+
+    /// Every symbol the chain may draw.
+    pub fn symbols(&self) -> impl Iterator<Item = &T> {
+        self.transitions.values().flat_map(BTreeMap::keys)
+    }
+
+    /// Draws the next symbol after `history`, using the longest suffix of it
+    /// that was seen during training.
+    pub fn sample<R: Rng>(&self, history: &[T], rng: &mut R) -> Option<T> {
+        let longest = self.order.min(history.len());
+
+        (0..=longest).rev().find_map(|length| {
+            let context = &history[history.len() - length..];
+            self.transitions
+                .get(context)
+                .and_then(|counts| weighted_choice(counts, rng))
+        })
+    }
+}
+
+fn weighted_choice<T: Clone, R: Rng>(counts: &BTreeMap<T, f64>, rng: &mut R) -> Option<T> {
+    let total: f64 = counts.values().sum();
+    if !(total.is_finite() && total > 0.0) {
+        return None;
+    }
+
+    let mut target = rng.random_range(0.0..total);
+    for (symbol, count) in counts {
+        if target < *count {
+            return Some(symbol.clone());
+        }
+        target -= count;
+    }
+
+    counts.keys().next_back().cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn draws(chain: &MarkovChain<u8>, history: &[u8], count: usize) -> BTreeMap<u8, usize> {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut draws = BTreeMap::new();
+        for _ in 0..count {
+            *draws
+                .entry(chain.sample(history, &mut rng).unwrap())
+                .or_default() += 1;
+        }
+        draws
+    }
+
+    #[test]
+    fn follows_the_longest_context_seen() {
+        let mut chain = MarkovChain::new(2);
+        chain.train(&[1, 2, 3], 1.0);
+        chain.train(&[4, 2, 5], 1.0);
+
+        assert_eq!(draws(&chain, &[1, 2], 100), BTreeMap::from([(3, 100)]));
+        assert_eq!(draws(&chain, &[4, 2], 100), BTreeMap::from([(5, 100)]));
+        assert_eq!(
+            draws(&chain, &[2], 1000)
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            [3, 5]
+        );
+    }
+
+    #[test]
+    fn backs_off_to_shorter_contexts() {
+        let mut chain = MarkovChain::new(3);
+        chain.train(&[1, 2, 3], 1.0);
+
+        assert_eq!(draws(&chain, &[9, 1, 2], 100), BTreeMap::from([(3, 100)]));
+        assert_eq!(draws(&chain, &[9, 2], 100), BTreeMap::from([(3, 100)]));
+        assert_eq!(
+            draws(&chain, &[9, 9], 1000)
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn draws_in_proportion_to_the_weights() {
+        let mut chain = MarkovChain::new(1);
+        chain.train(&[1, 2], 3.0);
+        chain.train(&[1, 3], 1.0);
+        chain.train(&[1, 4], 0.0);
+
+        let draws = draws(&chain, &[1], 4000);
+        let share = draws[&2] as f64 / 4000.0;
+        assert!((share - 0.75).abs() < 0.03, "{share}");
+        assert!(!draws.contains_key(&4));
+    }
+
+    #[test]
+    fn samples_nothing_when_untrained() {
+        let chain: MarkovChain<u8> = MarkovChain::new(2);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+
+        assert!(chain.is_empty());
+        assert_eq!(chain.sample(&[1, 2], &mut rng), None);
+    }
+
+    #[test]
+    fn samples_nothing_from_invalid_counts() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        for weight in [f64::INFINITY, f64::NAN, -1.0] {
+            let mut chain = MarkovChain::new(1);
+            chain.train(&[1, 2], weight);
+
+            assert!(!chain.is_valid());
+            assert_eq!(chain.sample(&[1], &mut rng), None);
+        }
+    }
+}
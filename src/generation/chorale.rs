@@ -0,0 +1,758 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::Line;
+use crate::{Analysis, Chord, ChordQuality, Key, Mode, Note, Result, Score, ScoreEvent};
+use midly::{
+    Format, Header, MetaMessage, MidiMessage, TrackEventKind,
+    num::{u4, u7},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// Lowest and highest pitch of the soprano, alto, tenor and bass.
+const RANGES: [(u8, u8); 4] = [(60, 79), (55, 74), (48, 67), (40, 60)];
+const TRACK_NAMES: [&[u8]; 4] = [b"Soprano", b"Alto", b"Tenor", b"Bass"];
+/// Widest distance between two neighbouring upper voices, an octave.
+const MAX_SPACING: u8 = 12;
+/// Widest distance between the tenor and the bass, a twelfth.
+const MAX_BASS_SPACING: u8 = 19;
+/// Voicings kept from one melody note to the next.
+const BEAM_WIDTH: usize = 400;
+/// Bars a key region must last to be harmonized in its own key. Shorter
+/// regions of a single line are mostly passing notes read as modulations.
+const MODULATION_BARS: u32 = 4;
+/// Cost of borrowing a chord from another key for a note foreign to the
+/// current one, as a secondary dominant or a chord of a neighbouring key,
+/// before a fifth more for every step around the circle of fifths.
+const BORROWED_COST: f64 = 4.0;
+/// Cost of breaking a voice-leading rule, well above any preference.
+const PENALTY: f64 = 100.0;
+
+/// Voice-leading rules of the harmonization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rule {
+    Parallels,
+    Overlap,
+    LeadingTone,
+    Seventh,
+    Leaps,
+    Range,
+}
+
+impl Rule {
+    const ALL: [Self; 6] = [
+        Self::Parallels,
+        Self::Overlap,
+        Self::LeadingTone,
+        Self::Seventh,
+        Self::Leaps,
+        Self::Range,
+    ];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Self::Parallels => "no parallel fifths or octaves",
+            Self::Overlap => "no voice moving past the previous note of its neighbour",
+            Self::LeadingTone => "leading tone of the outer voices rising to the tonic",
+            Self::Seventh => "seventh of the dominant falling by step",
+            Self::Leaps => "no augmented leap and no leap over an octave",
+            Self::Range => "soprano within its range",
+        };
+        write!(f, "{description}")
+    }
+}
+
+/// A chord of the key, and what it does in it.
+#[derive(Debug, Clone, Copy)]
+struct ScaleChord {
+    chord: Chord,
+    /// Scale degree of the root, counted from zero.
+    degree: usize,
+}
+
+/// One chord of the harmonization, with the pitches of the soprano, alto,
+/// tenor and bass.
+#[derive(Debug, Clone, Copy)]
+struct Voicing {
+    harmony: ScaleChord,
+    key: Key,
+    pitches: [u8; 4],
+}
+
+/// Four-part harmonization of a melody.
+#[derive(Debug, Clone)]
+pub struct Chorale {
+    /// Conductor track followed by one track per voice.
+    pub score: Score,
+    pub track: usize,
+    pub channel: u8,
+    pub key: Key,
+    /// Roman numeral of every chord, by bar.
+    pub numerals: Vec<(u32, String)>,
+    /// Rules broken where nothing better was found, with the bars breaking
+    /// them.
+    pub broken: Vec<(Rule, Vec<u32>)>,
+}
+
+impl Display for Chorale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Four-part harmonization of track {}, channel {} in {}: {} chords",
+            self.track,
+            self.channel + 1,
+            self.key,
+            self.numerals.len(),
+        )?;
+
+        let mut bars: BTreeMap<u32, Vec<&str>> = BTreeMap::new();
+        for (bar, numeral) in &self.numerals {
+            bars.entry(*bar).or_default().push(numeral);
+        }
+        for (bar, numerals) in bars {
+            writeln!(f, "  {:>4}  {}", bar + 1, numerals.join(" "))?;
+        }
+
+        if self.broken.is_empty() {
+            return writeln!(f, "Every rule holds.");
+        }
+        writeln!(f, "Broken rules:")?;
+        for (rule, bars) in &self.broken {
+            let bars: Vec<String> = bars.iter().map(|bar| (bar + 1).to_string()).collect();
+            let noun = if bars.len() == 1 { "bar" } else { "bars" };
+            writeln!(f, "  - {rule} ({noun} {})", bars.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Harmonizes a melody in four parts, one chord per melody note.
+///
+/// Chords are the diatonic triads of the local key, with the dominant
+/// seventh, in root position or first inversion. Chords and voicings are
+/// chosen together by dynamic programming: ranges, spacing and doubling
+/// limit the voicings, voice-leading rules weigh as penalties and
+/// functional progressions, cadences and smooth inner voices as
+/// preferences, so a harmonization is always found and the rules it could
+/// not keep are reported.
+#[derive(Debug)]
+pub struct Harmonizer;
+
+impl Harmonizer {
+    pub fn harmonize(
+        analysis: &Analysis,
+        track: Option<usize>,
+        channel: Option<u8>,
+    ) -> Result<Chorale> {
+        let ((track, channel), melody) = Line::melody(analysis, track, channel)?;
+        let shift = Self::octave_shift(&melody);
+        let soprano: Vec<u8> = melody
+            .iter()
+            .map(|note| (i16::from(note.pitch) + shift) as u8)
+            .collect();
+
+        let last = soprano.len() - 1;
+        let mut layers: Vec<Vec<(Voicing, f64, usize)>> = Vec::with_capacity(soprano.len());
+        for (index, (note, &pitch)) in melody.iter().zip(&soprano).enumerate() {
+            let key = Self::key_at(analysis, note.start);
+            let fitted = Self::fit(pitch);
+            let mut candidates = Self::voicings(fitted, key);
+            if candidates.is_empty() {
+                candidates = (0..12)
+                    .flat_map(|tonic| [Key::new(tonic, Mode::Major), Key::new(tonic, Mode::Minor)])
+                    .flat_map(|borrowed| {
+                        let distance = borrowed.signature().0.abs_diff(key.signature().0);
+                        let cost = BORROWED_COST + f64::from(distance.min(12 - distance));
+                        Self::voicings(fitted, borrowed)
+                            .into_iter()
+                            .map(move |(voicing, voicing_cost)| (voicing, voicing_cost + cost))
+                    })
+                    .collect();
+            }
+            for (voicing, _) in &mut candidates {
+                voicing.pitches[0] = pitch;
+            }
+
+            let mut layer: Vec<(Voicing, f64, usize)> = Vec::with_capacity(candidates.len());
+            for (voicing, cost) in candidates {
+                let cost = cost + Self::placement_cost(&voicing, index, last);
+                let best = match layers.last() {
+                    Some(previous) => previous
+                        .iter()
+                        .enumerate()
+                        .map(|(back, (from, total, _))| {
+                            let (broken, step) = Self::judge(from, &voicing);
+                            let penalty = f64::from(broken.count_ones()) * PENALTY;
+                            (total + step + penalty, back)
+                        })
+                        .min_by(|a, b| a.0.total_cmp(&b.0)),
+                    None => Some((0.0, 0)),
+                };
+                if let Some((total, back)) = best {
+                    layer.push((voicing, total + cost, back));
+                }
+            }
+
+            layer.sort_by(|a, b| a.1.total_cmp(&b.1));
+            layer.truncate(BEAM_WIDTH);
+            layers.push(layer);
+        }
+
+        let mut voicings: Vec<Voicing> = Vec::with_capacity(layers.len());
+        let mut index = 0;
+        for layer in layers.iter().rev() {
+            let Some(&(voicing, _, back)) = layer.get(index) else {
+                break;
+            };
+            voicings.push(voicing);
+            index = back;
+        }
+        voicings.reverse();
+
+        let bar = |tick: u64| analysis.tempo_map.position(tick).bar;
+        let mut broken: BTreeMap<Rule, Vec<u32>> = BTreeMap::new();
+        let mut mark = |rule: Rule, note: &Note| {
+            let bars = broken.entry(rule).or_default();
+            if bars.last() != Some(&bar(note.start)) {
+                bars.push(bar(note.start));
+            }
+        };
+        for (pair, note) in voicings.windows(2).zip(&melody[1..]) {
+            let (bits, _) = Self::judge(&pair[0], &pair[1]);
+            for rule in Rule::ALL.into_iter().filter(|rule| bits & rule.bit() != 0) {
+                mark(rule, note);
+            }
+        }
+        for (voicing, note) in voicings.iter().zip(&melody) {
+            if Self::fit(voicing.pitches[0]) != voicing.pitches[0] {
+                mark(Rule::Range, note);
+            }
+        }
+
+        let mut current = analysis.key.global;
+        let numerals = voicings
+            .iter()
+            .zip(&melody)
+            .map(|(voicing, note)| {
+                let numeral = voicing.harmony.chord.numeral(&voicing.key);
+                if voicing.key == current {
+                    return (bar(note.start), numeral);
+                }
+                current = voicing.key;
+                (
+                    bar(note.start),
+                    format!("{}: {numeral}", Self::key_label(current)),
+                )
+            })
+            .collect();
+
+        Ok(Chorale {
+            score: Self::score(analysis, &melody, &voicings),
+            track,
+            channel,
+            key: analysis.key.global,
+            numerals,
+            broken: broken.into_iter().collect(),
+        })
+    }
+
+    /// Key of the region holding `tick` when it lasts long enough to be a
+    /// modulation, the global key otherwise.
+    fn key_at(analysis: &Analysis, tick: u64) -> Key {
+        let bar = |tick: u64| analysis.tempo_map.position(tick).bar;
+        analysis
+            .key
+            .regions
+            .iter()
+            .find(|region| (region.start..region.end).contains(&tick))
+            .filter(|region| bar(region.end) - bar(region.start) >= MODULATION_BARS)
+            .map_or(analysis.key.global, |region| region.key)
+    }
+
+    /// Key as written before the numerals of a modulation, upper case for
+    /// major and lower case for minor.
+    fn key_label(key: Key) -> String {
+        let tonic = key.spell(key.tonic);
+        match key.mode {
+            Mode::Major => tonic.to_string(),
+            Mode::Minor => tonic.to_lowercase(),
+        }
+    }
+
+    /// Octaves moving the whole melody into the soprano range, or as close
+    /// to it as it goes without leaving the MIDI pitches, so the line keeps
+    /// its shape. Notes still outside are voiced as their octave within the
+    /// range and reported.
+    fn octave_shift(melody: &[&Note]) -> i16 {
+        let outside = |shift: i16| -> i16 {
+            melody
+                .iter()
+                .map(|note| {
+                    let pitch = i16::from(note.pitch) + shift;
+                    let (lowest, highest) = (i16::from(RANGES[0].0), i16::from(RANGES[0].1));
+                    (lowest - pitch).max(0) + (pitch - highest).max(0)
+                })
+                .sum()
+        };
+        (-4..=4)
+            .map(|octaves| octaves * 12)
+            .filter(|&shift| {
+                melody
+                    .iter()
+                    .all(|note| (0..=127).contains(&(i16::from(note.pitch) + shift)))
+            })
+            .min_by_key(|&shift| (outside(shift), shift.abs()))
+            .unwrap_or(0)
+    }
+
+    /// Octave of `pitch` within the soprano range, which the lower voices
+    /// are built under.
+    fn fit(pitch: u8) -> u8 {
+        let mut pitch = pitch;
+        while pitch < RANGES[0].0 {
+            pitch += 12;
+        }
+        while pitch > RANGES[0].1 {
+            pitch -= 12;
+        }
+        pitch
+    }
+
+    /// Diatonic triads of `key` and its dominant seventh, the dominant and
+    /// leading-tone chords of minor keys taking the raised leading tone.
+    fn harmonies(key: Key) -> Vec<ScaleChord> {
+        let scale = key.scale();
+        let leading_tone = (key.tonic + 11) % 12;
+        let raise = |degree: usize, pitch_class: u8| {
+            let dominant = degree == 4 || degree == 6;
+            if key.mode == Mode::Minor && dominant && pitch_class == (key.tonic + 10) % 12 {
+                leading_tone
+            } else {
+                pitch_class
+            }
+        };
+
+        let mut harmonies: Vec<ScaleChord> = (0..7)
+            .filter_map(|degree| {
+                let root = raise(degree, scale[degree]);
+                let third = raise(degree, scale[(degree + 2) % 7]);
+                let fifth = raise(degree, scale[(degree + 4) % 7]);
+                let quality = match ((third + 12 - root) % 12, (fifth + 12 - root) % 12) {
+                    (4, 7) => ChordQuality::Major,
+                    (3, 7) => ChordQuality::Minor,
+                    (3, 6) => ChordQuality::Diminished,
+                    _ => return None,
+                };
+                Some(ScaleChord {
+                    chord: Chord::new(root, quality, root),
+                    degree,
+                })
+            })
+            .collect();
+
+        let dominant = scale[4];
+        harmonies.push(ScaleChord {
+            chord: Chord::new(dominant, ChordQuality::Dominant7, dominant),
+            degree: 4,
+        });
+        harmonies
+    }
+
+    /// Every voicing of every chord holding `soprano`, with the cost of its
+    /// doubling and inversion. Voices stay in their ranges, in order and
+    /// within the spacing limits, and second inversions are left out.
+    fn voicings(soprano: u8, key: Key) -> Vec<(Voicing, f64)> {
+        let leading_tone = (key.tonic + 11) % 12;
+        let mut voicings = Vec::new();
+
+        for harmony in Self::harmonies(key) {
+            let tones: Vec<u8> = harmony.chord.pitch_classes().collect();
+            if !tones.contains(&(soprano % 12)) {
+                continue;
+            }
+            let in_chord = |pitch: u8| tones.contains(&(pitch % 12));
+
+            for bass in
+                (RANGES[3].0..=RANGES[3].1).filter(|&pitch| in_chord(pitch) && pitch < soprano)
+            {
+                let inversion = tones
+                    .iter()
+                    .position(|&tone| tone == bass % 12)
+                    .unwrap_or(0);
+                if inversion == 2 && !harmony.chord.quality.is_seventh() {
+                    continue;
+                }
+
+                for tenor in (RANGES[2].0..=RANGES[2].1).filter(|&pitch| {
+                    in_chord(pitch) && pitch > bass && pitch - bass <= MAX_BASS_SPACING
+                }) {
+                    for alto in (RANGES[1].0..=RANGES[1].1).filter(|&pitch| {
+                        in_chord(pitch)
+                            && pitch >= tenor
+                            && pitch <= soprano
+                            && pitch - tenor <= MAX_SPACING
+                            && soprano - pitch <= MAX_SPACING
+                    }) {
+                        let pitches = [soprano, alto, tenor, bass];
+                        let Some(cost) = Self::doubling_cost(&pitches, &tones, leading_tone) else {
+                            continue;
+                        };
+
+                        let mut chord = harmony.chord;
+                        chord.bass = bass % 12;
+                        let inversion_cost = match (inversion, harmony.degree) {
+                            (0, 6) => 2.0,
+                            (0, _) => 0.0,
+                            _ => 0.5,
+                        };
+                        let unisons = pitches.windows(2).filter(|pair| pair[0] == pair[1]).count();
+
+                        voicings.push((
+                            Voicing {
+                                harmony: ScaleChord { chord, ..harmony },
+                                key,
+                                pitches,
+                            },
+                            cost + inversion_cost + unisons as f64,
+                        ));
+                    }
+                }
+            }
+        }
+
+        voicings
+    }
+
+    /// Cost of the doubling of `pitches`, or nothing when the third is
+    /// missing, a seventh lacks its root or the leading tone is doubled.
+    /// Doubling the root is preferred, then the fifth, then the third.
+    fn doubling_cost(pitches: &[u8; 4], tones: &[u8], leading_tone: u8) -> Option<f64> {
+        let count = |tone: u8| pitches.iter().filter(|pitch| *pitch % 12 == tone).count();
+        let counts: Vec<usize> = tones.iter().map(|&tone| count(tone)).collect();
+
+        if counts[0] == 0 || counts[1] == 0 || count(leading_tone) > 1 {
+            return None;
+        }
+        if tones.len() == 4 {
+            return (counts[3] == 1).then_some(if counts[2] == 0 { 1.0 } else { 0.0 });
+        }
+
+        Some(match (counts[0], counts[1], counts[2]) {
+            (2, 1, 1) => 0.0,
+            (1, 1, 2) => 1.0,
+            (1, 2, 1) => 2.0,
+            (3, 1, 0) => 1.5,
+            _ => 3.0,
+        })
+    }
+
+    /// Cadential preferences: the tonic at both ends, in root position at
+    /// the end, after a root-position dominant.
+    fn placement_cost(voicing: &Voicing, index: usize, last: usize) -> f64 {
+        let degree = voicing.harmony.degree;
+        let root_position = voicing.harmony.chord.inversion() == 0;
+        match index {
+            0 if degree != 0 => 3.0,
+            _ if index == last && !(degree == 0 && root_position) => 10.0,
+            _ if index + 1 == last && !(degree == 4 && root_position) => 5.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Rules broken moving from `from` to `to`, and the cost of the
+    /// progression and of the motion of the voices.
+    fn judge(from: &Voicing, to: &Voicing) -> (u8, f64) {
+        let mut broken = 0;
+        let mut cost = Self::progression_cost(from.harmony.degree, to.harmony.degree);
+        if from.harmony.chord == to.harmony.chord && from.key == to.key {
+            cost += 1.0;
+        }
+
+        let (a, b) = (from.pitches, to.pitches);
+        for upper in 0..4 {
+            for lower in upper + 1..4 {
+                let before = a[upper].abs_diff(a[lower]) % 12;
+                let after = b[upper].abs_diff(b[lower]) % 12;
+                let moved = a[upper] != b[upper] || a[lower] != b[lower];
+                if moved && before == after && matches!(after, 0 | 7) && a != b {
+                    broken |= Rule::Parallels.bit();
+                }
+            }
+        }
+
+        for voice in 1..4 {
+            if b[voice] > a[voice - 1] || b[voice - 1] < a[voice] {
+                broken |= Rule::Overlap.bit();
+            }
+        }
+
+        let key = from.key;
+        let leading_tone = (key.tonic + 11) % 12;
+        let dominant = matches!(from.harmony.degree, 4 | 6);
+        let resolves = dominant
+            && to
+                .harmony
+                .chord
+                .pitch_classes()
+                .any(|tone| tone == key.tonic);
+        for voice in 0..4 {
+            let motion = i16::from(b[voice]) - i16::from(a[voice]);
+            let leap = motion.unsigned_abs();
+
+            if resolves && a[voice] % 12 == leading_tone && motion != 1 {
+                if voice == 0 || voice == 3 {
+                    broken |= Rule::LeadingTone.bit();
+                } else {
+                    cost += 3.0;
+                }
+            }
+
+            if from.harmony.chord.quality.is_seventh()
+                && a[voice] % 12 == (from.harmony.chord.root + 10) % 12
+                && from.harmony.chord != to.harmony.chord
+                && !(-2..=-1).contains(&motion)
+            {
+                broken |= Rule::Seventh.bit();
+            }
+
+            let augmented_second = key.mode == Mode::Minor
+                && leap == 3
+                && [a[voice] % 12, b[voice] % 12].contains(&leading_tone)
+                && [a[voice] % 12, b[voice] % 12].contains(&((key.tonic + 8) % 12));
+            if voice > 0 && (leap > 12 || leap == 6 || augmented_second) {
+                broken |= Rule::Leaps.bit();
+            }
+
+            cost += match voice {
+                0 => 0.0,
+                3 => f64::from(leap) * 0.25,
+                _ if leap > 4 => f64::from(leap),
+                _ => f64::from(leap) * 0.5,
+            };
+        }
+
+        let soprano = i16::from(b[0]) - i16::from(a[0]);
+        let bass = i16::from(b[3]) - i16::from(a[3]);
+        let outer = b[0].abs_diff(b[3]) % 12;
+        if soprano.signum() == bass.signum() && soprano.abs() > 2 && matches!(outer, 0 | 7) {
+            cost += 5.0;
+        }
+
+        (broken, cost)
+    }
+
+    /// Preference for the functional progressions of common practice, by
+    /// scale degree.
+    fn progression_cost(from: usize, to: usize) -> f64 {
+        match (from, to) {
+            (0, 2) => 2.0,
+            (0, _) => 0.0,
+            (1, 4 | 6) => 0.0,
+            (2, 5 | 3) => 0.0,
+            (3, 4 | 0 | 1 | 6) => 0.0,
+            (4, 0 | 5) => 0.0,
+            (4, 3) => 3.0,
+            (5, 1 | 3 | 4) => 0.0,
+            (6, 0) => 0.0,
+            (from, to) if from == to => 0.0,
+            (1 | 2 | 6, _) => 3.0,
+            _ => 2.0,
+        }
+    }
+
+    /// Format-1 score with a conductor track carrying the tempo, meter and
+    /// key of the input, then the soprano, alto, tenor and bass on tracks
+    /// and channels of their own.
+    fn score(analysis: &Analysis, melody: &[&Note], voicings: &[Voicing]) -> Score {
+        let source = &analysis.score;
+        let header = Header::new(Format::Parallel, source.header.timing);
+        let mut score = Score::new(header, 1 + TRACK_NAMES.len());
+
+        score.events = source
+            .events
+            .iter()
+            .filter(|event| {
+                matches!(
+                    event.kind(),
+                    TrackEventKind::Meta(
+                        MetaMessage::Tempo(_)
+                            | MetaMessage::TimeSignature(..)
+                            | MetaMessage::KeySignature(..)
+                    )
+                )
+            })
+            .map(|event| event.on_track(0))
+            .collect();
+
+        let end = melody.iter().map(|note| note.end()).max().unwrap_or(0);
+        for (voice, name) in TRACK_NAMES.iter().enumerate() {
+            let track = voice + 1;
+            let channel = voice as u8;
+            score.events.extend([
+                ScoreEvent::new(0, track, TrackEventKind::Meta(MetaMessage::TrackName(name))),
+                ScoreEvent::new(
+                    0,
+                    track,
+                    TrackEventKind::Midi {
+                        channel: u4::new(channel),
+                        message: MidiMessage::ProgramChange {
+                            program: u7::new(0),
+                        },
+                    },
+                ),
+            ]);
+
+            score
+                .notes
+                .extend(melody.iter().zip(voicings).map(|(note, voicing)| Note {
+                    pitch: voicing.pitches[voice],
+                    release: Some(64),
+                    channel,
+                    track,
+                    ..**note
+                }));
+        }
+        for track in 0..score.track_count {
+            score.events.push(ScoreEvent::new(
+                end,
+                track,
+                TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            ));
+        }
+
+        score.sort_notes();
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempoMap;
+    use midly::{Timing, num::u15};
+
+    const WHOLE: u64 = 4 * 480;
+
+    /// Sixteen bars in C major, closing on a falling step to the tonic.
+    const MELODY: [u8; 16] = [
+        64, 62, 60, 62, 64, 64, 64, 62, 62, 62, 64, 67, 67, 64, 62, 60,
+    ];
+
+    fn analysis(pitches: &[u8]) -> Analysis {
+        let header = Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480)));
+        let mut score = Score::new(header, 1);
+        score.notes = pitches
+            .iter()
+            .enumerate()
+            .map(|(bar, &pitch)| Note {
+                start: bar as u64 * WHOLE,
+                duration: WHOLE,
+                pitch,
+                velocity: 80,
+                release: None,
+                channel: 0,
+                track: 0,
+            })
+            .collect();
+        score.events.push(ScoreEvent::new(
+            0,
+            0,
+            TrackEventKind::Meta(MetaMessage::KeySignature(0, false)),
+        ));
+
+        let tempo_map = TempoMap::new(&score.to_smf());
+        Analysis::from_score(score, tempo_map)
+    }
+
+    /// Soprano, alto, tenor and bass of every chord of `chorale`.
+    fn chords(chorale: &Chorale) -> Vec<[u8; 4]> {
+        let voice = |track: usize| -> Vec<u8> {
+            chorale
+                .score
+                .notes
+                .iter()
+                .filter(|note| note.track == track)
+                .map(|note| note.pitch)
+                .collect()
+        };
+        let voices: Vec<Vec<u8>> = (1..=4).map(voice).collect();
+        (0..voices[0].len())
+            .map(|index| [0, 1, 2, 3].map(|voice| voices[voice][index]))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_voices_in_range_in_order_and_spaced() {
+        let chorale = Harmonizer::harmonize(&analysis(&MELODY), None, None).unwrap();
+        let chords = chords(&chorale);
+        assert_eq!(chords.len(), MELODY.len());
+
+        for chord in chords {
+            for (pitch, (lowest, highest)) in chord.iter().zip(RANGES) {
+                assert!((lowest..=highest).contains(pitch), "{chord:?}");
+            }
+            assert!(chord.windows(2).all(|pair| pair[0] >= pair[1]), "{chord:?}");
+            assert!(chord[0] - chord[1] <= MAX_SPACING, "{chord:?}");
+            assert!(chord[1] - chord[2] <= MAX_SPACING, "{chord:?}");
+            assert!(chord[2] - chord[3] <= MAX_BASS_SPACING, "{chord:?}");
+        }
+    }
+
+    #[test]
+    fn closes_on_a_root_position_tonic_after_the_dominant() {
+        let chorale = Harmonizer::harmonize(&analysis(&MELODY), None, None).unwrap();
+        let chords = chords(&chorale);
+        let numerals: Vec<&str> = chorale.numerals.iter().map(|(_, n)| n.as_str()).collect();
+
+        assert_eq!(numerals.first(), Some(&"I"));
+        assert_eq!(numerals.last(), Some(&"I"));
+        assert!(numerals[numerals.len() - 2].starts_with('V'));
+        assert!(!numerals[numerals.len() - 2].starts_with("VI"));
+        assert_eq!(chords[chords.len() - 1][3] % 12, 0);
+        assert_eq!(chords[chords.len() - 2][3] % 12, 7);
+        assert!(chorale.broken.is_empty(), "{chorale}");
+    }
+
+    #[test]
+    fn moves_the_whole_melody_by_octaves() {
+        let low: Vec<u8> = MELODY.iter().map(|pitch| pitch - 12).collect();
+        let chorale = Harmonizer::harmonize(&analysis(&low), None, None).unwrap();
+        let soprano: Vec<u8> = chords(&chorale).iter().map(|chord| chord[0]).collect();
+        assert_eq!(soprano, MELODY);
+    }
+
+    #[test]
+    fn keeps_the_shape_of_a_melody_wider_than_the_soprano() {
+        let wide = [60, 64, 67, 72, 76, 79, 84, 79, 76, 72, 67, 64, 62, 60];
+        let chorale = Harmonizer::harmonize(&analysis(&wide), None, None).unwrap();
+        let soprano: Vec<u8> = chords(&chorale).iter().map(|chord| chord[0]).collect();
+
+        assert_eq!(soprano, wide);
+        assert!(
+            chorale.broken.contains(&(Rule::Range, vec![6])),
+            "{chorale}"
+        );
+    }
+}
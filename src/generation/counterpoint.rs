@@ -0,0 +1,739 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::Line;
+use crate::{Analysis, Error, Note, Result, Score, ScoreEvent};
+use clap::ValueEnum;
+use midly::{
+    Format, MetaMessage, MidiMessage, TrackEventKind,
+    num::{u4, u7},
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
+
+const TRACK_NAME: &[u8] = b"Counterpoint";
+/// Widest distance between the two voices, a twelfth.
+const MAX_DISTANCE: u8 = 19;
+/// Widest leap, an octave.
+const MAX_LEAP: u8 = 12;
+/// Furthest the candidates reach past the cantus, two octaves.
+const SEARCH_SPAN: i16 = 24;
+/// Rhythms of the florid bars, in quarters of the cantus note, cycled through.
+const FLORID_BARS: [&[u64]; 4] = [&[2, 2], &[1, 1, 2], &[2, 1, 1], &[1, 1, 1, 1]];
+
+/// Kinds of counterpoint against a cantus firmus, after Fux.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Species {
+    /// Note against note
+    #[default]
+    #[value(name = "1", alias = "first")]
+    First,
+    /// Two notes against each note, with passing dissonances on the weak half
+    #[value(name = "2", alias = "second")]
+    Second,
+    /// Four notes against each note, with passing and neighbour dissonances
+    #[value(name = "3", alias = "third")]
+    Third,
+    /// Notes tied over the bar line, with dissonances as suspensions
+    #[value(name = "4", alias = "fourth")]
+    Fourth,
+    /// Florid counterpoint mixing the rhythms of the second and third species
+    #[value(name = "5", alias = "fifth")]
+    Fifth,
+}
+
+impl Display for Species {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::First => "First",
+            Self::Second => "Second",
+            Self::Third => "Third",
+            Self::Fourth => "Fourth",
+            Self::Fifth => "Fifth",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Rules the counterpoint follows, from the first given up when no line
+/// satisfies them all to the last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rule {
+    HiddenPerfects,
+    Leaps,
+    Range,
+    Cadence,
+    Dissonance,
+    Parallels,
+    Consonance,
+}
+
+impl Rule {
+    const ALL: [Self; 7] = [
+        Self::HiddenPerfects,
+        Self::Leaps,
+        Self::Range,
+        Self::Cadence,
+        Self::Dissonance,
+        Self::Parallels,
+        Self::Consonance,
+    ];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Self::HiddenPerfects => "no fifth or octave reached by similar motion with a leap",
+            Self::Leaps => "no leap of a tritone, a major sixth, a seventh or over an octave",
+            Self::Range => "no crossing and no more than a twelfth between the voices",
+            Self::Cadence => "begin on a perfect consonance, end on the octave by step",
+            Self::Dissonance => {
+                "dissonances only as passing or neighbour notes, or suspensions resolving down"
+            }
+            Self::Parallels => "no parallel fifths or octaves",
+            Self::Consonance => "consonances on every note entering on the beat",
+        };
+        write!(f, "{description}")
+    }
+}
+
+/// Onset of one note of the counterpoint, and what it sounds against.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    start: u64,
+    duration: u64,
+    /// Cantus note the slot starts over, counted from zero.
+    bar: usize,
+    cantus: u8,
+    /// Whether the note must be consonant when it starts.
+    consonant: bool,
+    /// Whether the slot starts with its cantus note.
+    downbeat: bool,
+    /// Cantus note the slot is held over into, in the fourth species.
+    tied: Option<u8>,
+    /// Previous slot starting with its cantus note.
+    previous_downbeat: Option<usize>,
+}
+
+/// Previous pitch, current pitch and pitch of the last downbeat.
+type State = (Option<u8>, u8, Option<u8>);
+
+/// Counterpoint written against a line of the input.
+#[derive(Debug, Clone)]
+pub struct Counterpoint {
+    /// Input with the cantus as its only notes and the counterpoint on a
+    /// track of its own.
+    pub score: Score,
+    pub species: Species,
+    pub below: bool,
+    pub track: usize,
+    pub channel: u8,
+    pub cantus_notes: usize,
+    pub notes: usize,
+    /// Rules given up to find a line, with the bars breaking them.
+    pub broken: Vec<(Rule, Vec<usize>)>,
+}
+
+impl Display for Counterpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} species counterpoint {} track {}, channel {}: {} notes against {}",
+            self.species,
+            if self.below { "below" } else { "above" },
+            self.track,
+            self.channel + 1,
+            self.notes,
+            self.cantus_notes,
+        )?;
+
+        if self.broken.is_empty() {
+            return writeln!(f, "Every rule holds.");
+        }
+        writeln!(f, "Relaxed rules:")?;
+        for (rule, bars) in &self.broken {
+            let bars: Vec<String> = bars.iter().map(ToString::to_string).collect();
+            let noun = if bars.len() == 1 { "bar" } else { "bars" };
+            writeln!(f, "  - {rule} ({noun} {})", bars.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Rule-based species counterpoint against a cantus firmus taken from the
+/// input.
+///
+/// Each cantus note makes a bar. The line is searched by dynamic
+/// programming over the pitches of the local key: every rule is a hard
+/// constraint, and melodic preferences such as stepwise and contrary motion
+/// are costs. When no line satisfies every rule, rules are given up one at
+/// a time in the order of [`Rule`] until one does.
+#[derive(Debug, Clone)]
+pub struct CounterpointGenerator {
+    species: Species,
+    below: bool,
+}
+
+impl CounterpointGenerator {
+    pub fn new(species: Species, below: bool) -> Self {
+        Self { species, below }
+    }
+
+    /// Writes a counterpoint against the melody of `track` and `channel`,
+    /// or of the first pitched part of that track, or of the input. A seed
+    /// adds random draws that only choose between lines of similar cost.
+    pub fn generate(
+        &self,
+        analysis: &Analysis,
+        track: Option<usize>,
+        channel: Option<u8>,
+        seed: Option<u64>,
+    ) -> Result<Counterpoint> {
+        let ((track, channel), cantus) = Line::melody(analysis, track, channel)?;
+
+        let slots = self.slots(&cantus);
+        let candidates: Vec<Vec<u8>> = slots
+            .iter()
+            .map(|slot| {
+                let cadence = slot.bar + 2 == cantus.len();
+                self.candidates(slot, analysis, cadence)
+            })
+            .collect();
+        let jitter: Vec<Vec<f64>> = match seed.map(ChaCha8Rng::seed_from_u64) {
+            Some(mut rng) => candidates
+                .iter()
+                .map(|pitches| pitches.iter().map(|_| rng.random_range(0.0..1.0)).collect())
+                .collect(),
+            None => candidates
+                .iter()
+                .map(|pitches| vec![0.0; pitches.len()])
+                .collect(),
+        };
+
+        let mut relaxed = 0;
+        let mut pitches = None;
+        for rule in Rule::ALL.iter().map(Some).chain([None]) {
+            pitches = self.solve(&slots, &candidates, &jitter, !relaxed);
+            match rule {
+                Some(rule) if pitches.is_none() => relaxed |= rule.bit(),
+                _ => break,
+            }
+        }
+        let pitches = pitches.unwrap_or_default();
+
+        let mut broken: BTreeMap<Rule, Vec<usize>> = BTreeMap::new();
+        let mut state: Option<State> = None;
+        for (index, &pitch) in pitches.iter().enumerate() {
+            let (bits, _) = self.judge(&slots, index, state, pitch);
+            for rule in Rule::ALL {
+                if bits & rule.bit() != 0 {
+                    let bars = broken.entry(rule).or_default();
+                    if bars.last() != Some(&(slots[index].bar + 1)) {
+                        bars.push(slots[index].bar + 1);
+                    }
+                }
+            }
+            state = Some(Self::advance(&slots[index], state, pitch));
+        }
+
+        let score = Self::score(analysis, &cantus, &slots, &pitches)?;
+        Ok(Counterpoint {
+            score,
+            species: self.species,
+            below: self.below,
+            track,
+            channel,
+            cantus_notes: cantus.len(),
+            notes: pitches.len(),
+            broken: broken.into_iter().collect(),
+        })
+    }
+
+    /// Onsets of the counterpoint over `cantus`. The last bar always holds
+    /// a single note.
+    fn slots(&self, cantus: &[&Note]) -> Vec<Slot> {
+        let last = cantus.len() - 1;
+        let mut slots: Vec<Slot> = Vec::new();
+        let mut previous_downbeat = None;
+
+        for (bar, note) in cantus.iter().enumerate() {
+            let next = cantus.get(bar + 1).filter(|_| bar + 1 < last);
+
+            if self.species == Species::Fourth && bar < last {
+                let start = note.start + note.duration / 2;
+                let end = next.map_or(note.end(), |next| next.start + next.duration / 2);
+                slots.push(Slot {
+                    start,
+                    duration: end.saturating_sub(start).max(1),
+                    bar,
+                    cantus: note.pitch,
+                    consonant: true,
+                    downbeat: false,
+                    tied: next.map(|next| next.pitch),
+                    previous_downbeat,
+                });
+                continue;
+            }
+
+            let parts: &[u64] = match self.species {
+                _ if bar == last => &[4],
+                Species::First | Species::Fourth => &[4],
+                Species::Second => &[2, 2],
+                Species::Third => &[1, 1, 1, 1],
+                Species::Fifth => FLORID_BARS[bar % FLORID_BARS.len()],
+            };
+
+            let mut offset = 0;
+            for (index, &part) in parts.iter().enumerate() {
+                let start = note.start + note.duration * offset / 4;
+                let end = note.start + note.duration * (offset + part) / 4;
+                slots.push(Slot {
+                    start,
+                    duration: end.saturating_sub(start).max(1),
+                    bar,
+                    cantus: note.pitch,
+                    consonant: index == 0,
+                    downbeat: index == 0,
+                    tied: None,
+                    previous_downbeat,
+                });
+                if index == 0 {
+                    previous_downbeat = Some(slots.len() - 1);
+                }
+                offset += part;
+            }
+        }
+
+        slots
+    }
+
+    /// Pitches of the natural scale of the local key, on the chosen side of
+    /// the cantus and a little past it, so crossings stay possible once the
+    /// range is given up. The leading tone is raised in the bar before the
+    /// last, as musica ficta would.
+    fn candidates(&self, slot: &Slot, analysis: &Analysis, cadence: bool) -> Vec<u8> {
+        let key = analysis.key.key_at(slot.start);
+        let scale = key.scale();
+        let leading_tone = (key.tonic + 11) % 12;
+        let cantus = i16::from(slot.cantus);
+        let (low, high) = if self.below {
+            (cantus - SEARCH_SPAN, cantus + 7)
+        } else {
+            (cantus - 7, cantus + SEARCH_SPAN)
+        };
+
+        (low.max(0)..=high.min(127))
+            .map(|pitch| pitch as u8)
+            .filter(|&pitch| {
+                let pitch_class = pitch % 12;
+                if cadence && pitch_class == (leading_tone + 11) % 12 {
+                    return false;
+                }
+                scale.contains(&pitch_class) || (cadence && pitch_class == leading_tone)
+            })
+            .collect()
+    }
+
+    /// Cheapest line breaking none of the `enforced` rules.
+    fn solve(
+        &self,
+        slots: &[Slot],
+        candidates: &[Vec<u8>],
+        jitter: &[Vec<f64>],
+        enforced: u8,
+    ) -> Option<Vec<u8>> {
+        let mut layers: Vec<BTreeMap<State, (f64, Option<State>)>> =
+            Vec::with_capacity(slots.len());
+
+        for (index, slot) in slots.iter().enumerate() {
+            let mut layer: BTreeMap<State, (f64, Option<State>)> = BTreeMap::new();
+            let previous: Vec<(Option<State>, f64)> = match layers.last() {
+                Some(layer) => layer
+                    .iter()
+                    .map(|(&state, &(cost, _))| (Some(state), cost))
+                    .collect(),
+                None => vec![(None, 0.0)],
+            };
+
+            for (&pitch, &jitter) in candidates[index].iter().zip(&jitter[index]) {
+                for &(state, cost) in &previous {
+                    let (broken, step) = self.judge(slots, index, state, pitch);
+                    if broken & enforced != 0 {
+                        continue;
+                    }
+
+                    let next = Self::advance(slot, state, pitch);
+                    let total = cost + step + jitter;
+                    match layer.get(&next) {
+                        Some(&(best, _)) if best <= total => {}
+                        _ => {
+                            layer.insert(next, (total, state));
+                        }
+                    }
+                }
+            }
+
+            if layer.is_empty() {
+                return None;
+            }
+            layers.push(layer);
+        }
+
+        let (mut state, _) = layers
+            .last()?
+            .iter()
+            .min_by(|a, b| a.1.0.total_cmp(&b.1.0))
+            .map(|(&state, &(cost, _))| (Some(state), cost))?;
+
+        let mut pitches = Vec::with_capacity(slots.len());
+        for layer in layers.iter().rev() {
+            let current = state?;
+            pitches.push(current.1);
+            state = layer.get(&current)?.1;
+        }
+        pitches.reverse();
+        Some(pitches)
+    }
+
+    fn advance(slot: &Slot, state: Option<State>, pitch: u8) -> State {
+        let downbeat = if slot.downbeat {
+            Some(pitch)
+        } else {
+            state.and_then(|state| state.2)
+        };
+        (state.map(|state| state.1), pitch, downbeat)
+    }
+
+    /// Rules broken by playing `pitch` at slot `index` after `state`, and
+    /// the cost of doing so.
+    fn judge(&self, slots: &[Slot], index: usize, state: Option<State>, pitch: u8) -> (u8, f64) {
+        let slot = &slots[index];
+        let last = index + 1 == slots.len();
+        let interval = i16::from(pitch) - i16::from(slot.cantus);
+        let distance = interval.unsigned_abs() as u8;
+
+        let mut broken = 0;
+        let mut cost = 0.0;
+
+        let crossed = if self.below {
+            interval > 0
+        } else {
+            interval < 0
+        };
+        if crossed || distance > MAX_DISTANCE {
+            broken |= Rule::Range.bit();
+        }
+        if slot.consonant && !is_consonant(distance) {
+            broken |= Rule::Consonance.bit();
+        }
+        let opening = distance.is_multiple_of(12) || (!self.below && distance % 12 == 7);
+        if (index == 0 && !opening) || (last && !distance.is_multiple_of(12)) {
+            broken |= Rule::Cadence.bit();
+        }
+        if !last && index > 0 && slot.downbeat && is_perfect(distance) {
+            cost += if distance == 0 { 2.0 } else { 1.0 };
+        }
+
+        let Some((before, previous, downbeat)) = state else {
+            return (broken, cost);
+        };
+        let previous_slot = &slots[index - 1];
+        let motion = i16::from(pitch) - i16::from(previous);
+        let leap = motion.unsigned_abs() as u8;
+        let cantus_motion = i16::from(slot.cantus) - i16::from(previous_slot.cantus);
+
+        if last && !(1..=2).contains(&leap) {
+            broken |= Rule::Cadence.bit();
+        }
+        if leap > MAX_LEAP || matches!(leap, 6 | 9 | 10 | 11) {
+            broken |= Rule::Leaps.bit();
+        }
+
+        let previous_interval = i16::from(previous) - i16::from(previous_slot.cantus);
+        if is_parallel(previous_interval, interval, motion, cantus_motion) {
+            broken |= Rule::Parallels.bit();
+        }
+        if slot.downbeat
+            && let (Some(downbeat), Some(downbeat_index)) = (downbeat, slot.previous_downbeat)
+            && downbeat_index + 1 != index
+        {
+            let downbeat_slot = &slots[downbeat_index];
+            let earlier = i16::from(downbeat) - i16::from(downbeat_slot.cantus);
+            let motion = i16::from(pitch) - i16::from(downbeat);
+            let cantus_motion = i16::from(slot.cantus) - i16::from(downbeat_slot.cantus);
+            if is_parallel(earlier, interval, motion, cantus_motion) {
+                broken |= Rule::Parallels.bit();
+            }
+        }
+
+        let similar = motion != 0 && motion.signum() == cantus_motion.signum();
+        if similar && is_perfect(distance) && leap > 2 {
+            broken |= Rule::HiddenPerfects.bit();
+        }
+
+        let previous_distance = previous_interval.unsigned_abs() as u8;
+        if !previous_slot.consonant && !is_consonant(previous_distance) {
+            let resolved = before.is_some_and(|before| {
+                let approach = i16::from(previous) - i16::from(before);
+                let step_in = (1..=2).contains(&approach.abs());
+                let step_out = (1..=2).contains(&leap);
+                let passing = step_in && step_out && approach.signum() == motion.signum();
+                let neighbour = step_in && step_out && approach.signum() == -motion.signum();
+                passing || (neighbour && self.species != Species::Second)
+            });
+            if !resolved {
+                broken |= Rule::Dissonance.bit();
+            }
+        }
+        if let Some(tied) = previous_slot.tied {
+            let suspension = (i16::from(previous) - i16::from(tied)).unsigned_abs() as u8;
+            if !is_consonant(suspension) && !(-2..=-1).contains(&motion) {
+                broken |= Rule::Dissonance.bit();
+            }
+        }
+
+        cost += match leap {
+            0 if self.species == Species::First => 2.0,
+            0 => 4.0,
+            1 | 2 => 0.0,
+            3 | 4 => 1.0,
+            5 | 7 => 2.0,
+            _ => 3.0,
+        };
+        if similar {
+            cost += 1.0;
+        }
+        if let Some(before) = before {
+            let approach = i16::from(previous) - i16::from(before);
+            let recovered = (1..=2).contains(&leap) && motion.signum() == -approach.signum();
+            if approach.abs() > 4 && !recovered {
+                cost += 2.0;
+            }
+        }
+
+        (broken, cost)
+    }
+
+    /// Input with only the cantus left, and the counterpoint on a new track
+    /// and the first channel the input leaves free.
+    fn score(
+        analysis: &Analysis,
+        cantus: &[&Note],
+        slots: &[Slot],
+        pitches: &[u8],
+    ) -> Result<Score> {
+        let mut score = analysis.score.clone();
+        score.notes = cantus.iter().map(|&&note| note).collect();
+
+        let counterpoint_channel = score
+            .free_channel()
+            .ok_or_else(|| Error::NoFreeChannel("the counterpoint".to_string()))?;
+        let counterpoint_track = score.track_count;
+        score.track_count += 1;
+        if score.header.format == Format::SingleTrack {
+            score.header.format = Format::Parallel;
+        }
+
+        let end = slots.last().map_or(0, |slot| slot.start + slot.duration);
+        let event = |tick: u64, kind| ScoreEvent::new(tick, counterpoint_track, kind);
+        score.events.extend([
+            event(0, TrackEventKind::Meta(MetaMessage::TrackName(TRACK_NAME))),
+            event(
+                0,
+                TrackEventKind::Midi {
+                    channel: u4::new(counterpoint_channel),
+                    message: MidiMessage::ProgramChange {
+                        program: u7::new(0),
+                    },
+                },
+            ),
+            event(end, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ]);
+
+        let velocity = cantus
+            .iter()
+            .map(|note| u32::from(note.velocity))
+            .sum::<u32>()
+            / cantus.len() as u32;
+        score
+            .notes
+            .extend(slots.iter().zip(pitches).map(|(slot, &pitch)| Note {
+                start: slot.start,
+                duration: slot.duration,
+                pitch,
+                velocity: velocity.clamp(1, 127) as u8,
+                release: Some(64),
+                channel: counterpoint_channel,
+                track: counterpoint_track,
+            }));
+
+        score.sort_notes();
+        Ok(score)
+    }
+}
+
+/// Unisons, thirds, perfect fifths, sixths and their compounds. Fourths
+/// count as dissonant between two voices.
+fn is_consonant(distance: u8) -> bool {
+    matches!(distance % 12, 0 | 3 | 4 | 7 | 8 | 9)
+}
+
+fn is_perfect(distance: u8) -> bool {
+    matches!(distance % 12, 0 | 7)
+}
+
+/// Whether moving from `from` to `to`, signed intervals above the cantus,
+/// makes parallel fifths or octaves.
+fn is_parallel(from: i16, to: i16, motion: i16, cantus_motion: i16) -> bool {
+    let (from, to) = (from.unsigned_abs() as u8, to.unsigned_abs() as u8);
+    is_perfect(to)
+        && from % 12 == to % 12
+        && motion != 0
+        && motion.signum() == cantus_motion.signum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ScoreEvent, TempoMap};
+    use midly::{Header, MetaMessage, Timing, num::u15};
+
+    const WHOLE: u64 = 4 * 480;
+
+    /// Fux's cantus firmus in D, from the first exercise of the Gradus.
+    const CANTUS: [u8; 11] = [62, 65, 64, 62, 67, 65, 69, 67, 65, 64, 62];
+    /// Fux's own first species counterpoint above it.
+    const FUX_ABOVE: [u8; 11] = [69, 69, 67, 69, 71, 72, 72, 71, 74, 73, 74];
+
+    fn analysis() -> Analysis {
+        let header = Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480)));
+        let mut score = Score::new(header, 1);
+        score.notes = CANTUS
+            .iter()
+            .enumerate()
+            .map(|(bar, &pitch)| Note {
+                start: bar as u64 * WHOLE,
+                duration: WHOLE,
+                pitch,
+                velocity: 80,
+                release: None,
+                channel: 0,
+                track: 0,
+            })
+            .collect();
+        score.events.push(ScoreEvent::new(
+            0,
+            0,
+            TrackEventKind::Meta(MetaMessage::KeySignature(-1, true)),
+        ));
+
+        let tempo_map = TempoMap::new(&score.to_smf());
+        Analysis::from_score(score, tempo_map)
+    }
+
+    /// Rules `pitches` break over the cantus, and the bars breaking them.
+    fn judge(generator: &CounterpointGenerator, pitches: &[u8]) -> Vec<(Rule, usize)> {
+        let analysis = analysis();
+        let cantus: Vec<&Note> = analysis.score.notes.iter().collect();
+        let slots = generator.slots(&cantus);
+
+        let mut broken = Vec::new();
+        let mut state = None;
+        for (index, &pitch) in pitches.iter().enumerate() {
+            let (bits, _) = generator.judge(&slots, index, state, pitch);
+            broken.extend(
+                Rule::ALL
+                    .into_iter()
+                    .filter(|rule| bits & rule.bit() != 0)
+                    .map(|rule| (rule, slots[index].bar + 1)),
+            );
+            state = Some(CounterpointGenerator::advance(&slots[index], state, pitch));
+        }
+        broken
+    }
+
+    #[test]
+    fn counts_thirds_sixths_and_perfect_intervals_as_consonant() {
+        for distance in [0, 3, 4, 7, 8, 9, 12, 15, 19] {
+            assert!(is_consonant(distance), "{distance}");
+        }
+        for distance in [1, 2, 5, 6, 10, 11, 13, 17] {
+            assert!(!is_consonant(distance), "{distance}");
+        }
+    }
+
+    #[test]
+    fn finds_parallel_fifths_and_octaves() {
+        assert!(is_parallel(7, 7, 2, 2));
+        assert!(is_parallel(7, 19, 2, 1));
+        assert!(is_parallel(12, 0, -2, -2));
+        assert!(is_parallel(-12, -12, -1, -3));
+        assert!(!is_parallel(7, 7, 2, -2));
+        assert!(!is_parallel(7, 7, 0, 0));
+        assert!(!is_parallel(3, 7, 2, 2));
+        assert!(!is_parallel(7, 12, 2, 2));
+    }
+
+    #[test]
+    fn accepts_the_counterpoint_of_fux() {
+        let generator = CounterpointGenerator::new(Species::First, false);
+
+        assert_eq!(judge(&generator, &FUX_ABOVE), []);
+    }
+
+    #[test]
+    fn reports_the_rules_a_line_breaks() {
+        let generator = CounterpointGenerator::new(Species::First, false);
+        let mut pitches = FUX_ABOVE;
+        pitches[1] = 72;
+        pitches[6] = 71;
+
+        assert_eq!(
+            judge(&generator, &pitches),
+            [
+                (Rule::HiddenPerfects, 2),
+                (Rule::Parallels, 2),
+                (Rule::Consonance, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn solves_every_species_without_breaking_a_rule() {
+        let analysis = analysis();
+        for species in Species::value_variants() {
+            for below in [false, true] {
+                let generator = CounterpointGenerator::new(*species, below);
+                let counterpoint = generator.generate(&analysis, None, None, None).unwrap();
+
+                assert_eq!(counterpoint.cantus_notes, CANTUS.len());
+                assert!(
+                    counterpoint.broken.is_empty(),
+                    "{species} below {below}: {:?}",
+                    counterpoint.broken
+                );
+            }
+        }
+    }
+}
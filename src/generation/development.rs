@@ -0,0 +1,251 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::Line;
+use crate::{Analysis, Error, Motif, Result, STEPS_PER_QUARTER, Score};
+use clap::ValueEnum;
+use rand::{Rng, seq::IndexedRandom};
+
+/// Motifs developed when none are selected, the most salient first.
+const DEFAULT_MOTIFS: usize = 3;
+/// Widest transposition applied to a restatement, in semitones.
+const MAX_TRANSPOSITION: i16 = 5;
+
+/// Where the melodies of a generated piece come from.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MelodyMode {
+    /// Intervals drawn from a Markov chain, note after note
+    #[default]
+    Markov,
+    /// Motifs of the input, restated and developed
+    Motifs,
+}
+
+/// Classical techniques a motif is restated with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Development {
+    Statement,
+    Transposition,
+    Inversion,
+    Retrograde,
+    Augmentation,
+    Diminution,
+    Fragmentation,
+}
+
+impl Development {
+    const VARIED: [Self; 6] = [
+        Self::Transposition,
+        Self::Inversion,
+        Self::Retrograde,
+        Self::Augmentation,
+        Self::Diminution,
+        Self::Fragmentation,
+    ];
+
+    /// Intervals and note lengths of the developed motif.
+    fn apply<R: Rng>(self, motif: &Motif, rng: &mut R) -> (Vec<i16>, Vec<u32>) {
+        let intervals = motif.intervals.clone();
+        let rhythm = motif.rhythm.clone();
+
+        match self {
+            Self::Statement | Self::Transposition => (intervals, rhythm),
+            Self::Inversion => (intervals.iter().map(|interval| -interval).collect(), rhythm),
+            Self::Retrograde => (
+                intervals.iter().rev().map(|interval| -interval).collect(),
+                rhythm.into_iter().rev().collect(),
+            ),
+            Self::Augmentation => (intervals, rhythm.iter().map(|steps| steps * 2).collect()),
+            Self::Diminution => (
+                intervals,
+                rhythm.iter().map(|steps| (steps / 2).max(1)).collect(),
+            ),
+            Self::Fragmentation => {
+                let length = (rhythm.len() / 2).max(2);
+                let fragment = &intervals[..length - 1];
+                let step = if rng.random_bool(0.5) { 2 } else { -2 };
+
+                let mut intervals = fragment.to_vec();
+                intervals.push(step - fragment.iter().sum::<i16>());
+                intervals.extend_from_slice(fragment);
+                (intervals, rhythm[..length].repeat(2))
+            }
+        }
+    }
+}
+
+/// Builds melodies out of the motifs found in the input, restating them
+/// transposed, inverted, reversed, stretched, compressed or cut down.
+#[derive(Debug, Clone)]
+pub struct MotifDeveloper {
+    motifs: Vec<Motif>,
+}
+
+impl MotifDeveloper {
+    /// Keeps the motifs numbered in `selection`, counted from one in the
+    /// order `compose analyze` lists them, or the most salient ones when
+    /// the selection is empty. Numbers past the last motif are an error.
+    pub fn new(analysis: &Analysis, selection: &[usize]) -> Result<Self> {
+        let motifs = if selection.is_empty() {
+            analysis
+                .motifs
+                .iter()
+                .take(DEFAULT_MOTIFS)
+                .cloned()
+                .collect()
+        } else {
+            selection
+                .iter()
+                .map(|&number| {
+                    number
+                        .checked_sub(1)
+                        .and_then(|index| analysis.motifs.get(index))
+                        .cloned()
+                        .ok_or(Error::UnknownMotif(number, analysis.motifs.len()))
+                })
+                .collect::<Result<_>>()?
+        };
+
+        Ok(Self { motifs })
+    }
+
+    /// Replaces the notes of every part owning a selected motif by `bars`
+    /// bars of developments of its motifs.
+    pub fn develop<R: Rng>(&self, analysis: &Analysis, bars: u32, rng: &mut R, score: &mut Score) {
+        let tempo_map = &analysis.tempo_map;
+        let grid = (tempo_map.ticks_per_quarter(0) / STEPS_PER_QUARTER).max(1.0);
+        let end = tempo_map.bar_start(bars.max(1));
+
+        for ((track, channel), melody) in analysis.score.melodies(grid) {
+            let motifs: Vec<&Motif> = self
+                .motifs
+                .iter()
+                .filter(|motif| motif.track == track && motif.channel == channel)
+                .collect();
+            let (Some(first), Some(line)) = (motifs.first(), Line::new(track, channel, &melody))
+            else {
+                continue;
+            };
+
+            score
+                .notes
+                .retain(|note| note.track != track || note.channel != channel);
+
+            let mut quarters = 0.0;
+            let mut pitch = first.occurrences[0].pitch;
+            let mut development = Development::Statement;
+            let mut motif = *first;
+
+            loop {
+                let offset = Self::beat_offset(analysis, motif.occurrences[0].start);
+                let beat = tempo_map
+                    .time_signature(tempo_map.tick_at_quarters(quarters))
+                    .beat_quarters();
+                quarters = ((quarters - offset) / beat).ceil().max(0.0) * beat + offset;
+
+                let start = tempo_map.tick_at_quarters(quarters);
+                if start >= end {
+                    break;
+                }
+
+                let (intervals, rhythm) = development.apply(motif, rng);
+                let shift = match development {
+                    Development::Statement => 0,
+                    Development::Transposition => {
+                        let shift = rng.random_range(1..=MAX_TRANSPOSITION);
+                        if rng.random_bool(0.5) { shift } else { -shift }
+                    }
+                    _ => rng.random_range(-2..=2),
+                };
+                pitch = line.fit(pitch, shift, analysis, start);
+
+                for (index, steps) in rhythm.iter().enumerate() {
+                    let tick = tempo_map.tick_at_quarters(quarters);
+                    if tick >= end {
+                        break;
+                    }
+                    if index > 0 {
+                        pitch = line.fit(pitch, intervals[index - 1], analysis, tick);
+                    }
+
+                    quarters += f64::from(*steps) / STEPS_PER_QUARTER;
+                    let next = tempo_map.tick_at_quarters(quarters).min(end);
+                    score
+                        .notes
+                        .push(line.note(tick, next.saturating_sub(tick).max(1), pitch));
+                }
+
+                motif = motifs.choose(rng).copied().unwrap_or(first);
+                development = *Development::VARIED
+                    .choose(rng)
+                    .unwrap_or(&Development::Statement);
+            }
+        }
+
+        score.sort_notes();
+    }
+
+    /// Distance from the last beat to `tick`, in quarter notes.
+    fn beat_offset(analysis: &Analysis, tick: u64) -> f64 {
+        let tempo_map = &analysis.tempo_map;
+        let position = tempo_map.position(tick);
+        position.beat.fract() * tempo_map.time_signature(tick).beat_quarters()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::Smf;
+
+    static PRELUDE: &[u8] = include_bytes!("../../assets/midi/prelude1.mid");
+
+    fn analysis() -> Analysis {
+        Analysis::new(&Smf::parse(PRELUDE).unwrap())
+    }
+
+    #[test]
+    fn keeps_the_selected_motifs_in_order() {
+        let analysis = analysis();
+        let count = analysis.motifs.len();
+        assert!(count > DEFAULT_MOTIFS);
+
+        let developer = MotifDeveloper::new(&analysis, &[]).unwrap();
+        assert_eq!(developer.motifs, analysis.motifs[..DEFAULT_MOTIFS]);
+
+        let developer = MotifDeveloper::new(&analysis, &[count, 1]).unwrap();
+        assert_eq!(
+            developer.motifs,
+            [
+                analysis.motifs[count - 1].clone(),
+                analysis.motifs[0].clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_motifs_past_the_end_of_the_list() {
+        let analysis = analysis();
+        let count = analysis.motifs.len();
+
+        for selection in [&[count + 1][..], &[1, count + 1], &[0]] {
+            assert!(matches!(
+                MotifDeveloper::new(&analysis, selection),
+                Err(Error::UnknownMotif(number, found)) if number == selection[selection.len() - 1] && found == count
+            ));
+        }
+    }
+}
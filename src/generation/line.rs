@@ -0,0 +1,119 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Analysis, Error, Note, Result, STEPS_PER_QUARTER};
+use serde::{Deserialize, Serialize};
+
+/// Register and dynamics of the melodic line of one track and channel, used
+/// to keep new notes sounding like that part.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Line {
+    pub track: usize,
+    pub channel: u8,
+    pub percussion: bool,
+    pub first_pitch: u8,
+    pub lowest: u8,
+    pub highest: u8,
+    pub velocity: u8,
+}
+
+impl Line {
+    pub fn new(track: usize, channel: u8, melody: &[&Note]) -> Option<Self> {
+        let first = melody.first()?;
+        let velocity = melody
+            .iter()
+            .map(|note| u32::from(note.velocity))
+            .sum::<u32>()
+            / melody.len() as u32;
+
+        Some(Self {
+            track,
+            channel,
+            percussion: first.is_percussion(),
+            first_pitch: first.pitch,
+            lowest: melody.iter().map(|note| note.pitch).min()?,
+            highest: melody.iter().map(|note| note.pitch).max()?,
+            velocity: (velocity as u8).max(1),
+        })
+    }
+
+    /// Melody of `track` and `channel`, or of the first pitched part of that
+    /// track, or of the input, with the part it was found on.
+    pub fn melody(
+        analysis: &Analysis,
+        track: Option<usize>,
+        channel: Option<u8>,
+    ) -> Result<((usize, u8), Vec<&Note>)> {
+        let grid = (analysis.tempo_map.ticks_per_quarter(0) / STEPS_PER_QUARTER).max(1.0);
+        analysis
+            .score
+            .melodies(grid)
+            .into_iter()
+            .find(|((part_track, part_channel), melody)| {
+                track.is_none_or(|track| track == *part_track)
+                    && channel.is_none_or(|channel| channel == *part_channel)
+                    && melody.iter().any(|note| !note.is_percussion())
+            })
+            .ok_or_else(|| {
+                let part = match (track, channel) {
+                    (Some(track), Some(channel)) => {
+                        format!("track {track}, channel {}", channel + 1)
+                    }
+                    (Some(track), None) => format!("track {track}"),
+                    (None, Some(channel)) => format!("channel {}", channel + 1),
+                    (None, None) => "the input".to_string(),
+                };
+                Error::NoMelody(format!("no pitched notes on {part}"))
+            })
+    }
+
+    /// Applies `interval` to `pitch`, then folds the result back into the
+    /// range of the line and, for pitched parts, into the key at `tick`.
+    pub fn fit(&self, pitch: u8, interval: i16, analysis: &Analysis, tick: u64) -> u8 {
+        let mut candidate = (i16::from(pitch) + interval).clamp(0, 127);
+        let (lowest, highest) = (i16::from(self.lowest), i16::from(self.highest));
+
+        if highest - lowest >= 12 {
+            while candidate > highest {
+                candidate -= 12;
+            }
+            while candidate < lowest {
+                candidate += 12;
+            }
+        } else {
+            candidate = candidate.clamp(lowest, highest);
+        }
+
+        let candidate = candidate as u8;
+        if self.percussion {
+            return candidate;
+        }
+
+        analysis.key.key_at(tick).snap(candidate, interval >= 0)
+    }
+
+    pub fn note(&self, start: u64, duration: u64, pitch: u8) -> Note {
+        Note {
+            start,
+            duration,
+            pitch,
+            velocity: self.velocity,
+            release: Some(64),
+            channel: self.channel,
+            track: self.track,
+        }
+    }
+}
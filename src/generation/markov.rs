@@ -0,0 +1,166 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{Line, MarkovChain, RhythmGenerator};
+use crate::{Analysis, Corpus, Note, STEPS_PER_QUARTER, Score, ScoreEvent};
+use midly::{MetaMessage, TrackEventKind};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Interval chain learned from the melodic line of one track and channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LineModel {
+    line: Line,
+    /// Intervals between successive notes, in semitones.
+    chain: MarkovChain<i16>,
+}
+
+/// Melody generator trained on the pitch intervals of every track of a
+/// corpus. Rhythms come from a separate [`RhythmGenerator`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarkovGenerator {
+    lines: Vec<LineModel>,
+}
+
+impl MarkovGenerator {
+    /// Learns one chain per track and channel of the reference, from the
+    /// matching part of every piece of the corpus, each piece weighing its
+    /// share whatever its length. When a track plays chords, only the
+    /// highest note of each onset is kept as its melody.
+    pub fn train(corpus: &Corpus, order: usize) -> Self {
+        let melodies: Vec<_> = corpus
+            .pieces
+            .iter()
+            .map(|piece| Self::melodies(&piece.analysis))
+            .collect();
+        let lines = melodies[0]
+            .iter()
+            .filter_map(|(&(track, channel), melody)| {
+                let line = Line::new(track, channel, melody)?;
+
+                let mut chain = MarkovChain::new(order.max(1));
+                for (index, part) in corpus.parts(track, channel) {
+                    let Some(melody) = melodies[index].get(&part) else {
+                        continue;
+                    };
+
+                    let intervals: Vec<i16> = melody
+                        .windows(2)
+                        .map(|pair| i16::from(pair[1].pitch) - i16::from(pair[0].pitch))
+                        .collect();
+                    if !intervals.is_empty() {
+                        let weight = corpus.pieces[index].weight;
+                        chain.train(&intervals, weight / intervals.len() as f64);
+                    }
+                }
+
+                Some(LineModel { line, chain })
+            })
+            .collect();
+
+        Self { lines }
+    }
+
+    /// Whether every chain decoded from a model file is usable, on a part
+    /// of a reference with `track_count` tracks.
+    pub(super) fn is_valid(&self, track_count: usize) -> bool {
+        self.lines.iter().all(|model| {
+            model.line.track < track_count && model.line.channel <= 15 && model.chain.is_valid()
+        })
+    }
+
+    fn melodies(analysis: &Analysis) -> BTreeMap<(usize, u8), Vec<&Note>> {
+        let grid = (analysis.tempo_map.ticks_per_quarter(0) / STEPS_PER_QUARTER).max(1.0);
+        analysis.score.melodies(grid)
+    }
+
+    /// Generates `bars` bars of new melodies on the tracks, instruments,
+    /// tempo and meter of the analyzed score, on the rhythms drawn from
+    /// `rhythm`, keeping every pitch inside the local key.
+    pub fn generate<R: Rng>(
+        &self,
+        analysis: &Analysis,
+        rhythm: &RhythmGenerator,
+        bars: u32,
+        rng: &mut R,
+    ) -> Score {
+        let source = &analysis.score;
+        let end = analysis.tempo_map.bar_start(bars.max(1));
+
+        let mut score = Score::new(source.header, source.track_count);
+        score.events = source
+            .events
+            .iter()
+            .filter(|event| is_carried_over(event, end))
+            .cloned()
+            .collect();
+        for track in 0..source.track_count {
+            score.events.push(ScoreEvent::new(
+                end,
+                track,
+                TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            ));
+        }
+
+        for model in &self.lines {
+            let line = &model.line;
+            let onsets = rhythm.generate(line.track, line.channel, &analysis.tempo_map, bars, rng);
+            Self::generate_line(model, analysis, &onsets, rng, &mut score.notes);
+        }
+
+        score.sort_notes();
+        score
+    }
+
+    fn generate_line<R: Rng>(
+        model: &LineModel,
+        analysis: &Analysis,
+        onsets: &[(u64, u64)],
+        rng: &mut R,
+        notes: &mut Vec<Note>,
+    ) {
+        let order = model.chain.order();
+        let mut history: Vec<i16> = Vec::with_capacity(order + 1);
+        let mut pitch = model.line.first_pitch;
+
+        for (index, &(start, duration)) in onsets.iter().enumerate() {
+            if index > 0 {
+                let interval = model.chain.sample(&history, rng).unwrap_or(0);
+                pitch = model.line.fit(pitch, interval, analysis, start);
+
+                history.push(interval);
+                if history.len() > order {
+                    history.remove(0);
+                }
+            }
+
+            notes.push(model.line.note(start, duration, pitch));
+        }
+    }
+}
+
+/// Keeps the setup of each track and the tempo, meter and key changes that
+/// happen before the end of the generated piece.
+fn is_carried_over(event: &ScoreEvent, end: u64) -> bool {
+    match event.kind() {
+        TrackEventKind::Meta(MetaMessage::EndOfTrack) => false,
+        TrackEventKind::Meta(
+            MetaMessage::Tempo(_) | MetaMessage::TimeSignature(..) | MetaMessage::KeySignature(..),
+        ) => event.tick < end,
+        _ => event.tick == 0,
+    }
+}
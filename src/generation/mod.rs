@@ -0,0 +1,41 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod accompaniment;
+mod chain;
+mod chorale;
+mod counterpoint;
+mod development;
+mod line;
+mod markov;
+mod model;
+mod progression;
+mod rhythm;
+mod structure;
+mod transform;
+
+pub use accompaniment::{Accompaniment, Voicing};
+pub use chain::{MAX_ORDER, MarkovChain};
+pub use chorale::Harmonizer;
+pub use counterpoint::{CounterpointGenerator, Species};
+pub use development::{MelodyMode, MotifDeveloper};
+pub use line::Line;
+pub use markov::MarkovGenerator;
+pub use model::Model;
+pub use progression::ProgressionGenerator;
+pub use rhythm::RhythmGenerator;
+pub use structure::{FormSpec, Structure};
+pub use transform::{Transform, Transformer};
@@ -0,0 +1,292 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{MarkovGenerator, ProgressionGenerator, RhythmGenerator};
+use crate::{Analysis, Corpus, Error, Reader, Result, Writer};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, sync::OnceLock};
+
+/// Bytes opening every model file.
+const MAGIC: &[u8; 4] = b"ACM\0";
+/// Layout of the models written by this build. Bump it whenever a trained
+/// structure changes shape.
+const VERSION: u16 = 1;
+/// Magic, version and checksum.
+const HEADER_LEN: usize = MAGIC.len() + 2 + 8;
+
+/// Everything learned from a corpus, enough to compose without reading it
+/// again.
+///
+/// Model files start with the `ACM\0` magic, a little-endian version and an
+/// FNV-1a checksum of the payload, followed by the payload itself encoded
+/// with bincode. The reference piece is stored as a Standard MIDI File, so
+/// its tracks, tempo, meter and keys frame new pieces as they would when
+/// training on the fly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Model {
+    reference: Vec<u8>,
+    /// The reference parsed and analyzed, once per model.
+    #[serde(skip)]
+    analysis: OnceLock<Analysis>,
+    pub melody: MarkovGenerator,
+    pub rhythm: RhythmGenerator,
+    pub harmony: ProgressionGenerator,
+}
+
+impl Model {
+    pub fn train(corpus: &Corpus, order: usize) -> Result<Self> {
+        let reference = Writer::new().write(&corpus.reference().score.to_smf())?;
+
+        Ok(Self {
+            reference,
+            analysis: OnceLock::new(),
+            melody: MarkovGenerator::train(corpus, order),
+            rhythm: RhythmGenerator::train(corpus, order),
+            harmony: ProgressionGenerator::train(corpus, order),
+        })
+    }
+
+    /// Analysis of the reference piece, parsed and analyzed on the first
+    /// call only.
+    pub fn reference(&self) -> Result<&Analysis> {
+        if let Some(analysis) = self.analysis.get() {
+            return Ok(analysis);
+        }
+
+        let smf = Reader::parse(&self.reference)?;
+        Ok(self.analysis.get_or_init(|| Analysis::new(&smf)))
+    }
+
+    pub fn read_file(path: &Path) -> Result<Self> {
+        Self::from_bytes(&fs::read(path).map_err(Error::ReadInputFile)?)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let payload = bincode::serde::encode_to_vec(self, bincode::config::standard())
+            .map_err(Error::EncodeModel)?;
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+            return Err(Error::CorruptModel("not a model file".to_string()));
+        }
+
+        let (version, rest) = bytes[MAGIC.len()..].split_at(2);
+        let version = u16::from_le_bytes([version[0], version[1]]);
+        if version != VERSION {
+            return Err(Error::UnsupportedModelVersion(version, VERSION));
+        }
+
+        let (expected, payload) = rest.split_at(8);
+        let expected = u64::from_le_bytes(expected.try_into().unwrap_or_default());
+        if checksum(payload) != expected {
+            return Err(Error::CorruptModel("checksum mismatch".to_string()));
+        }
+
+        let (model, read): (Self, usize) =
+            bincode::serde::decode_from_slice(payload, bincode::config::standard())
+                .map_err(Error::DecodeModel)?;
+        if read != payload.len() {
+            return Err(Error::CorruptModel(format!(
+                "{} unexpected trailing bytes",
+                payload.len() - read
+            )));
+        }
+        let track_count = model
+            .reference()
+            .map_err(|_| Error::CorruptModel("unreadable reference piece".to_string()))?
+            .score
+            .track_count;
+        if !(model.melody.is_valid(track_count)
+            && model.rhythm.is_valid(track_count)
+            && model.harmony.is_valid())
+        {
+            return Err(Error::CorruptModel(
+                "out of range parts, orders or weights".to_string(),
+            ));
+        }
+        Ok(model)
+    }
+}
+
+/// 64-bit FNV-1a hash, catching truncated or altered payloads.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_ORDER, Note, Score, TempoMap};
+    use midly::{Format, Header, Smf, Timing, num::u15};
+    use std::error::Error as _;
+
+    static PRELUDE: &[u8] = include_bytes!("../../assets/midi/prelude1.mid");
+
+    fn model(weight: f64) -> Model {
+        let analysis = Analysis::new(&Smf::parse(PRELUDE).unwrap());
+        Model::train(&Corpus::new(vec![(analysis, weight)]), 2).unwrap()
+    }
+
+    /// `bytes` with its payload replaced by `payload` and a matching
+    /// checksum.
+    fn with_payload(bytes: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes[..HEADER_LEN].to_vec();
+        bytes[MAGIC.len() + 2..].copy_from_slice(&checksum(payload).to_le_bytes());
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn round_trips_a_trained_model() {
+        let bytes = model(1.0).to_bytes().unwrap();
+        let model = Model::from_bytes(&bytes).unwrap();
+
+        assert_eq!(model.to_bytes().unwrap(), bytes);
+        assert!(model.reference().is_ok());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = model(1.0).to_bytes().unwrap();
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+        assert!(matches!(
+            Model::from_bytes(&bytes),
+            Err(Error::UnsupportedModelVersion(found, VERSION)) if found == VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_damaged_payloads() {
+        let bytes = model(1.0).to_bytes().unwrap();
+        let payload = &bytes[HEADER_LEN..];
+
+        let mut flipped = bytes.clone();
+        flipped[HEADER_LEN + payload.len() / 2] ^= 0x01;
+        assert!(matches!(
+            Model::from_bytes(&flipped),
+            Err(Error::CorruptModel(err)) if err == "checksum mismatch"
+        ));
+
+        let trailing = with_payload(&bytes, &[payload, &[0]].concat());
+        assert!(matches!(
+            Model::from_bytes(&trailing),
+            Err(Error::CorruptModel(err)) if err == "1 unexpected trailing bytes"
+        ));
+
+        let truncated = with_payload(&bytes, &payload[..payload.len() / 2]);
+        let err = Model::from_bytes(&truncated).unwrap_err();
+        assert!(matches!(err, Error::DecodeModel(_)));
+        assert!(err.source().is_some());
+
+        assert!(matches!(
+            Model::from_bytes(b"MThd"),
+            Err(Error::CorruptModel(err)) if err == "not a model file"
+        ));
+    }
+
+    #[test]
+    fn rejects_parts_missing_from_the_reference() {
+        let mut model = model(1.0);
+        let header = Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480)));
+        model.reference = Writer::new()
+            .write(&Score::new(header, 1).to_smf())
+            .unwrap();
+        let bytes = model.to_bytes().unwrap();
+
+        assert!(matches!(
+            Model::from_bytes(&bytes),
+            Err(Error::CorruptModel(err)) if err == "out of range parts, orders or weights"
+        ));
+    }
+
+    #[test]
+    fn rejects_channels_above_fifteen() {
+        let header = Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480)));
+        let tempo_map = TempoMap::new(&Score::new(header, 1).to_smf());
+        let mut score = Score::new(header, 1);
+        score.notes = [60, 62, 64, 65]
+            .into_iter()
+            .enumerate()
+            .map(|(index, pitch)| Note {
+                start: index as u64 * 480,
+                duration: 480,
+                pitch,
+                velocity: 80,
+                release: None,
+                channel: 16,
+                track: 0,
+            })
+            .collect();
+        let analysis = Analysis::from_score(score, tempo_map);
+        let bytes = Model::train(&Corpus::new(vec![(analysis, 1.0)]), 2)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        assert!(matches!(
+            Model::from_bytes(&bytes),
+            Err(Error::CorruptModel(err)) if err == "out of range parts, orders or weights"
+        ));
+    }
+
+    #[test]
+    fn rejects_orders_above_the_maximum() {
+        let analysis = Analysis::new(&Smf::parse(PRELUDE).unwrap());
+        let corpus = Corpus::new(vec![(analysis, 1.0)]);
+        let bytes = Model::train(&corpus, MAX_ORDER + 1)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        assert!(matches!(
+            Model::from_bytes(&bytes),
+            Err(Error::CorruptModel(err)) if err == "out of range parts, orders or weights"
+        ));
+    }
+
+    #[test]
+    fn rejects_unreadable_references() {
+        let mut model = model(1.0);
+        model.reference = b"MThd".to_vec();
+        let bytes = model.to_bytes().unwrap();
+
+        assert!(matches!(
+            Model::from_bytes(&bytes),
+            Err(Error::CorruptModel(err)) if err == "unreadable reference piece"
+        ));
+    }
+
+    #[test]
+    fn rejects_non_finite_weights() {
+        let bytes = model(f64::INFINITY).to_bytes().unwrap();
+
+        assert!(matches!(
+            Model::from_bytes(&bytes),
+            Err(Error::CorruptModel(_))
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::MarkovChain;
+use crate::{Analysis, Chord, ChordQuality, ChordSpan, Corpus, Harmony, Key, Mode};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Bars between two cadences.
+const PHRASE_BARS: u32 = 4;
+/// Most chords placed in one bar.
+const MAX_CHORDS_PER_BAR: usize = 2;
+/// Draws allowed to find a chord that fits the local key before falling
+/// back to the tonic.
+const MAX_DRAWS: usize = 8;
+
+/// A chord relative to the key it is heard in, as a Roman numeral would
+/// describe it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+struct Degree {
+    /// Semitones from the tonic to the root.
+    interval: u8,
+    quality: ChordQuality,
+}
+
+impl Degree {
+    fn of(chord: &Chord, key: &Key) -> Self {
+        Self {
+            interval: (chord.root + 12 - key.tonic) % 12,
+            quality: chord.quality,
+        }
+    }
+
+    fn tonic(key: &Key) -> Self {
+        let quality = match key.mode {
+            Mode::Major => ChordQuality::Major,
+            Mode::Minor => ChordQuality::Minor,
+        };
+        Self {
+            interval: 0,
+            quality,
+        }
+    }
+
+    fn dominant() -> Self {
+        Self {
+            interval: 7,
+            quality: ChordQuality::Dominant7,
+        }
+    }
+
+    /// Whether the chord belongs to `key`. Dominant sevenths only need a
+    /// diatonic root, which keeps secondary dominants available.
+    fn fits(&self, key: &Key) -> bool {
+        let chord = self.chord(key);
+        if chord.quality == ChordQuality::Dominant7 {
+            key.contains(chord.root)
+        } else {
+            chord
+                .pitch_classes()
+                .all(|pitch_class| key.contains(pitch_class))
+        }
+    }
+
+    fn chord(&self, key: &Key) -> Chord {
+        let root = (key.tonic + self.interval) % 12;
+        Chord::new(root, self.quality, root)
+    }
+}
+
+/// How a phrase ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cadence {
+    /// Dominant then tonic, closing the phrase.
+    Authentic,
+    /// Stopping on the dominant, leaving the phrase open.
+    Half,
+}
+
+/// Chord-progression generator trained on the Roman numerals of a corpus.
+///
+/// Degrees learned in one mode are replayed in whatever key the piece is in
+/// at that point, so chords foreign to that key are drawn again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressionGenerator {
+    chain: MarkovChain<Degree>,
+    chords_per_bar: usize,
+}
+
+impl ProgressionGenerator {
+    /// Learns the transitions between successive chords of the harmony of
+    /// every piece, each piece weighing its share whatever its length, and
+    /// how many chords a bar usually holds.
+    pub fn train(corpus: &Corpus, order: usize) -> Self {
+        let mut chain = MarkovChain::new(order.max(1));
+        let mut chords_per_bar = 0.0;
+
+        for piece in &corpus.pieces {
+            let analysis = &piece.analysis;
+            let chords = &analysis.harmony.chords;
+            let degrees: Vec<Degree> = chords
+                .iter()
+                .map(|span| Degree::of(&span.chord, &span.key))
+                .collect();
+            if degrees.is_empty() {
+                continue;
+            }
+            chain.train(&degrees, piece.weight / degrees.len() as f64);
+
+            let bars = analysis.tempo_map.bar_count().max(1);
+            chords_per_bar += piece.weight * chords.len() as f64 / f64::from(bars);
+        }
+
+        let chords_per_bar = (chords_per_bar.round() as usize).clamp(1, MAX_CHORDS_PER_BAR);
+
+        Self {
+            chain,
+            chords_per_bar,
+        }
+    }
+
+    /// Whether the chain decoded from a model file is usable.
+    pub(super) fn is_valid(&self) -> bool {
+        self.chain.is_valid() && (1..=MAX_CHORDS_PER_BAR).contains(&self.chords_per_bar)
+    }
+
+    /// Generates a progression of `bars` bars in the keys of the analyzed
+    /// score. Phrases alternate half and authentic cadences, and the last
+    /// bar always closes on the tonic. Nothing is generated when the input
+    /// has no harmony to learn from.
+    pub fn generate<R: Rng>(&self, analysis: &Analysis, bars: u32, rng: &mut R) -> Harmony {
+        let tempo_map = &analysis.tempo_map;
+        let mut harmony = Harmony::default();
+        if self.chain.is_empty() {
+            return harmony;
+        }
+
+        let mut slots: Vec<(u32, u64, u64)> = Vec::new();
+        for bar in 0..bars {
+            let start = tempo_map.bar_start(bar);
+            let end = tempo_map.bar_start(bar + 1);
+            let count = self.chords_per_bar as u64;
+            slots.extend((0..count).map(|slot| {
+                (
+                    bar,
+                    start + (end - start) * slot / count,
+                    start + (end - start) * (slot + 1) / count,
+                )
+            }));
+        }
+
+        let order = self.chain.order();
+        let mut history: Vec<Degree> = Vec::with_capacity(order + 1);
+        for (index, &(bar, start, end)) in slots.iter().enumerate() {
+            let key = analysis.key.key_at(start);
+            let degree = match Self::cadence_at(&slots, index, bars) {
+                Some((Cadence::Authentic, true)) => Degree::tonic(&key),
+                Some(_) => Degree::dominant(),
+                _ if bar == 0 && index == 0 => Degree::tonic(&key),
+                _ => (0..MAX_DRAWS)
+                    .filter_map(|_| self.chain.sample(&history, rng))
+                    .find(|degree| degree.fits(&key))
+                    .unwrap_or_else(|| Degree::tonic(&key)),
+            };
+
+            history.push(degree);
+            if history.len() > order {
+                history.remove(0);
+            }
+
+            let chord = degree.chord(&key);
+            match harmony.chords.last_mut() {
+                Some(last) if last.chord == chord && last.key == key => last.end = end,
+                _ => harmony.chords.push(ChordSpan {
+                    start,
+                    end,
+                    chord,
+                    key,
+                }),
+            }
+        }
+
+        harmony
+    }
+
+    /// Cadence the slot at `index` takes part in, and whether it is the
+    /// final chord of it rather than the one preparing it.
+    fn cadence_at(slots: &[(u32, u64, u64)], index: usize, bars: u32) -> Option<(Cadence, bool)> {
+        let cadence = |bar: u32| {
+            if bar + 1 == bars {
+                Some(Cadence::Authentic)
+            } else if !(bar + 1).is_multiple_of(PHRASE_BARS) {
+                None
+            } else if (bar / PHRASE_BARS).is_multiple_of(2) {
+                Some(Cadence::Half)
+            } else {
+                Some(Cadence::Authentic)
+            }
+        };
+        let is_last_of_bar = |index: usize| {
+            slots
+                .get(index + 1)
+                .is_none_or(|next| next.0 != slots[index].0)
+        };
+
+        if is_last_of_bar(index)
+            && let Some(cadence) = cadence(slots[index].0)
+        {
+            return Some((cadence, true));
+        }
+
+        let next = index + 1;
+        if next < slots.len()
+            && is_last_of_bar(next)
+            && cadence(slots[next].0) == Some(Cadence::Authentic)
+        {
+            return Some((Cadence::Authentic, false));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::Smf;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    static PRELUDE: &[u8] = include_bytes!("../../assets/midi/prelude1.mid");
+
+    /// Bar, start and end of `per_bar` slots in each of `bars` bars of 4.
+    fn slots(bars: u32, per_bar: u64) -> Vec<(u32, u64, u64)> {
+        (0..bars)
+            .flat_map(|bar| {
+                let start = u64::from(bar) * 1920;
+                (0..per_bar).map(move |slot| {
+                    (
+                        bar,
+                        start + 1920 * slot / per_bar,
+                        start + 1920 * (slot + 1) / per_bar,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn places_cadences_at_phrase_ends() {
+        let slots = slots(12, 1);
+        let cadences: Vec<_> = (0..slots.len())
+            .map(|index| ProgressionGenerator::cadence_at(&slots, index, 12))
+            .collect();
+
+        assert_eq!(
+            cadences,
+            [
+                None,
+                None,
+                None,
+                Some((Cadence::Half, true)),
+                None,
+                None,
+                Some((Cadence::Authentic, false)),
+                Some((Cadence::Authentic, true)),
+                None,
+                None,
+                Some((Cadence::Authentic, false)),
+                Some((Cadence::Authentic, true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn places_cadences_on_the_last_slots_of_a_bar() {
+        let slots = slots(4, 2);
+        let cadences: Vec<_> = (0..slots.len())
+            .map(|index| ProgressionGenerator::cadence_at(&slots, index, 4))
+            .collect();
+
+        assert_eq!(
+            cadences,
+            [
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some((Cadence::Authentic, false)),
+                Some((Cadence::Authentic, true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_on_the_tonic_after_the_dominant() {
+        let analysis = Analysis::new(&Smf::parse(PRELUDE).unwrap());
+        let generator = ProgressionGenerator::train(&Corpus::new(vec![(analysis.clone(), 1.0)]), 2);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+
+        let harmony = generator.generate(&analysis, 8, &mut rng);
+
+        let [.., dominant, tonic] = harmony.chords.as_slice() else {
+            panic!("expected at least two chords");
+        };
+        assert_eq!(tonic.chord, Degree::tonic(&tonic.key).chord(&tonic.key));
+        assert_eq!(tonic.end, analysis.tempo_map.bar_start(8));
+        assert_eq!(dominant.chord, Degree::dominant().chord(&dominant.key));
+        assert_eq!(dominant.end, tonic.start);
+
+        let first = &harmony.chords[0];
+        assert_eq!(first.chord, Degree::tonic(&first.key).chord(&first.key));
+
+        let half = analysis.tempo_map.bar_start(4) - 1;
+        let half = harmony
+            .chords
+            .iter()
+            .find(|span| (span.start..span.end).contains(&half))
+            .unwrap();
+        assert_eq!(half.chord, Degree::dominant().chord(&half.key));
+    }
+}
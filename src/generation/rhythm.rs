@@ -0,0 +1,331 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::MarkovChain;
+use crate::{Corpus, Hit, RhythmPattern, STEPS_PER_QUARTER, TempoMap};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Draws allowed to find a pattern in the current meter before stretching
+/// one from another meter.
+const MAX_DRAWS: usize = 8;
+
+/// Bar patterns of one part, and the order they follow each other in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PartModel {
+    patterns: Vec<RhythmPattern>,
+    chain: MarkovChain<usize>,
+}
+
+/// Generates bar rhythms from the patterns of a corpus, independently of any
+/// pitch.
+///
+/// The density factor scales how many notes a bar holds, splitting long
+/// notes above `1.0` and merging notes into the previous one below it. The
+/// syncopation amount is the chance of anticipating a note on the beat by
+/// half a beat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RhythmGenerator {
+    parts: BTreeMap<(usize, u8), PartModel>,
+    density: f64,
+    syncopation: f64,
+}
+
+impl RhythmGenerator {
+    /// Learns the bar patterns of every part of the reference from the
+    /// matching part of every piece of the corpus, each piece weighing its
+    /// share whatever its length.
+    pub fn train(corpus: &Corpus, order: usize) -> Self {
+        let parts = corpus
+            .reference()
+            .rhythm
+            .parts
+            .iter()
+            .map(|part| {
+                let mut patterns: Vec<RhythmPattern> = Vec::new();
+                let mut chain = MarkovChain::new(order.max(1));
+
+                for (index, (track, channel)) in corpus.parts(part.track, part.channel) {
+                    let piece = &corpus.pieces[index];
+                    let Some(source) = piece
+                        .analysis
+                        .rhythm
+                        .parts
+                        .iter()
+                        .find(|source| source.track == track && source.channel == channel)
+                    else {
+                        continue;
+                    };
+
+                    let sequence: Vec<usize> = source
+                        .bars
+                        .iter()
+                        .map(
+                            |pattern| match patterns.iter().position(|known| known == pattern) {
+                                Some(index) => index,
+                                None => {
+                                    patterns.push(pattern.clone());
+                                    patterns.len() - 1
+                                }
+                            },
+                        )
+                        .collect();
+                    if !sequence.is_empty() {
+                        chain.train(&sequence, piece.weight / sequence.len() as f64);
+                    }
+                }
+
+                ((part.track, part.channel), PartModel { patterns, chain })
+            })
+            .collect();
+
+        Self {
+            parts,
+            density: 1.0,
+            syncopation: 0.0,
+        }
+    }
+
+    /// Whether every chain decoded from a model file is usable, on a part
+    /// of a reference with `track_count` tracks, drawing only patterns it
+    /// knows, and the settings are in range.
+    pub(super) fn is_valid(&self, track_count: usize) -> bool {
+        let parts = self.parts.iter().all(|(&(track, channel), part)| {
+            let patterns = part.patterns.len();
+            track < track_count
+                && channel <= 15
+                && part.chain.is_valid()
+                && part.chain.symbols().all(|&index| index < patterns)
+        });
+        parts
+            && self.density.is_finite()
+            && self.density >= 0.0
+            && (0.0..=1.0).contains(&self.syncopation)
+    }
+
+    pub fn density(mut self, density: f64) -> Self {
+        self.density = density.max(0.0);
+        self
+    }
+
+    pub fn syncopation(mut self, syncopation: f64) -> Self {
+        self.syncopation = syncopation.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Start and duration, in ticks, of every note of `bars` bars for the
+    /// part played on `track` and `channel`. Parts missing from the input
+    /// get no notes.
+    pub fn generate<R: Rng>(
+        &self,
+        track: usize,
+        channel: u8,
+        tempo_map: &TempoMap,
+        bars: u32,
+        rng: &mut R,
+    ) -> Vec<(u64, u64)> {
+        let Some(part) = self.parts.get(&(track, channel)) else {
+            return Vec::new();
+        };
+
+        let order = part.chain.order();
+        let mut history: Vec<usize> = Vec::with_capacity(order + 1);
+        let mut notes = Vec::new();
+
+        for bar in 0..bars {
+            let start = tempo_map.bar_start(bar);
+            let signature = tempo_map.time_signature(start);
+
+            let draws: Vec<usize> = (0..MAX_DRAWS)
+                .filter_map(|_| part.chain.sample(&history, rng))
+                .collect();
+            let Some(&index) = draws
+                .iter()
+                .find(|&&index| part.patterns[index].signature == signature)
+                .or(draws.first())
+            else {
+                break;
+            };
+
+            history.push(index);
+            if history.len() > order {
+                history.remove(0);
+            }
+
+            let pattern = &part.patterns[index];
+            let steps = RhythmPattern::steps_in(&signature);
+            let source_steps = RhythmPattern::steps_in(&pattern.signature);
+            let mut hits: Vec<Hit> = pattern
+                .hits
+                .iter()
+                .map(|hit| Hit {
+                    onset: hit.onset * steps / source_steps,
+                    duration: (hit.duration * steps / source_steps).max(1),
+                })
+                .collect();
+            hits.dedup_by_key(|hit| hit.onset);
+
+            let hits = self.vary(hits, RhythmPattern::steps_per_beat(&signature), rng);
+
+            let origin = tempo_map.quarters(start);
+            notes.extend(hits.into_iter().map(|hit| {
+                let tick = |step: u32| {
+                    tempo_map.tick_at_quarters(origin + f64::from(step) / STEPS_PER_QUARTER)
+                };
+                let onset = tick(hit.onset);
+                (
+                    onset,
+                    tick(hit.onset + hit.duration).saturating_sub(onset).max(1),
+                )
+            }));
+        }
+
+        notes
+    }
+
+    fn vary<R: Rng>(&self, mut hits: Vec<Hit>, steps_per_beat: u32, rng: &mut R) -> Vec<Hit> {
+        if self.density < 1.0 {
+            let mut kept: Vec<Hit> = Vec::with_capacity(hits.len());
+            for hit in hits {
+                match kept.last_mut() {
+                    Some(last) if !rng.random_bool(self.density) => {
+                        last.duration = hit.onset + hit.duration - last.onset;
+                    }
+                    _ => kept.push(hit),
+                }
+            }
+            hits = kept;
+        }
+
+        let mut remaining = self.density;
+        while remaining > 1.0 {
+            let chance = (remaining - 1.0).min(1.0);
+            hits = hits
+                .into_iter()
+                .flat_map(|hit| {
+                    if hit.duration >= 2 && rng.random_bool(chance) {
+                        let half = hit.duration / 2;
+                        vec![
+                            Hit {
+                                onset: hit.onset,
+                                duration: half,
+                            },
+                            Hit {
+                                onset: hit.onset + half,
+                                duration: hit.duration - half,
+                            },
+                        ]
+                    } else {
+                        vec![hit]
+                    }
+                })
+                .collect();
+            remaining /= 2.0;
+        }
+
+        if self.syncopation > 0.0 {
+            let shift = (steps_per_beat / 2).max(1);
+            for index in 1..hits.len() {
+                let hit = hits[index];
+                let previous = hits[index - 1];
+                if hit.onset.is_multiple_of(steps_per_beat)
+                    && hit.onset > previous.onset + shift
+                    && rng.random_bool(self.syncopation)
+                {
+                    hits[index - 1].duration =
+                        previous.duration.min(hit.onset - shift - previous.onset);
+                    hits[index] = Hit {
+                        onset: hit.onset - shift,
+                        duration: hit.duration + shift,
+                    };
+                }
+            }
+        }
+
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// Sixteenth-note grid steps of a 4/4 bar.
+    const BEAT: u32 = 4;
+
+    fn generator(density: f64, syncopation: f64) -> RhythmGenerator {
+        RhythmGenerator {
+            parts: BTreeMap::new(),
+            density: 1.0,
+            syncopation: 0.0,
+        }
+        .density(density)
+        .syncopation(syncopation)
+    }
+
+    /// A half note then two quarter notes.
+    fn hits() -> Vec<Hit> {
+        vec![
+            Hit {
+                onset: 0,
+                duration: 2 * BEAT,
+            },
+            Hit {
+                onset: 2 * BEAT,
+                duration: BEAT,
+            },
+            Hit {
+                onset: 3 * BEAT,
+                duration: BEAT,
+            },
+        ]
+    }
+
+    fn vary(generator: &RhythmGenerator) -> Vec<(u32, u32)> {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        generator
+            .vary(hits(), BEAT, &mut rng)
+            .into_iter()
+            .map(|hit| (hit.onset, hit.duration))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_pattern_at_the_neutral_settings() {
+        assert_eq!(vary(&generator(1.0, 0.0)), [(0, 8), (8, 4), (12, 4)]);
+    }
+
+    #[test]
+    fn merges_or_splits_notes_with_the_density() {
+        assert_eq!(vary(&generator(0.0, 0.0)), [(0, 16)]);
+        assert_eq!(
+            vary(&generator(2.0, 0.0)),
+            [(0, 4), (4, 4), (8, 2), (10, 2), (12, 2), (14, 2)]
+        );
+
+        let dense = vary(&generator(1.0e6, 0.0));
+        assert_eq!(dense.len(), 16);
+        assert!(dense.iter().all(|&(_, duration)| duration == 1));
+    }
+
+    #[test]
+    fn anticipates_beats_with_the_syncopation() {
+        assert_eq!(vary(&generator(1.0, 1.0)), [(0, 6), (6, 4), (10, 6)]);
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Analysis, Note, Score, Section};
+use std::str::FromStr;
+
+/// Sections requested with `--form`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormSpec {
+    /// The sections detected in the input, with their lengths.
+    SameAsInput,
+    /// Section labels such as `AABA` or `ABA'CA`, sharing the piece's bars
+    /// evenly, the first sections taking one more bar when they do not
+    /// divide.
+    Labels(Vec<(char, u8)>),
+}
+
+impl FromStr for FormSpec {
+    type Err = String;
+
+    fn from_str(form: &str) -> Result<Self, Self::Err> {
+        if form.eq_ignore_ascii_case("same-as-input") {
+            return Ok(Self::SameAsInput);
+        }
+
+        let mut labels: Vec<(char, u8)> = Vec::new();
+        for symbol in form.chars().filter(|symbol| !symbol.is_whitespace()) {
+            match (symbol, labels.last_mut()) {
+                ('\'', Some((_, variant))) => *variant = variant.saturating_add(1),
+                (label, _) if label.is_ascii_alphabetic() => {
+                    labels.push((label.to_ascii_uppercase(), 0));
+                }
+                _ => {
+                    return Err(format!(
+                        "expected section letters such as AABA, or same-as-input, found `{symbol}`"
+                    ));
+                }
+            }
+        }
+
+        if labels.is_empty() {
+            return Err("expected at least one section".to_string());
+        }
+        Ok(Self::Labels(labels))
+    }
+}
+
+impl FormSpec {
+    /// Lays the sections out over `bars` bars. The input's own layout keeps
+    /// its lengths whatever `bars` is.
+    pub fn sections(&self, analysis: &Analysis, bars: u32) -> Vec<Section> {
+        match self {
+            Self::SameAsInput if !analysis.form.sections.is_empty() => {
+                analysis.form.sections.clone()
+            }
+            Self::SameAsInput => vec![Section {
+                label: 0,
+                variant: 0,
+                start_bar: 0,
+                bars,
+            }],
+            Self::Labels(labels) => {
+                let count = labels.len() as u32;
+                let (length, remainder) = if bars >= count {
+                    (bars / count, bars % count)
+                } else {
+                    (1, 0)
+                };
+
+                let mut start_bar = 0;
+                labels
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &(label, variant))| {
+                        let bars = length + u32::from((index as u32) < remainder);
+                        let section = Section {
+                            label: u32::from(label) - u32::from('A'),
+                            variant,
+                            start_bar,
+                            bars,
+                        };
+                        start_bar += bars;
+                        section
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Imposes a form on a generated score.
+#[derive(Debug)]
+pub struct Structure;
+
+impl Structure {
+    /// Replaces every section restating an earlier label with the notes of
+    /// the first section so labelled. Varied sections, such as `A'`, only
+    /// restate the first half and keep their own continuation, and new
+    /// labels keep their own material, which brings the contrast.
+    pub fn apply(sections: &[Section], analysis: &Analysis, score: &mut Score) {
+        let tempo_map = &analysis.tempo_map;
+
+        for (index, section) in sections.iter().enumerate() {
+            let Some(model) = sections[..index]
+                .iter()
+                .find(|model| model.label == section.label)
+            else {
+                continue;
+            };
+
+            let bars = section.bars.min(model.bars);
+            let bars = if section.variant > model.variant {
+                bars.div_ceil(2)
+            } else {
+                bars
+            };
+
+            let source = (
+                tempo_map.bar_start(model.start_bar),
+                tempo_map.bar_start(model.start_bar + bars),
+            );
+            let target = (
+                tempo_map.bar_start(section.start_bar),
+                tempo_map.bar_start(section.start_bar + bars),
+            );
+
+            let copied: Vec<Note> = score
+                .notes
+                .iter()
+                .filter(|note| note.start >= source.0 && note.start < source.1)
+                .map(|note| Note {
+                    start: note.start - source.0 + target.0,
+                    duration: note.duration.min(source.1 - note.start),
+                    ..*note
+                })
+                .collect();
+
+            score
+                .notes
+                .retain(|note| note.start < target.0 || note.start >= target.1);
+            for note in &mut score.notes {
+                if note.start < target.0 && note.end() > target.0 {
+                    note.duration = target.0 - note.start;
+                }
+            }
+            score.notes.extend(copied);
+        }
+
+        score.sort_notes();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::Smf;
+
+    static PRELUDE: &[u8] = include_bytes!("../../assets/midi/prelude1.mid");
+
+    /// Sections of `form` over `bars` bars, such as `A@0x4` for a first
+    /// section of four bars.
+    fn layout(form: &str, bars: u32) -> String {
+        let analysis = Analysis::new(&Smf::parse(PRELUDE).unwrap());
+        let sections: Vec<String> = form
+            .parse::<FormSpec>()
+            .unwrap()
+            .sections(&analysis, bars)
+            .iter()
+            .map(|section| format!("{section}@{}x{}", section.start_bar, section.bars))
+            .collect();
+        sections.join(" ")
+    }
+
+    #[test]
+    fn parses_labels_and_variants() {
+        assert_eq!(
+            "ab A'c''".parse(),
+            Ok(FormSpec::Labels(vec![
+                ('A', 0),
+                ('B', 0),
+                ('A', 1),
+                ('C', 2)
+            ]))
+        );
+        assert_eq!("Same-As-Input".parse(), Ok(FormSpec::SameAsInput));
+        assert!("".parse::<FormSpec>().is_err());
+        assert!("'A".parse::<FormSpec>().is_err());
+        assert!("A1B".parse::<FormSpec>().is_err());
+    }
+
+    #[test]
+    fn spreads_the_remainder_over_the_first_sections() {
+        assert_eq!(layout("AAB", 10), "A@0x4 A@4x3 B@7x3");
+        assert_eq!(layout("ABA'", 12), "A@0x4 B@4x4 A'@8x4");
+        assert_eq!(layout("AABA", 2), "A@0x1 A@1x1 B@2x1 A@3x1");
+    }
+
+    #[test]
+    fn keeps_the_input_layout() {
+        let analysis = Analysis::new(&Smf::parse(PRELUDE).unwrap());
+        let sections = FormSpec::SameAsInput.sections(&analysis, 4);
+
+        assert_eq!(sections, analysis.form.sections);
+    }
+}
@@ -0,0 +1,534 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{DEFAULT_TEMPO, Key, KeyFinder, Mode, Score, ScoreEvent, TempoMap, pitch_name};
+use midly::{MetaMessage, MidiMessage, TrackEventKind, num::u24};
+use std::{collections::BTreeMap, str::FromStr};
+
+/// One step of `compose transform`, written as `name` or `name:value`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    /// `transpose:N`, moving every note by N semitones, from -127 to 127.
+    Transpose(i16),
+    /// `diatonic:N`, moving every note by N steps of the scale of the key,
+    /// from -75 to 75.
+    Diatonic(i16),
+    /// `invert[:PITCH]`, mirroring the notes around a pitch such as `C4` or
+    /// `60`, the first note by default.
+    Invert(Option<u8>),
+    /// `retrograde`, playing the notes backwards.
+    Retrograde,
+    /// `stretch:FACTOR`, scaling the durations of the notes, `2` for
+    /// augmentation and `0.5` for diminution.
+    Stretch(f64),
+    /// `tempo:FACTOR`, playing faster by a factor without moving the notes.
+    Tempo(f64),
+    /// `mode:major` or `mode:minor`, moving the notes into the parallel
+    /// mode.
+    Mode(Mode),
+}
+
+impl FromStr for Transform {
+    type Err = String;
+
+    fn from_str(transform: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, value) = match transform.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (transform, None),
+        };
+        let required =
+            || value.ok_or_else(|| format!("`{name}` expects a value, as in `{name}:2`"));
+        let integer = |value: &str, limit: i16| match value.parse::<i16>() {
+            Ok(steps) if (-limit..=limit).contains(&steps) => Ok(steps),
+            _ => Err(format!(
+                "expected a whole number from -{limit} to {limit}, found `{value}`"
+            )),
+        };
+        let factor = |value: &str| match value.parse::<f64>() {
+            Ok(factor) if factor.is_finite() && factor > 0.0 => Ok(factor),
+            _ => Err(format!("expected a positive factor, found `{value}`")),
+        };
+
+        match name.to_ascii_lowercase().as_str() {
+            "transpose" => Ok(Self::Transpose(integer(required()?, 127)?)),
+            "diatonic" => Ok(Self::Diatonic(integer(required()?, 75)?)),
+            "invert" => Ok(Self::Invert(value.map(Self::pitch).transpose()?)),
+            "retrograde" => Ok(Self::Retrograde),
+            "stretch" => Ok(Self::Stretch(factor(required()?)?)),
+            "tempo" => Ok(Self::Tempo(factor(required()?)?)),
+            "mode" => match required()?.to_ascii_lowercase().as_str() {
+                "major" => Ok(Self::Mode(Mode::Major)),
+                "minor" => Ok(Self::Mode(Mode::Minor)),
+                mode => Err(format!("expected `major` or `minor`, found `{mode}`")),
+            },
+            _ => Err(format!(
+                "unknown transformation `{name}`, expected one of transpose, diatonic, invert, \
+                 retrograde, stretch, tempo or mode"
+            )),
+        }
+    }
+}
+
+impl Transform {
+    /// MIDI key of a pitch written as a number or in scientific pitch
+    /// notation.
+    fn pitch(pitch: &str) -> std::result::Result<u8, String> {
+        if let Ok(key) = pitch.parse::<u8>() {
+            return (key <= 127)
+                .then_some(key)
+                .ok_or_else(|| format!("expected a MIDI key from 0 to 127, found `{key}`"));
+        }
+        (0..=127)
+            .find(|&key| pitch_name(key).eq_ignore_ascii_case(pitch))
+            .ok_or_else(|| format!("expected a pitch such as `C4` or `60`, found `{pitch}`"))
+    }
+}
+
+/// Applies transformations to the notes of a track, a channel or a whole
+/// score. Pitch transformations leave percussion alone, and tempo, meter
+/// and key signatures follow only when the whole score is transformed.
+#[derive(Debug)]
+pub struct Transformer {
+    track: Option<usize>,
+    channel: Option<u8>,
+}
+
+impl Transformer {
+    pub fn new(track: Option<usize>, channel: Option<u8>) -> Self {
+        Self { track, channel }
+    }
+
+    /// Applies `transforms` to `score` one after the other.
+    pub fn apply(&self, transforms: &[Transform], score: &mut Score) {
+        for &transform in transforms {
+            match transform {
+                Transform::Transpose(semitones) => self.transpose(score, semitones),
+                Transform::Diatonic(steps) => {
+                    let key = Self::key(score);
+                    self.map_pitches(score, |pitch| Self::diatonic(key, pitch, steps));
+                }
+                Transform::Invert(pivot) => self.invert(score, pivot),
+                Transform::Retrograde => self.retrograde(score),
+                Transform::Stretch(factor) => self.stretch(score, factor),
+                Transform::Tempo(factor) => Self::scale_tempo(score, factor),
+                Transform::Mode(mode) => self.change_mode(score, mode),
+            }
+        }
+        score.sort_notes();
+    }
+
+    fn whole(&self) -> bool {
+        self.track.is_none() && self.channel.is_none()
+    }
+
+    fn selects(&self, track: usize, channel: u8) -> bool {
+        self.track.is_none_or(|selected| selected == track)
+            && self.channel.is_none_or(|selected| selected == channel)
+    }
+
+    fn map_pitches(&self, score: &mut Score, map: impl Fn(u8) -> i16) {
+        for note in score
+            .notes
+            .iter_mut()
+            .filter(|note| !note.is_percussion() && self.selects(note.track, note.channel))
+        {
+            note.pitch = map(note.pitch).clamp(0, 127) as u8;
+        }
+    }
+
+    /// Rewrites every key signature with `map` when the whole score moves.
+    fn map_keys(&self, score: &mut Score, map: impl Fn(Key) -> Key) {
+        if !self.whole() {
+            return;
+        }
+        for event in &mut score.events {
+            if let TrackEventKind::Meta(MetaMessage::KeySignature(accidentals, minor)) =
+                event.kind()
+            {
+                let (accidentals, minor) = map(Key::from_signature(accidentals, minor)).signature();
+                let signature = MetaMessage::KeySignature(accidentals, minor);
+                *event = ScoreEvent::new(event.tick, event.track, TrackEventKind::Meta(signature));
+            }
+        }
+    }
+
+    /// Global key of the score as it stands.
+    fn key(score: &Score) -> Key {
+        let tempo_map = TempoMap::new(&score.to_smf());
+        KeyFinder::analyze(score, &tempo_map).global
+    }
+
+    fn transpose(&self, score: &mut Score, semitones: i16) {
+        self.map_pitches(score, |pitch| i16::from(pitch) + semitones);
+        self.map_keys(score, |key| {
+            Key::new(
+                (i16::from(key.tonic) + semitones).rem_euclid(12) as u8,
+                key.mode,
+            )
+        });
+    }
+
+    /// Moves `pitch` by `steps` degrees of the scale of `key`, keeping its
+    /// chromatic alteration.
+    fn diatonic(key: Key, pitch: u8, steps: i16) -> i16 {
+        let offsets = key
+            .scale()
+            .map(|pitch_class| i16::from((pitch_class + 12 - key.tonic) % 12));
+        let relative = i16::from(pitch) - i16::from(key.tonic);
+        let (octave, offset) = (relative.div_euclid(12), relative.rem_euclid(12));
+
+        let degree = offsets
+            .iter()
+            .rposition(|&step| step <= offset)
+            .unwrap_or(0);
+        let alteration = offset - offsets[degree];
+        let target = degree as i16 + steps;
+        let octave = octave + target.div_euclid(7);
+
+        i16::from(key.tonic) + octave * 12 + offsets[target.rem_euclid(7) as usize] + alteration
+    }
+
+    fn invert(&self, score: &mut Score, pivot: Option<u8>) {
+        let Some(pivot) = pivot.or_else(|| {
+            score
+                .notes
+                .iter()
+                .find(|note| !note.is_percussion() && self.selects(note.track, note.channel))
+                .map(|note| note.pitch)
+        }) else {
+            return;
+        };
+        let mirror = |pitch: i16| 2 * i16::from(pivot) - pitch;
+        self.map_pitches(score, |pitch| mirror(i16::from(pitch)));
+        // The tonic triad mirrors into the triad of the other mode whose
+        // root is the mirrored dominant, so C major becomes F minor around C.
+        self.map_keys(score, |key| {
+            let mode = match key.mode {
+                Mode::Major => Mode::Minor,
+                Mode::Minor => Mode::Major,
+            };
+            Key::new(mirror(i16::from(key.tonic) + 7).rem_euclid(12) as u8, mode)
+        });
+    }
+
+    /// Reverses the selected notes within the time they span, so the first
+    /// note starts where the last one ended. Controllers and program changes
+    /// of the selection follow: each moves to where the notes it applied to
+    /// now begin, and the setting in force before the span takes over where
+    /// the first change used to be.
+    fn retrograde(&self, score: &mut Score) {
+        let selected = |track: usize, channel: u8| self.selects(track, channel);
+        let span = score
+            .notes
+            .iter()
+            .filter(|note| selected(note.track, note.channel))
+            .fold(None, |span: Option<(u64, u64)>, note| match span {
+                Some((start, end)) => Some((start.min(note.start), end.max(note.end()))),
+                None => Some((note.start, note.end())),
+            });
+        let Some((start, end)) = span else {
+            return;
+        };
+
+        for note in score
+            .notes
+            .iter_mut()
+            .filter(|note| selected(note.track, note.channel))
+        {
+            note.start = start + end - note.end();
+        }
+
+        let mut settings: BTreeMap<(usize, u8, u8), Vec<usize>> = BTreeMap::new();
+        for (index, event) in score.events.iter().enumerate() {
+            if let TrackEventKind::Midi { channel, message } = event.kind() {
+                let setting = match message {
+                    MidiMessage::Controller { controller, .. } => controller.as_int(),
+                    MidiMessage::ProgramChange { .. } => 128,
+                    _ => continue,
+                };
+                if selected(event.track, channel.as_int()) {
+                    let group = (event.track, channel.as_int(), setting);
+                    settings.entry(group).or_default().push(index);
+                }
+            }
+        }
+
+        for mut changes in settings.into_values() {
+            changes.sort_by_key(|&index| score.events[index].tick);
+            let inside: Vec<usize> = changes
+                .iter()
+                .copied()
+                .filter(|&index| (start..end).contains(&score.events[index].tick))
+                .collect();
+            if inside.is_empty() {
+                continue;
+            }
+            let ticks: Vec<u64> = inside
+                .iter()
+                .map(|&index| score.events[index].tick)
+                .collect();
+
+            if ticks[0] > start
+                && let Some(&before) = changes
+                    .iter()
+                    .rev()
+                    .find(|&&index| score.events[index].tick < start)
+            {
+                let mut event = score.events[before].clone();
+                event.tick = start + end - ticks[0];
+                score.events.push(event);
+            }
+            for (position, &index) in inside.iter().enumerate() {
+                let until = ticks.get(position + 1).copied().unwrap_or(end);
+                score.events[index].tick = start + end - until;
+            }
+        }
+    }
+
+    /// Scales the start and duration of the selected notes from the
+    /// beginning of the piece, along with every event when the whole score
+    /// is stretched.
+    fn stretch(&self, score: &mut Score, factor: f64) {
+        let scale = |tick: u64| (tick as f64 * factor).round() as u64;
+        for note in score
+            .notes
+            .iter_mut()
+            .filter(|note| self.selects(note.track, note.channel))
+        {
+            let end = scale(note.end());
+            note.start = scale(note.start);
+            note.duration = end - note.start;
+        }
+
+        if self.whole() {
+            for event in &mut score.events {
+                event.tick = scale(event.tick);
+            }
+        }
+    }
+
+    /// Divides every tempo by `factor`, which speeds up the whole score
+    /// whatever is selected.
+    fn scale_tempo(score: &mut Score, factor: f64) {
+        let scale = |tempo: u32| {
+            u24::new((f64::from(tempo) / factor).round().clamp(1.0, 16_777_215.0) as u32)
+        };
+
+        let mut found = false;
+        for event in &mut score.events {
+            if let TrackEventKind::Meta(MetaMessage::Tempo(tempo)) = event.kind() {
+                let tempo = MetaMessage::Tempo(scale(tempo.as_int()));
+                *event = ScoreEvent::new(event.tick, event.track, TrackEventKind::Meta(tempo));
+                found = true;
+            }
+        }
+
+        if !found {
+            score.events.push(ScoreEvent::new(
+                0,
+                0,
+                TrackEventKind::Meta(MetaMessage::Tempo(scale(DEFAULT_TEMPO))),
+            ));
+        }
+    }
+
+    /// Moves the notes from the mode of the global key to `mode`: the major
+    /// third and sixth become minor, or the minor third, sixth and seventh
+    /// become major. The leading tone stays, as in harmonic minor.
+    fn change_mode(&self, score: &mut Score, mode: Mode) {
+        let key = Self::key(score);
+        if key.mode == mode {
+            return;
+        }
+
+        let shift: i16 = match mode {
+            Mode::Minor => -1,
+            Mode::Major => 1,
+        };
+        let moved: &[u8] = match mode {
+            Mode::Minor => &[4, 9],
+            Mode::Major => &[3, 8, 10],
+        };
+        self.map_pitches(score, |pitch| {
+            let degree = (pitch + 12 - key.tonic) % 12;
+            i16::from(pitch) + if moved.contains(&degree) { shift } else { 0 }
+        });
+        self.map_keys(score, |key| Key::new(key.tonic, mode));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Note;
+    use midly::{
+        Format, Header, Timing,
+        num::{u4, u7, u15},
+    };
+
+    const QUARTER: u64 = 480;
+
+    /// Whole score of quarter notes on channel 0, with a key signature.
+    fn score(pitches: &[u8], signature: (i8, bool)) -> Score {
+        let header = Header::new(Format::SingleTrack, Timing::Metrical(u15::new(480)));
+        let mut score = Score::new(header, 1);
+        score.notes = pitches
+            .iter()
+            .enumerate()
+            .map(|(index, &pitch)| Note {
+                start: index as u64 * QUARTER,
+                duration: QUARTER,
+                pitch,
+                velocity: 80,
+                release: None,
+                channel: 0,
+                track: 0,
+            })
+            .collect();
+        score.events.push(ScoreEvent::new(
+            0,
+            0,
+            TrackEventKind::Meta(MetaMessage::KeySignature(signature.0, signature.1)),
+        ));
+        score
+    }
+
+    fn pitches(score: &Score) -> Vec<u8> {
+        score.notes.iter().map(|note| note.pitch).collect()
+    }
+
+    fn signatures(score: &Score) -> Vec<(i8, bool)> {
+        score
+            .events
+            .iter()
+            .filter_map(|event| match event.kind() {
+                TrackEventKind::Meta(MetaMessage::KeySignature(accidentals, minor)) => {
+                    Some((accidentals, minor))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn program(tick: u64, program: u8) -> ScoreEvent {
+        ScoreEvent::new(
+            tick,
+            0,
+            TrackEventKind::Midi {
+                channel: u4::new(0),
+                message: MidiMessage::ProgramChange {
+                    program: u7::new(program),
+                },
+            },
+        )
+    }
+
+    #[test]
+    fn parses_transformations() {
+        assert_eq!("transpose:-3".parse(), Ok(Transform::Transpose(-3)));
+        assert_eq!("Diatonic:2".parse(), Ok(Transform::Diatonic(2)));
+        assert_eq!("transpose:-127".parse(), Ok(Transform::Transpose(-127)));
+        assert_eq!("diatonic:75".parse(), Ok(Transform::Diatonic(75)));
+        assert_eq!("invert".parse(), Ok(Transform::Invert(None)));
+        assert_eq!("invert:C4".parse(), Ok(Transform::Invert(Some(60))));
+        assert_eq!("invert:f#3".parse(), Ok(Transform::Invert(Some(54))));
+        assert_eq!("invert:67".parse(), Ok(Transform::Invert(Some(67))));
+        assert_eq!("retrograde".parse(), Ok(Transform::Retrograde));
+        assert_eq!("stretch:0.5".parse(), Ok(Transform::Stretch(0.5)));
+        assert_eq!("tempo:2".parse(), Ok(Transform::Tempo(2.0)));
+        assert_eq!("mode:Minor".parse(), Ok(Transform::Mode(Mode::Minor)));
+    }
+
+    #[test]
+    fn rejects_malformed_transformations() {
+        for transform in [
+            "transpose",
+            "transpose:up",
+            "transpose:128",
+            "transpose:-128",
+            "diatonic:76",
+            "diatonic:-76",
+            "invert:128",
+            "invert:H2",
+            "stretch:0",
+            "stretch:-1",
+            "tempo:inf",
+            "mode:dorian",
+            "reverse",
+        ] {
+            assert!(transform.parse::<Transform>().is_err(), "{transform}");
+        }
+    }
+
+    #[test]
+    fn moves_by_steps_of_the_scale() {
+        let c_major = Key::new(0, Mode::Major);
+        assert_eq!(Transformer::diatonic(c_major, 64, 2), 67);
+        assert_eq!(Transformer::diatonic(c_major, 71, 1), 72);
+        assert_eq!(Transformer::diatonic(c_major, 60, -1), 59);
+        assert_eq!(Transformer::diatonic(c_major, 60, 7), 72);
+        assert_eq!(Transformer::diatonic(c_major, 61, 1), 63);
+
+        let a_minor = Key::new(9, Mode::Minor);
+        assert_eq!(Transformer::diatonic(a_minor, 69, 2), 72);
+        assert_eq!(Transformer::diatonic(a_minor, 68, 1), 70);
+        assert_eq!(Transformer::diatonic(a_minor, 57, -3), 52);
+    }
+
+    #[test]
+    fn changes_mode_and_key_signature() {
+        let mut score = score(&[60, 62, 64, 65, 67, 69, 71, 72], (0, false));
+        Transformer::new(None, None).apply(&[Transform::Mode(Mode::Minor)], &mut score);
+        assert_eq!(pitches(&score), [60, 62, 63, 65, 67, 68, 71, 72]);
+        assert_eq!(signatures(&score), [(-3, true)]);
+
+        Transformer::new(None, None).apply(&[Transform::Mode(Mode::Major)], &mut score);
+        assert_eq!(pitches(&score), [60, 62, 64, 65, 67, 69, 71, 72]);
+        assert_eq!(signatures(&score), [(0, false)]);
+    }
+
+    #[test]
+    fn inverts_notes_and_key_signature() {
+        let mut score = score(&[60, 64, 67], (0, false));
+        Transformer::new(None, None).apply(&[Transform::Invert(None)], &mut score);
+        assert_eq!(pitches(&score), [60, 56, 53]);
+        assert_eq!(signatures(&score), [(-4, true)]);
+    }
+
+    #[test]
+    fn reverses_program_changes_with_the_notes() {
+        let mut score = score(&[60, 62, 64, 65], (0, false));
+        score
+            .events
+            .extend([program(0, 1), program(2 * QUARTER, 2)]);
+        Transformer::new(None, None).apply(&[Transform::Retrograde], &mut score);
+
+        assert_eq!(pitches(&score), [65, 64, 62, 60]);
+        let mut programs: Vec<(u64, u8)> = score
+            .events
+            .iter()
+            .filter_map(|event| match event.kind() {
+                TrackEventKind::Midi {
+                    message: MidiMessage::ProgramChange { program },
+                    ..
+                } => Some((event.tick, program.as_int())),
+                _ => None,
+            })
+            .collect();
+        programs.sort();
+        assert_eq!(programs, [(0, 2), (2 * QUARTER, 1)]);
+    }
+}
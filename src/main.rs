@@ -14,15 +14,33 @@
  * limitations under the License.
  */
 
+mod analysis;
 mod app;
 mod args;
 mod core;
+mod generation;
 mod media;
+mod midi;
 
+use analysis::{
+    Analysis, Chord, ChordQuality, ChordSpan, Corpus, Harmony, Hit, Key, KeyFinder, Mode, Motif,
+    Report, ReportFormat, RhythmPattern, STEPS_PER_QUARTER, Section, VoiceSeparator,
+};
 use app::{AutoComposer, Input, Output};
 use args::{Cli, Commands, Generate};
-use core::{Error, Result};
-use media::{Player, SoundFont};
+use core::{Error, Result, write_file};
+use generation::{
+    Accompaniment, CounterpointGenerator, FormSpec, Harmonizer, MAX_ORDER, MelodyMode, Model,
+    MotifDeveloper, Species, Structure, Transform, Transformer, Voicing,
+};
+use media::{
+    ChannelMap, DeviceSettings, Playback, Player, Renderer, SoundFont, WavFormat, describe_devices,
+    encode_wav,
+};
+use midi::{
+    DEFAULT_TEMPO, Note, Position, Reader, SHARP_NAMES, Score, ScoreEvent, Source, TempoMap,
+    TimeSignature, Writer, instrument_name, pitch_name,
+};
 
 fn main() -> Result<()> {
     match Cli::parse() {
@@ -33,10 +51,83 @@ fn main() -> Result<()> {
                 .for_each(|sound| println!("  - {sound}"));
             Ok(())
         }
+        Commands::Devices => {
+            print!("{}", describe_devices());
+            Ok(())
+        }
         Commands::Generate(args) => {
-            let input = Input::build(&args)?;
+            let mut input = Input::build(&args)?;
             let output = AutoComposer::run(&input)?;
-            output.process()
+            if input.interactive {
+                output.interact(&mut input)
+            } else {
+                output.process()
+            }
+        }
+        Commands::Train(args) => {
+            let corpus = Corpus::load(&args.files, args.split_voices)?;
+            let model = Model::train(&corpus, usize::from(args.order))?;
+            write_file(&args.output, &model.to_bytes()?, args.force)
+        }
+        Commands::Analyze(args) => {
+            let bytes = Reader::read_file(&args.file)?;
+            let smf = Reader::parse(&bytes)?;
+            let analysis = if args.split_voices {
+                Analysis::with_voices(&smf)
+            } else {
+                Analysis::new(&smf)
+            };
+            let report = Report::new(&analysis);
+            print!("{}", report.render(args.format)?);
+            Ok(())
+        }
+        Commands::Render(args) => {
+            let bytes = Reader::read_file(&args.file)?;
+            let smf = Reader::parse(&bytes)?;
+            let soundfont = SoundFont::new(&args.sound, args.custom_sound.as_ref())?;
+            let renderer = Renderer::new(smf, soundfont, args.sample_rate, args.max_tail)?;
+            let audio = renderer.render()?;
+            let bytes = encode_wav(&audio, args.bit_depth)?;
+            write_file(&args.output, &bytes, args.force)
+        }
+        Commands::Counterpoint(args) => {
+            let bytes = Reader::read_file(&args.file)?;
+            let smf = Reader::parse(&bytes)?;
+            let analysis = Analysis::new(&smf);
+            let generator = CounterpointGenerator::new(args.species, args.below);
+            let channel = args.channel.map(|channel| channel - 1);
+            let counterpoint = generator.generate(&analysis, args.track, channel, args.seed)?;
+            let bytes = Writer::new().write(&counterpoint.score.to_smf())?;
+            write_file(&args.output, &bytes, args.force)?;
+            print!("{counterpoint}");
+            Ok(())
+        }
+        Commands::Harmonize(args) => {
+            let bytes = Reader::read_file(&args.file)?;
+            let smf = Reader::parse(&bytes)?;
+            let analysis = Analysis::new(&smf);
+            let channel = args.channel.map(|channel| channel - 1);
+            let chorale = Harmonizer::harmonize(&analysis, args.track, channel)?;
+            let bytes = Writer::new().write(&chorale.score.to_smf())?;
+            write_file(&args.output, &bytes, args.force)?;
+            print!("{chorale}");
+            Ok(())
+        }
+        Commands::Transform(args) => {
+            let bytes = Reader::read_file(&args.file)?;
+            let smf = Reader::parse(&bytes)?;
+            let mut score = Score::from_smf(&smf);
+            let channel = args.channel.map(|channel| channel - 1);
+            Transformer::new(args.track, channel).apply(&args.transforms, &mut score);
+            let bytes = Writer::new().write(&score.to_smf())?;
+            write_file(&args.output, &bytes, args.force)
+        }
+        Commands::SplitVoices(args) => {
+            let bytes = Reader::read_file(&args.file)?;
+            let smf = Reader::parse(&bytes)?;
+            let score = VoiceSeparator::split(&Score::from_smf(&smf), &TempoMap::new(&smf));
+            let bytes = Writer::new().write(&score.to_smf())?;
+            write_file(&args.output, &bytes, args.force)
         }
     }
 }
@@ -0,0 +1,504 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Error, Result};
+use cpal::{
+    BufferSize, Device, FrameCount, SampleFormat, SampleRate, StreamConfig, SupportedBufferSize,
+    SupportedStreamConfig, SupportedStreamConfigRange,
+    traits::{DeviceTrait, HostTrait},
+};
+use std::{fmt::Write, iter, ops::RangeInclusive, str::FromStr};
+
+/// Sample rates tried, in order, when the default output config is set aside.
+const PREFERRED_SAMPLE_RATES: [SampleRate; 2] = [48_000, 44_100];
+
+/// Device channels the left and right sides are written to, counted from
+/// zero. Both sides on one channel are mixed down to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelMap {
+    pub left: u16,
+    pub right: u16,
+}
+
+impl FromStr for ChannelMap {
+    type Err = String;
+
+    fn from_str(map: &str) -> std::result::Result<Self, Self::Err> {
+        let channel = |channel: &str| match channel.trim().parse::<u16>() {
+            Ok(channel @ 1..) => Ok(channel - 1),
+            _ => Err(format!(
+                "expected LEFT,RIGHT or a single channel counted from 1, found `{map}`"
+            )),
+        };
+
+        let (left, right) = match map.split_once(',') {
+            Some((left, right)) => (channel(left)?, channel(right)?),
+            None => {
+                let channel = channel(map)?;
+                (channel, channel)
+            }
+        };
+        Ok(Self { left, right })
+    }
+}
+
+impl ChannelMap {
+    /// The first two channels of the device, or its only one.
+    pub fn stereo(channels: u16) -> Self {
+        Self {
+            left: 0,
+            right: channels.clamp(1, 2) - 1,
+        }
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.left == self.right
+    }
+
+    /// Channels a device needs for the map to fit.
+    fn channels(&self) -> u16 {
+        self.left.max(self.right) + 1
+    }
+}
+
+/// Output device settings for playback. Whatever is left unset comes from
+/// the default device and config.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceSettings {
+    /// Name or id of the device, as listed by `compose devices`.
+    pub name: Option<String>,
+    pub sample_rate: Option<SampleRate>,
+    /// Frames per callback.
+    pub buffer_size: Option<FrameCount>,
+    /// Where the sides go, or the first two channels.
+    pub channel_map: Option<ChannelMap>,
+}
+
+impl DeviceSettings {
+    /// Finds the device named in the settings, looking at the default host
+    /// first, or the default output device.
+    pub fn output_device(&self) -> Result<Device> {
+        let Some(name) = &self.name else {
+            return cpal::default_host()
+                .default_output_device()
+                .ok_or_else(|| Error::DeviceNotFound("no default output device".to_string()));
+        };
+
+        let default = cpal::default_host().id();
+        let hosts = iter::once(default).chain(
+            cpal::available_hosts()
+                .into_iter()
+                .filter(|&host| host != default),
+        );
+        let devices: Vec<Device> = hosts
+            .filter_map(|host| cpal::host_from_id(host).ok())
+            .filter_map(|host| host.output_devices().ok())
+            .flatten()
+            .collect();
+
+        let id = |device: &Device| device.id().map(|id| id.to_string()).ok();
+        let device_name = |device: &Device| {
+            device
+                .description()
+                .map(|description| description.name().to_string())
+                .ok()
+        };
+
+        find_device(devices, name, id, device_name)
+    }
+
+    /// Picks the config to open `device` with: its default one when it meets
+    /// the settings, otherwise the best of its supported configs that does.
+    pub fn output_config(&self, device: &Device) -> Result<SupportedStreamConfig> {
+        let default = device.default_output_config().ok();
+        if let Some(config) = &default
+            && self.fits(
+                config.channels(),
+                config.sample_format(),
+                config.sample_rate()..=config.sample_rate(),
+                config.buffer_size(),
+            )
+        {
+            return Ok(config.clone());
+        }
+
+        let range = device
+            .supported_output_configs()
+            .map_err(|err| {
+                Error::AudioPlayback(format!("Failed to get supported output configs: {err}"))
+            })?
+            .filter(|range| {
+                self.fits(
+                    range.channels(),
+                    range.sample_format(),
+                    range.min_sample_rate()..=range.max_sample_rate(),
+                    range.buffer_size(),
+                )
+            })
+            .max_by(SupportedStreamConfigRange::cmp_default_heuristics)
+            .ok_or_else(|| Error::UnsupportedDeviceConfig(self.requirements()))?;
+
+        let sample_rate = self
+            .sample_rate
+            .into_iter()
+            .chain(default.iter().map(SupportedStreamConfig::sample_rate))
+            .chain(PREFERRED_SAMPLE_RATES)
+            .find(|&rate| (range.min_sample_rate()..=range.max_sample_rate()).contains(&rate));
+
+        Ok(match sample_rate {
+            Some(sample_rate) => range.with_sample_rate(sample_rate),
+            None => range.with_max_sample_rate(),
+        })
+    }
+
+    /// The stream config to open the device with in `config`.
+    pub fn stream_config(&self, config: &SupportedStreamConfig) -> StreamConfig {
+        let mut stream_config = config.config();
+        if let Some(buffer_size) = self.buffer_size {
+            stream_config.buffer_size = BufferSize::Fixed(buffer_size);
+        }
+        stream_config
+    }
+
+    fn fits(
+        &self,
+        channels: u16,
+        format: SampleFormat,
+        sample_rates: RangeInclusive<SampleRate>,
+        buffer_sizes: &SupportedBufferSize,
+    ) -> bool {
+        let needed = self.channel_map.map_or(1, |map| map.channels());
+        let sample_rate = self
+            .sample_rate
+            .is_none_or(|sample_rate| sample_rates.contains(&sample_rate));
+        let buffer_size = match (self.buffer_size, buffer_sizes) {
+            (Some(buffer_size), SupportedBufferSize::Range { min, max }) => {
+                (min..=max).contains(&&buffer_size)
+            }
+            (Some(_), SupportedBufferSize::Unknown) => false,
+            (None, _) => true,
+        };
+        channels >= needed && is_supported(format) && sample_rate && buffer_size
+    }
+
+    /// What a config needs to meet the settings, for the error when none does.
+    fn requirements(&self) -> String {
+        let mut requirements = Vec::new();
+        if let Some(map) = self.channel_map {
+            requirements.push(format!("{} channels", map.channels()));
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            requirements.push(format!("{sample_rate} Hz"));
+        }
+        if let Some(buffer_size) = self.buffer_size {
+            requirements.push(format!("{buffer_size}-frame buffers"));
+        }
+
+        if requirements.is_empty() {
+            "the device has no config in a supported sample format".to_string()
+        } else {
+            format!(
+                "the device has no config with {} in a supported sample format",
+                requirements.join(", ")
+            )
+        }
+    }
+}
+
+/// The device of `devices` that `name` designates: the one with that id,
+/// else the one with that name whatever the case, else the only one whose
+/// name contains it.
+fn find_device<D>(
+    mut devices: Vec<D>,
+    name: &str,
+    id: impl Fn(&D) -> Option<String>,
+    device_name: impl Fn(&D) -> Option<String>,
+) -> Result<D> {
+    let position = devices
+        .iter()
+        .position(|device| id(device).as_deref() == Some(name))
+        .or_else(|| {
+            devices.iter().position(|device| {
+                device_name(device).is_some_and(|found| found.eq_ignore_ascii_case(name))
+            })
+        });
+    if let Some(position) = position {
+        return Ok(devices.swap_remove(position));
+    }
+
+    let lowercase = name.to_lowercase();
+    let mut matches: Vec<D> = devices
+        .into_iter()
+        .filter(|device| {
+            device_name(device).is_some_and(|found| found.to_lowercase().contains(&lowercase))
+        })
+        .collect();
+    match matches.len() {
+        0 => Err(Error::DeviceNotFound(format!("no device named `{name}`"))),
+        1 => Ok(matches.remove(0)),
+        _ => Err(Error::AmbiguousDevice(
+            name.to_string(),
+            matches.iter().filter_map(&device_name).collect(),
+        )),
+    }
+}
+
+/// Lists every host, its output devices and the configs they support.
+pub fn describe_devices() -> String {
+    let mut text = String::new();
+    let default = cpal::default_host().id();
+
+    for host in cpal::available_hosts() {
+        let marker = if host == default { " (default)" } else { "" };
+        let _ = writeln!(text, "{}{marker}", host.name());
+
+        let host = match cpal::host_from_id(host) {
+            Ok(host) => host,
+            Err(err) => {
+                let _ = writeln!(text, "  unavailable: {err}");
+                continue;
+            }
+        };
+        let devices = match host.output_devices() {
+            Ok(devices) => devices,
+            Err(err) => {
+                let _ = writeln!(text, "  unavailable: {err}");
+                continue;
+            }
+        };
+        let default_id = host
+            .default_output_device()
+            .and_then(|device| device.id().ok());
+
+        for device in devices {
+            let id = device.id().ok();
+            let marker = if id.is_some() && id == default_id {
+                " (default)"
+            } else {
+                ""
+            };
+            let name = device.description().map_or_else(
+                |_| "unknown device".to_string(),
+                |description| description.name().to_string(),
+            );
+            let _ = writeln!(text, "  {name}{marker}");
+            if let Some(id) = id {
+                let _ = writeln!(text, "    id: {id}");
+            }
+
+            match device.supported_output_configs() {
+                Ok(ranges) => {
+                    let mut configs: Vec<(String, Vec<u16>)> = Vec::new();
+                    for range in ranges {
+                        let config = describe_config(&range);
+                        match configs.last_mut() {
+                            Some((last, channels)) if *last == config => {
+                                channels.push(range.channels());
+                            }
+                            _ => configs.push((config, vec![range.channels()])),
+                        }
+                    }
+                    for (config, channels) in configs {
+                        let _ = writeln!(text, "    {}, {config}", describe_channels(&channels));
+                    }
+                }
+                Err(err) => {
+                    let _ = writeln!(text, "    configs unavailable: {err}");
+                }
+            }
+        }
+    }
+
+    text
+}
+
+/// Sample format, rates and buffer sizes of a config.
+fn describe_config(range: &SupportedStreamConfigRange) -> String {
+    let sample_rates = if range.min_sample_rate() == range.max_sample_rate() {
+        format!("{} Hz", range.min_sample_rate())
+    } else {
+        format!("{}-{} Hz", range.min_sample_rate(), range.max_sample_rate())
+    };
+    let buffer_sizes = match range.buffer_size() {
+        SupportedBufferSize::Range { min, max } => format!(", {min}-{max} frames"),
+        SupportedBufferSize::Unknown => String::new(),
+    };
+    format!("{}, {sample_rates}{buffer_sizes}", range.sample_format())
+}
+
+/// Channel counts sharing a config, as `2 channels` or `1-32 channels`.
+fn describe_channels(channels: &[u16]) -> String {
+    match channels {
+        [1] => "1 channel".to_string(),
+        [count] => format!("{count} channels"),
+        [first, .., last] if channels.windows(2).all(|pair| pair[1] == pair[0] + 1) => {
+            format!("{first}-{last} channels")
+        }
+        _ => {
+            let counts: Vec<String> = channels.iter().map(u16::to_string).collect();
+            format!("{} channels", counts.join(", "))
+        }
+    }
+}
+
+/// Whether the player can write samples of `format`.
+fn is_supported(format: SampleFormat) -> bool {
+    matches!(
+        format,
+        SampleFormat::I8
+            | SampleFormat::I16
+            | SampleFormat::I24
+            | SampleFormat::I32
+            | SampleFormat::I64
+            | SampleFormat::U8
+            | SampleFormat::U16
+            | SampleFormat::U24
+            | SampleFormat::U32
+            | SampleFormat::U64
+            | SampleFormat::F32
+            | SampleFormat::F64
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_channel_maps_counted_from_one() {
+        let map = |left, right| Ok(ChannelMap { left, right });
+        assert_eq!("3".parse(), map(2, 2));
+        assert_eq!("3,4".parse(), map(2, 3));
+        assert_eq!(" 2 , 1 ".parse(), map(1, 0));
+        assert_eq!("2,2".parse(), map(1, 1));
+        assert!("2,2".parse::<ChannelMap>().unwrap().is_mono());
+        assert!(!"1,2".parse::<ChannelMap>().unwrap().is_mono());
+    }
+
+    #[test]
+    fn rejects_malformed_channel_maps() {
+        for map in ["0", "0,1", "1,0", "", "left", "1,2,3", "65536", "-1"] {
+            assert!(map.parse::<ChannelMap>().is_err(), "{map}");
+        }
+    }
+
+    #[test]
+    fn needs_as_many_channels_as_the_highest_mapped() {
+        assert_eq!("3".parse::<ChannelMap>().unwrap().channels(), 3);
+        assert_eq!("3,4".parse::<ChannelMap>().unwrap().channels(), 4);
+        assert_eq!(ChannelMap::stereo(1), ChannelMap { left: 0, right: 0 });
+        assert_eq!(ChannelMap::stereo(8), ChannelMap { left: 0, right: 1 });
+    }
+
+    fn settings(
+        sample_rate: Option<SampleRate>,
+        buffer_size: Option<FrameCount>,
+    ) -> DeviceSettings {
+        DeviceSettings {
+            sample_rate,
+            buffer_size,
+            ..DeviceSettings::default()
+        }
+    }
+
+    #[test]
+    fn fits_configs_holding_the_rate_and_buffer_size() {
+        let buffers = SupportedBufferSize::Range { min: 64, max: 4096 };
+        let unknown = SupportedBufferSize::Unknown;
+        let format = SampleFormat::F32;
+
+        let any = DeviceSettings::default();
+        assert!(any.fits(2, format, 44_100..=48_000, &buffers));
+        assert!(any.fits(1, format, 8_000..=8_000, &unknown));
+
+        let rate = settings(Some(48_000), None);
+        assert!(rate.fits(2, format, 44_100..=96_000, &buffers));
+        assert!(!rate.fits(2, format, 44_100..=44_100, &buffers));
+
+        let buffer = settings(None, Some(256));
+        assert!(buffer.fits(2, format, 44_100..=44_100, &buffers));
+        let large = SupportedBufferSize::Range {
+            min: 512,
+            max: 4096,
+        };
+        assert!(!buffer.fits(2, format, 44_100..=44_100, &large));
+        assert!(!buffer.fits(2, format, 44_100..=44_100, &unknown));
+
+        let map = DeviceSettings {
+            channel_map: Some("3,4".parse().unwrap()),
+            ..DeviceSettings::default()
+        };
+        assert!(map.fits(4, format, 44_100..=44_100, &buffers));
+        assert!(!map.fits(2, format, 44_100..=44_100, &buffers));
+    }
+
+    #[test]
+    fn states_what_no_config_meets() {
+        assert_eq!(
+            DeviceSettings::default().requirements(),
+            "the device has no config in a supported sample format"
+        );
+
+        let settings = DeviceSettings {
+            channel_map: Some("3,4".parse().unwrap()),
+            ..settings(Some(48_000), Some(256))
+        };
+        assert_eq!(
+            settings.requirements(),
+            "the device has no config with 4 channels, 48000 Hz, 256-frame buffers in a \
+             supported sample format"
+        );
+    }
+
+    #[test]
+    fn groups_channel_counts() {
+        assert_eq!(describe_channels(&[1]), "1 channel");
+        assert_eq!(describe_channels(&[2]), "2 channels");
+        assert_eq!(describe_channels(&[1, 2, 3, 4]), "1-4 channels");
+        assert_eq!(describe_channels(&[1, 2, 4]), "1, 2, 4 channels");
+        assert_eq!(describe_channels(&[2, 6, 8]), "2, 6, 8 channels");
+    }
+
+    #[test]
+    fn finds_devices_by_id_then_name_then_unique_substring() {
+        let devices = vec![
+            ("alsa:default", "Default"),
+            ("alsa:hw:1", "USB Audio"),
+            ("alsa:hw:2", "USB Audio Pro"),
+            ("alsa:pulse", "PulseAudio"),
+            ("Default", "Speakers"),
+        ];
+        let find = |name: &str| {
+            find_device(
+                devices.clone(),
+                name,
+                |device| Some(device.0.to_string()),
+                |device| Some(device.1.to_string()),
+            )
+        };
+
+        assert_eq!(find("alsa:hw:2").unwrap().1, "USB Audio Pro");
+        assert_eq!(find("Default").unwrap().1, "Speakers");
+        assert_eq!(find("usb audio").unwrap().1, "USB Audio");
+        assert_eq!(find("pulse").unwrap().1, "PulseAudio");
+        assert!(matches!(
+            find("usb"),
+            Err(Error::AmbiguousDevice(name, matches))
+                if name == "usb" && matches == ["USB Audio", "USB Audio Pro"]
+        ));
+        assert!(matches!(find("hdmi"), Err(Error::DeviceNotFound(_))));
+    }
+}
@@ -14,8 +14,22 @@
  * limitations under the License.
  */
 
+mod device;
 mod player;
+mod renderer;
+mod ring;
+mod sequencer;
 mod soundfont;
+mod transport;
+mod tui;
+mod wav;
 
-pub use player::Player;
+pub use device::{ChannelMap, DeviceSettings, describe_devices};
+pub use player::{Playback, Player, format_time};
+pub use renderer::{Audio, Renderer};
+pub use ring::SampleRing;
+pub use sequencer::{build_sequencer, build_synthesizer};
 pub use soundfont::SoundFont;
+pub use transport::{Mix, PlaybackHandle, PlaybackState};
+pub use tui::{Action, Terminal, Tui};
+pub use wav::{WavFormat, encode_wav};
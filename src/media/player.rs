@@ -14,129 +14,297 @@
  * limitations under the License.
  */
 
-use crate::{Error, Result, SoundFont};
+use super::{
+    Action, ChannelMap, DeviceSettings, Mix, PlaybackHandle, PlaybackState, SampleRing, Terminal,
+    Tui,
+};
+use crate::{Error, Result, Score, SoundFont};
 use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait}, Device, FromSample, OutputCallbackInfo, SampleFormat, SizedSample, Stream,
-    StreamConfig,
+    Device, FromSample, I24, OutputCallbackInfo, SampleFormat, SizedSample, Stream, StreamConfig,
+    U24,
+    traits::{DeviceTrait, StreamTrait},
 };
-use midly::{Format, Fps, MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
-use rustysynth::{MidiFile, MidiFileSequencer, Synthesizer, SynthesizerSettings};
 use std::{
-    io::Cursor,
-    sync::{Arc, Mutex},
-    thread,
-    time::Duration,
+    fmt::{self, Debug, Formatter},
+    io::{self, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+/// Frames synthesized ahead of the audio callback, about 90 ms at 44.1 kHz.
+const RING_FRAMES: usize = 4096;
+/// Frames the synthesis thread renders at once.
+const RENDER_FRAMES: usize = 512;
+/// Time left for released notes to fade out once playback finishes.
+const RELEASE: Duration = Duration::from_millis(500);
+/// Longest wait for the device to play the frames left in the ring, in case
+/// it stopped pulling them.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Commands read from the terminal during playback, each followed by Enter.
+const COMMANDS: &str = "p pause/resume  s stop  b BAR[:BEAT] go to bar  t SECONDS go to time  \
+                        x FACTOR speed  q quit";
+
+/// Passage to play and how to play it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Playback {
+    /// First bar played, counted from zero.
+    pub start_bar: u32,
+    /// Bar where playback ends, counted from zero and left out, or the end of
+    /// the piece.
+    pub end_bar: Option<u32>,
+    pub looping: bool,
+    pub speed: f64,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            start_bar: 0,
+            end_bar: None,
+            looping: false,
+            speed: 1.0,
+        }
+    }
+}
+
+/// An output stream playing, and the thread synthesizing ahead of it into
+/// the ring its callback reads. The thread stops when the stream is dropped.
+pub struct AudioStream {
+    _stream: Stream,
+    ring: Arc<SampleRing>,
+    running: Arc<AtomicBool>,
+    synthesis: Option<JoinHandle<()>>,
+}
+
+impl Debug for AudioStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AudioStream")
+            .field("ring", &self.ring)
+            .field("running", &self.running.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl AudioStream {
+    pub fn ring(&self) -> Arc<SampleRing> {
+        Arc::clone(&self.ring)
+    }
+
+    /// Callbacks that found the ring empty and played silence.
+    pub fn underruns(&self) -> u64 {
+        self.ring.underruns()
+    }
+
+    /// Stops synthesizing and waits for the device to play what is left in
+    /// the ring before the stream is dropped.
+    pub fn drain(mut self) {
+        self.stop_synthesis();
+        let deadline = Instant::now() + DRAIN_TIMEOUT;
+        while !self.ring.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn stop_synthesis(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(synthesis) = self.synthesis.take() {
+            let _ = synthesis.join();
+        }
+    }
+}
+
+impl Drop for AudioStream {
+    fn drop(&mut self) {
+        self.stop_synthesis();
+    }
+}
+
 #[derive(Debug)]
 pub struct Player {
-    smf: Smf<'static>,
+    score: Score,
     soundfont: SoundFont,
+    playback: Playback,
+    device: DeviceSettings,
 }
 
 impl Player {
-    pub fn new(smf: Smf<'static>, soundfont: SoundFont) -> Result<Self> {
-        Ok(Self { smf, soundfont })
+    pub fn new(score: Score, soundfont: SoundFont) -> Result<Self> {
+        Ok(Self {
+            score,
+            soundfont,
+            playback: Playback::default(),
+            device: DeviceSettings::default(),
+        })
     }
 
-    pub fn run(&self) -> Result<()> {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .ok_or_else(|| Error::AudioPlayback("No output device available".to_string()))?;
+    pub fn playback(mut self, playback: Playback) -> Self {
+        self.playback = playback;
+        self
+    }
 
-        let config = device.default_output_config().map_err(|err| {
-            Error::AudioPlayback(format!("Failed to get default output config: {err}"))
-        })?;
+    pub fn device(mut self, device: DeviceSettings) -> Self {
+        self.device = device;
+        self
+    }
 
-        let sample_rate = config.sample_rate();
+    pub fn run(&self) -> Result<()> {
+        let (stream, handle) = self.start(&self.score)?;
 
-        let mut cursor = Cursor::new(self.soundfont.get_bytes());
-        let soundfont = Arc::new(
-            rustysynth::SoundFont::new(&mut cursor)
-                .map_err(|err| Error::AudioPlayback(format!("Failed to load soundfont: {err}")))?,
-        );
+        let tempo_map = handle.tempo_map();
+        let duration = handle.duration();
+        let commands = read_commands();
+        println!("{COMMANDS}");
 
-        let settings = SynthesizerSettings::new(sample_rate as i32);
-        let synthesizer = Synthesizer::new(&soundfont, &settings)
-            .map_err(|err| Error::AudioPlayback(format!("Failed to create synthesizer: {err}")))?;
+        loop {
+            if handle.state() == PlaybackState::Finished {
+                break;
+            }
+            if commands
+                .try_iter()
+                .any(|command| !apply_command(&handle, command.trim()))
+            {
+                handle.stop();
+                break;
+            }
+            let seconds = handle.position();
+
+            let position = tempo_map.position(tempo_map.tick_at_seconds(seconds));
+            let speed = handle.speed();
+            let speed = if speed == 1.0 {
+                String::new()
+            } else {
+                format!("  x{speed:.2}")
+            };
+            let underruns = match stream.underruns() {
+                0 => String::new(),
+                underruns => format!("  underruns {underruns}"),
+            };
+            print!(
+                "\rBar {position}  {} / {}{speed}{underruns}    ",
+                format_time(seconds),
+                format_time(duration)
+            );
+            let _ = io::stdout().flush();
 
-        let midi_data = self.convert_midly_to_bytes()?;
-        let midi_file = Arc::new(
-            MidiFile::new(&mut Cursor::new(midi_data))
-                .map_err(|err| Error::AudioPlayback(format!("Failed to parse MIDI: {err}")))?,
-        );
+            thread::sleep(Duration::from_millis(100));
+        }
+        println!();
 
-        let mut sequencer = MidiFileSequencer::new(synthesizer);
-        sequencer.play(&midi_file, false);
+        thread::sleep(RELEASE);
+        stream.drain();
 
-        let sequencer = Arc::new(Mutex::new(sequencer));
-        let sequencer_clone = sequencer.clone();
+        Ok(())
+    }
 
-        let stream = match config.sample_format() {
-            SampleFormat::F32 => {
-                self.build_stream::<f32>(&device, &config.into(), sequencer_clone)?
-            }
-            SampleFormat::I16 => {
-                self.build_stream::<i16>(&device, &config.into(), sequencer_clone)?
-            }
-            SampleFormat::U16 => {
-                self.build_stream::<u16>(&device, &config.into(), sequencer_clone)?
-            }
-            _ => {
-                return Err(Error::AudioPlayback(
-                    "Unsupported sample format".to_string(),
-                ));
+    /// Plays in the interactive terminal player until the listener quits,
+    /// composing a new piece with `regenerate` whenever asked to. Mutes and
+    /// solos carry over to the new piece.
+    pub fn run_interactive(
+        &self,
+        seed: u64,
+        mut regenerate: impl FnMut() -> Result<(Score, u64)>,
+    ) -> Result<()> {
+        let _terminal = Terminal::enter()?;
+        let mut score = self.score.clone();
+        let mut seed = seed;
+        let mut mix = Mix::default();
+
+        loop {
+            let (stream, handle) = self.start(&score)?;
+            handle.set_mix(mix);
+
+            match Tui::new(score.clone(), handle.clone(), stream.ring(), seed).run()? {
+                Action::Quit => return Ok(()),
+                Action::Regenerate => {
+                    mix = handle.mix();
+                    (score, seed) = regenerate()?;
+                }
             }
+        }
+    }
+
+    /// Opens the output device and starts playing `score` on it with the
+    /// playback settings.
+    fn start(&self, score: &Score) -> Result<(AudioStream, PlaybackHandle)> {
+        let device = self.device.output_device()?;
+        let config = self.device.output_config(&device)?;
+        let sample_rate = config.sample_rate();
+        let map = self
+            .device
+            .channel_map
+            .unwrap_or_else(|| ChannelMap::stereo(config.channels()));
+
+        let handle = PlaybackHandle::new(&score.to_smf(), &self.soundfont, sample_rate)?;
+        handle.set_section(self.playback.start_bar, self.playback.end_bar);
+        handle.set_looping(self.playback.looping);
+        handle.set_speed(self.playback.speed);
+        handle.play();
+
+        let ring = Arc::new(SampleRing::new(RING_FRAMES));
+        let format = config.sample_format();
+        let config = self.device.stream_config(&config);
+        let stream = match format {
+            SampleFormat::I8 => self.build_stream::<i8>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::I16 => self.build_stream::<i16>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::I24 => self.build_stream::<I24>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::I32 => self.build_stream::<i32>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::I64 => self.build_stream::<i64>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::U8 => self.build_stream::<u8>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::U16 => self.build_stream::<u16>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::U24 => self.build_stream::<U24>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::U32 => self.build_stream::<u32>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::U64 => self.build_stream::<u64>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::F32 => self.build_stream::<f32>(&device, &config, map, Arc::clone(&ring)),
+            SampleFormat::F64 => self.build_stream::<f64>(&device, &config, map, Arc::clone(&ring)),
+            format => Err(Error::AudioPlayback(format!(
+                "Unsupported sample format: {format}"
+            ))),
+        }?;
+        prime(&handle, &ring);
+
+        let running = Arc::new(AtomicBool::new(true));
+        let synthesis = {
+            let (handle, ring, running) = (handle.clone(), Arc::clone(&ring), Arc::clone(&running));
+            thread::spawn(move || synthesize(&handle, &ring, &running))
+        };
+        let output = AudioStream {
+            _stream: stream,
+            ring,
+            running,
+            synthesis: Some(synthesis),
         };
 
-        stream
+        output
+            ._stream
             .play()
             .map_err(|err| Error::AudioPlayback(format!("Failed to play stream: {err}")))?;
 
-        while !sequencer.lock().unwrap().end_of_sequence() {
-            thread::sleep(Duration::from_millis(100));
-        }
-
-        thread::sleep(Duration::from_millis(500));
-
-        Ok(())
+        Ok((output, handle))
     }
 
+    /// Builds a stream writing the frames of `ring` to the channels of
+    /// `map`.
     fn build_stream<T: SizedSample + FromSample<f32>>(
         &self,
         device: &Device,
         config: &StreamConfig,
-        sequencer: Arc<Mutex<MidiFileSequencer>>,
+        map: ChannelMap,
+        ring: Arc<SampleRing>,
     ) -> Result<Stream> {
-        let channels = config.channels as usize;
-        let mut left = vec![0_f32; 4096];
-        let mut right = vec![0_f32; 4096];
+        let channels = usize::from(config.channels);
 
         let stream = device
             .build_output_stream(
                 config,
                 move |data: &mut [T], _: &OutputCallbackInfo| {
-                    let mut sequencer = sequencer.lock().unwrap();
-
-                    for chunk in data.chunks_mut(channels) {
-                        if left.is_empty() {
-                            left.resize(4096, 0_f32);
-                            right.resize(4096, 0_f32);
-                            sequencer.render(&mut left, &mut right);
-                        }
-
-                        let left_sample = left.remove(0);
-                        let right_sample = right.remove(0);
-
-                        if channels == 1 {
-                            chunk[0] = T::from_sample((left_sample + right_sample) * 0.5);
-                        } else {
-                            chunk[0] = T::from_sample(left_sample);
-                            if chunk.len() > 1 {
-                                chunk[1] = T::from_sample(right_sample);
-                            }
-                        }
+                    if !write_frames(data, channels, map, || ring.pop()) {
+                        ring.record_underrun();
                     }
                 },
                 |err| eprintln!("Stream error: {err}"),
@@ -146,195 +314,193 @@ impl Player {
 
         Ok(stream)
     }
+}
 
-    fn convert_midly_to_bytes(&self) -> Result<Vec<u8>> {
-        let mut output = Vec::new();
-
-        output.extend_from_slice(b"MThd");
-        output.extend_from_slice(&6_u32.to_be_bytes());
-
-        let format = match self.smf.header.format {
-            Format::SingleTrack => 0_u16,
-            Format::Parallel => 1_u16,
-            Format::Sequential => 2_u16,
+/// Writes the frames of `next` to the interleaved `data` of a device with
+/// `channels` channels: the left and right sides go to the channels of
+/// `map`, mixed down when they share one, and every other channel is
+/// silent. Frames `next` runs out of are silent too, and `false` is
+/// returned.
+fn write_frames<T: SizedSample + FromSample<f32>>(
+    data: &mut [T],
+    channels: usize,
+    map: ChannelMap,
+    mut next: impl FnMut() -> Option<(f32, f32)>,
+) -> bool {
+    let (left, right) = (usize::from(map.left), usize::from(map.right));
+    let mut complete = true;
+
+    for frame in data.chunks_mut(channels.max(1)) {
+        frame.fill(T::EQUILIBRIUM);
+        let Some((left_sample, right_sample)) = next() else {
+            complete = false;
+            continue;
         };
-        output.extend_from_slice(&format.to_be_bytes());
-
-        let num_tracks = self.smf.tracks.len() as u16;
-        output.extend_from_slice(&num_tracks.to_be_bytes());
-
-        let timing = match self.smf.header.timing {
-            Timing::Metrical(ticks) => ticks.as_int(),
-            Timing::Timecode(fps, ticks) => {
-                let fps_val = match fps {
-                    Fps::Fps24 => -24_i8,
-                    Fps::Fps25 => -25_i8,
-                    Fps::Fps29 => -29_i8,
-                    Fps::Fps30 => -30_i8,
-                };
-                ((fps_val as u8 as u16) << 8) | (ticks as u16)
+
+        if map.is_mono() {
+            if let Some(sample) = frame.get_mut(left) {
+                *sample = T::from_sample((left_sample + right_sample) * 0.5);
             }
-        };
-        output.extend_from_slice(&timing.to_be_bytes());
-
-        for track in &self.smf.tracks {
-            output.extend_from_slice(b"MTrk");
-
-            let mut track_data = Vec::new();
-            let mut running_status: Option<u8> = None;
-
-            for event in track {
-                self.write_variable_length(&mut track_data, event.delta.as_int());
-
-                match event.kind {
-                    TrackEventKind::Midi { channel, message } => {
-                        let channel = channel.as_int();
-                        match message {
-                            MidiMessage::NoteOff { key, vel } => {
-                                let status = 0x80 | channel;
-                                if running_status != Some(status) {
-                                    track_data.push(status);
-                                    running_status = Some(status);
-                                }
-                                track_data.push(key.as_int());
-                                track_data.push(vel.as_int());
-                            }
-                            MidiMessage::NoteOn { key, vel } => {
-                                let status = 0x90 | channel;
-                                if running_status != Some(status) {
-                                    track_data.push(status);
-                                    running_status = Some(status);
-                                }
-                                track_data.push(key.as_int());
-                                track_data.push(vel.as_int());
-                            }
-                            MidiMessage::Aftertouch { key, vel } => {
-                                let status = 0xA0 | channel;
-                                if running_status != Some(status) {
-                                    track_data.push(status);
-                                    running_status = Some(status);
-                                }
-                                track_data.push(key.as_int());
-                                track_data.push(vel.as_int());
-                            }
-                            MidiMessage::Controller { controller, value } => {
-                                let status = 0xB0 | channel;
-                                if running_status != Some(status) {
-                                    track_data.push(status);
-                                    running_status = Some(status);
-                                }
-                                track_data.push(controller.as_int());
-                                track_data.push(value.as_int());
-                            }
-                            MidiMessage::ProgramChange { program } => {
-                                let status = 0xC0 | channel;
-                                if running_status != Some(status) {
-                                    track_data.push(status);
-                                    running_status = Some(status);
-                                }
-                                track_data.push(program.as_int());
-                            }
-                            MidiMessage::ChannelAftertouch { vel } => {
-                                let status = 0xD0 | channel;
-                                if running_status != Some(status) {
-                                    track_data.push(status);
-                                    running_status = Some(status);
-                                }
-                                track_data.push(vel.as_int());
-                            }
-                            MidiMessage::PitchBend { bend } => {
-                                let status = 0xE0 | channel;
-                                if running_status != Some(status) {
-                                    track_data.push(status);
-                                    running_status = Some(status);
-                                }
-                                let value = (bend.as_int() + 8192) as u16;
-                                track_data.push((value & 0x7F) as u8);
-                                track_data.push(((value >> 7) & 0x7F) as u8);
-                            }
-                        }
-                    }
-                    TrackEventKind::Meta(msg) => {
-                        running_status = None;
-                        track_data.push(0xFF);
-                        match msg {
-                            MetaMessage::Tempo(tempo) => {
-                                track_data.push(0x51);
-                                track_data.push(0x03);
-                                let bytes = tempo.as_int().to_be_bytes();
-                                track_data.extend_from_slice(&bytes[1..4]);
-                            }
-                            MetaMessage::EndOfTrack => {
-                                track_data.push(0x2F);
-                                track_data.push(0x00);
-                            }
-                            MetaMessage::TimeSignature(num, denom, clocks, thirty_seconds) => {
-                                track_data.push(0x58);
-                                track_data.push(0x04);
-                                track_data.push(num);
-                                track_data.push(denom);
-                                track_data.push(clocks);
-                                track_data.push(thirty_seconds);
-                            }
-                            MetaMessage::KeySignature(key, scale) => {
-                                track_data.push(0x59);
-                                track_data.push(0x02);
-                                track_data.push(key as u8);
-                                track_data.push(if scale { 0 } else { 1 });
-                            }
-                            MetaMessage::TrackName(name) => {
-                                track_data.push(0x03);
-                                self.write_variable_length(&mut track_data, name.len() as u32);
-                                track_data.extend_from_slice(name);
-                            }
-                            MetaMessage::Text(text) | MetaMessage::Copyright(text) => {
-                                track_data.push(0x01);
-                                self.write_variable_length(&mut track_data, text.len() as u32);
-                                track_data.extend_from_slice(text);
-                            }
-                            _ => {}
-                        }
-                    }
-                    TrackEventKind::SysEx(data) => {
-                        running_status = None;
-                        track_data.push(0xF0);
-                        self.write_variable_length(&mut track_data, data.len() as u32);
-                        track_data.extend_from_slice(data);
-                    }
-                    TrackEventKind::Escape(data) => {
-                        running_status = None;
-                        track_data.push(0xF7);
-                        self.write_variable_length(&mut track_data, data.len() as u32);
-                        track_data.extend_from_slice(data);
-                    }
-                }
+        } else {
+            if let Some(sample) = frame.get_mut(left) {
+                *sample = T::from_sample(left_sample);
+            }
+            if let Some(sample) = frame.get_mut(right) {
+                *sample = T::from_sample(right_sample);
             }
-
-            output.extend_from_slice(&(track_data.len() as u32).to_be_bytes());
-            output.extend_from_slice(&track_data);
         }
-
-        Ok(output)
     }
+    complete
+}
 
-    fn write_variable_length(&self, output: &mut Vec<u8>, mut value: u32) {
-        let mut buffer = [0u8; 4];
-        let mut n = 0;
+/// Fills the ring before the stream starts, so its first callbacks find
+/// frames instead of counting underruns.
+fn prime(handle: &PlaybackHandle, ring: &SampleRing) {
+    let mut left = vec![0_f32; RENDER_FRAMES];
+    let mut right = vec![0_f32; RENDER_FRAMES];
+    while ring.free() >= RENDER_FRAMES {
+        handle.render(&mut left, &mut right);
+        ring.push(&left, &right);
+    }
+}
 
-        buffer[n] = (value & 0x7F) as u8;
-        value >>= 7;
+/// Keeps the ring filled with frames of the playback until `running` is
+/// cleared. Only this thread locks the transport, so the audio callback
+/// never waits on it.
+fn synthesize(handle: &PlaybackHandle, ring: &SampleRing, running: &AtomicBool) {
+    let mut left = vec![0_f32; RENDER_FRAMES];
+    let mut right = vec![0_f32; RENDER_FRAMES];
+
+    while running.load(Ordering::Relaxed) {
+        if ring.free() < RENDER_FRAMES {
+            thread::sleep(Duration::from_millis(1));
+            continue;
+        }
+        handle.render(&mut left, &mut right);
+        ring.push(&left, &right);
+    }
+}
 
-        while value > 0 {
-            n += 1;
-            buffer[n] = ((value & 0x7F) | 0x80) as u8;
-            value >>= 7;
+/// Reads the commands typed during playback, one per line, on a thread of
+/// its own so the display keeps updating.
+fn read_commands() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
         }
+    });
+    receiver
+}
 
-        while n > 0 {
-            output.push(buffer[n]);
-            n -= 1;
+/// Applies a command typed during playback, returning `false` to quit.
+/// Bars and beats are counted from 1, as displayed.
+fn apply_command(handle: &PlaybackHandle, command: &str) -> bool {
+    let (name, argument) = command
+        .split_once(' ')
+        .map_or((command, ""), |(name, argument)| (name, argument.trim()));
+
+    match name {
+        "p" if handle.state() == PlaybackState::Playing => handle.pause(),
+        "p" => handle.play(),
+        "s" => handle.stop(),
+        "b" => {
+            let (bar, beat) = argument.split_once(':').unwrap_or((argument, "1"));
+            if let (Ok(bar @ 1..), Ok(beat)) = (bar.parse::<u32>(), beat.parse::<f64>()) {
+                handle.seek_bar(bar - 1, (beat - 1.0).max(0.0));
+            }
         }
+        "t" => {
+            if let Ok(seconds) = argument.parse::<f64>() {
+                handle.seek(seconds);
+            }
+        }
+        "x" => {
+            if let Ok(speed) = argument.parse::<f64>()
+                && speed.is_finite()
+                && speed > 0.0
+            {
+                handle.set_speed(speed);
+            }
+        }
+        "q" => return false,
+        _ => {}
+    }
+    true
+}
+
+pub fn format_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(frames: &[(f32, f32)]) -> impl FnMut() -> Option<(f32, f32)> + '_ {
+        let mut frames = frames.iter().copied();
+        move || frames.next()
+    }
+
+    #[test]
+    fn writes_both_sides_to_their_channels() {
+        let map = "3,2".parse().unwrap();
+        let mut data = [9.0_f32; 8];
+        assert!(write_frames(
+            &mut data,
+            4,
+            map,
+            frames(&[(0.5, -0.5), (0.25, 1.0)])
+        ));
+        assert_eq!(data, [0.0, -0.5, 0.5, 0.0, 0.0, 1.0, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn mixes_down_to_a_single_channel() {
+        let map = "2".parse().unwrap();
+        let mut data = [9.0_f32; 6];
+        assert!(write_frames(
+            &mut data,
+            3,
+            map,
+            frames(&[(0.5, 0.25), (-1.0, 1.0)])
+        ));
+        assert_eq!(data, [0.0, 0.375, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn fills_missing_frames_with_silence() {
+        let mut data = [9_i16; 6];
+        let written = write_frames(&mut data, 2, ChannelMap::stereo(2), frames(&[(1.0, -1.0)]));
+        assert!(!written);
+        assert_eq!(data, [i16::MAX, i16::MIN, 0, 0, 0, 0]);
+
+        let mut data = [9_u8; 2];
+        assert!(!write_frames(
+            &mut data,
+            2,
+            ChannelMap::stereo(2),
+            frames(&[])
+        ));
+        assert_eq!(data, [128, 128]);
+    }
 
-        output.push(buffer[0]);
+    #[test]
+    fn leaves_out_channels_the_device_lacks() {
+        let map = "1,3".parse().unwrap();
+        let mut data = [9.0_f32; 4];
+        assert!(write_frames(
+            &mut data,
+            2,
+            map,
+            frames(&[(0.5, 0.25), (1.0, 1.0)])
+        ));
+        assert_eq!(data, [0.5, 0.0, 1.0, 0.0]);
     }
 }
@@ -0,0 +1,100 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::build_sequencer;
+use crate::{Error, Result, SoundFont};
+use midly::Smf;
+
+const BLOCK_SECONDS: f64 = 0.1;
+const SILENCE_THRESHOLD: f32 = 1.0e-4;
+
+/// Stereo samples produced by [`Renderer::render`].
+#[derive(Debug, Clone, Default)]
+pub struct Audio {
+    pub sample_rate: u32,
+    pub left: Vec<f32>,
+    pub right: Vec<f32>,
+}
+
+/// Synthesizes a score offline, as fast as the CPU allows, without any audio
+/// device.
+#[derive(Debug)]
+pub struct Renderer<'a> {
+    smf: Smf<'a>,
+    soundfont: SoundFont,
+    sample_rate: u32,
+    max_tail: f64,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(
+        smf: Smf<'a>,
+        soundfont: SoundFont,
+        sample_rate: u32,
+        max_tail: f64,
+    ) -> Result<Self> {
+        if !max_tail.is_finite() || max_tail < 0.0 {
+            return Err(Error::InvalidRender(format!(
+                "expected a tail of zero seconds or more, found {max_tail}"
+            )));
+        }
+
+        Ok(Self {
+            smf,
+            soundfont,
+            sample_rate,
+            max_tail,
+        })
+    }
+
+    /// Renders the whole sequence, then keeps rendering the reverb and chorus
+    /// tail until it decays to silence or `max_tail` seconds have elapsed.
+    pub fn render(&self) -> Result<Audio> {
+        let mut sequencer = build_sequencer(&self.smf, &self.soundfont, self.sample_rate)?;
+
+        let block_len = ((self.sample_rate as f64 * BLOCK_SECONDS) as usize).max(1);
+        let max_tail_blocks = (self.max_tail / BLOCK_SECONDS).ceil() as usize;
+
+        let mut left_block = vec![0_f32; block_len];
+        let mut right_block = vec![0_f32; block_len];
+        let mut audio = Audio {
+            sample_rate: self.sample_rate,
+            ..Audio::default()
+        };
+
+        while !sequencer.end_of_sequence() {
+            sequencer.render(&mut left_block, &mut right_block);
+            audio.left.extend_from_slice(&left_block);
+            audio.right.extend_from_slice(&right_block);
+        }
+
+        for _ in 0..max_tail_blocks {
+            sequencer.render(&mut left_block, &mut right_block);
+            audio.left.extend_from_slice(&left_block);
+            audio.right.extend_from_slice(&right_block);
+
+            let peak = left_block
+                .iter()
+                .chain(&right_block)
+                .fold(0_f32, |peak, sample| peak.max(sample.abs()));
+            if peak < SILENCE_THRESHOLD {
+                break;
+            }
+        }
+
+        Ok(audio)
+    }
+}
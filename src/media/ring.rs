@@ -0,0 +1,149 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+/// Stereo frames passed from one producer thread to one consumer thread
+/// without locks or allocation. Samples are stored as the bits of their
+/// `f32` value, and the read and write counters only ever grow, so the ring
+/// is empty when they are equal and full when they are `capacity` apart.
+#[derive(Debug)]
+pub struct SampleRing {
+    /// Interleaved left and right samples.
+    samples: Box<[AtomicU32]>,
+    capacity: usize,
+    read: AtomicUsize,
+    write: AtomicUsize,
+    underruns: AtomicU64,
+}
+
+impl SampleRing {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            samples: (0..capacity * 2).map(|_| AtomicU32::new(0)).collect(),
+            capacity,
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+            underruns: AtomicU64::new(0),
+        }
+    }
+
+    /// Frames the producer can push without overwriting unread ones.
+    pub fn free(&self) -> usize {
+        let read = self.read.load(Ordering::Acquire);
+        let write = self.write.load(Ordering::Relaxed);
+        self.capacity - write.wrapping_sub(read)
+    }
+
+    /// Whether the consumer has read every frame pushed.
+    pub fn is_empty(&self) -> bool {
+        self.free() == self.capacity
+    }
+
+    /// Pushes as many frames of `left` and `right` as there is room for,
+    /// returning how many were pushed. Only the producer may call it.
+    pub fn push(&self, left: &[f32], right: &[f32]) -> usize {
+        let write = self.write.load(Ordering::Relaxed);
+        let count = self.free().min(left.len()).min(right.len());
+
+        for (offset, (left, right)) in left.iter().zip(right).take(count).enumerate() {
+            let index = (write.wrapping_add(offset) % self.capacity) * 2;
+            self.samples[index].store(left.to_bits(), Ordering::Relaxed);
+            self.samples[index + 1].store(right.to_bits(), Ordering::Relaxed);
+        }
+
+        self.write
+            .store(write.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// Pops the oldest frame, if any. Only the consumer may call it.
+    pub fn pop(&self) -> Option<(f32, f32)> {
+        let read = self.read.load(Ordering::Relaxed);
+        if read == self.write.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let index = (read % self.capacity) * 2;
+        let left = f32::from_bits(self.samples[index].load(Ordering::Relaxed));
+        let right = f32::from_bits(self.samples[index + 1].load(Ordering::Relaxed));
+        self.read.store(read.wrapping_add(1), Ordering::Release);
+        Some((left, right))
+    }
+
+    /// Counts a callback that ran out of frames and played silence instead.
+    pub fn record_underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn keeps_order_across_the_wrap() {
+        let ring = SampleRing::new(4);
+        assert_eq!(ring.push(&[1.0, 2.0, 3.0], &[-1.0, -2.0, -3.0]), 3);
+        assert_eq!(ring.pop(), Some((1.0, -1.0)));
+        assert_eq!(ring.pop(), Some((2.0, -2.0)));
+
+        assert_eq!(
+            ring.push(&[4.0, 5.0, 6.0, 7.0], &[-4.0, -5.0, -6.0, -7.0]),
+            3
+        );
+        assert_eq!(ring.free(), 0);
+        let frames: Vec<_> = std::iter::from_fn(|| ring.pop()).collect();
+        assert_eq!(frames, [(3.0, -3.0), (4.0, -4.0), (5.0, -5.0), (6.0, -6.0)]);
+        assert_eq!(ring.pop(), None);
+        assert_eq!(ring.free(), 4);
+    }
+
+    #[test]
+    fn passes_every_frame_between_threads() {
+        let frames: Vec<f32> = (0..20_000).map(|frame| frame as f32).collect();
+        let ring = Arc::new(SampleRing::new(64));
+
+        let producer = {
+            let (ring, frames) = (Arc::clone(&ring), frames.clone());
+            thread::spawn(move || {
+                let mut next = 0;
+                while next < frames.len() {
+                    next += ring.push(&frames[next..], &frames[next..]);
+                    thread::yield_now();
+                }
+            })
+        };
+
+        for &expected in &frames {
+            let frame = loop {
+                match ring.pop() {
+                    Some(frame) => break frame,
+                    None => thread::yield_now(),
+                }
+            };
+            assert_eq!(frame, (expected, expected));
+        }
+        producer.join().unwrap();
+        assert_eq!(ring.pop(), None);
+    }
+}
@@ -0,0 +1,54 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Error, Result, SoundFont, Writer};
+use midly::Smf;
+use rustysynth::{MidiFile, MidiFileSequencer, Synthesizer, SynthesizerSettings};
+use std::{io::Cursor, sync::Arc};
+
+/// Loads `soundfont` into a synthesizer running at `sample_rate`.
+pub fn build_synthesizer(soundfont: &SoundFont, sample_rate: u32) -> Result<Synthesizer> {
+    let mut cursor = Cursor::new(soundfont.get_bytes());
+    let soundfont = Arc::new(
+        rustysynth::SoundFont::new(&mut cursor)
+            .map_err(|err| Error::Synthesizer(format!("Failed to load soundfont: {err}")))?,
+    );
+
+    let settings = SynthesizerSettings::new(sample_rate as i32);
+    Synthesizer::new(&soundfont, &settings)
+        .map_err(|err| Error::Synthesizer(format!("Failed to create synthesizer: {err}")))
+}
+
+/// Loads `soundfont` into a synthesizer running at `sample_rate` and starts
+/// sequencing `smf` on it.
+pub fn build_sequencer(
+    smf: &Smf,
+    soundfont: &SoundFont,
+    sample_rate: u32,
+) -> Result<MidiFileSequencer> {
+    let synthesizer = build_synthesizer(soundfont, sample_rate)?;
+
+    let midi_data = Writer::new().write(smf)?;
+    let midi_file = Arc::new(
+        MidiFile::new(&mut Cursor::new(midi_data))
+            .map_err(|err| Error::Synthesizer(format!("Failed to parse MIDI: {err}")))?,
+    );
+
+    let mut sequencer = MidiFileSequencer::new(synthesizer);
+    sequencer.play(&midi_file, false);
+
+    Ok(sequencer)
+}
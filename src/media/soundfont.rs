@@ -28,6 +28,15 @@ pub enum SoundFont {
 }
 
 impl SoundFont {
+    /// Picks the custom soundfont file when one is given, the built-in
+    /// soundfont called `name` otherwise.
+    pub fn new(name: &str, custom_file: Option<&PathBuf>) -> Result<Self> {
+        match custom_file {
+            Some(file) => Self::new_from_file(file),
+            None => Self::new_from_name(name),
+        }
+    }
+
     pub fn new_from_name(name: &str) -> Result<SoundFont> {
         match name {
             "default" | "piano" => Ok(SoundFont::Piano),
@@ -0,0 +1,611 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::build_synthesizer;
+use crate::{Position, Result, SoundFont, TempoMap};
+use midly::{MidiMessage, Smf, TrackEventKind};
+use rustysynth::Synthesizer;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Where the transport stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    /// Stopped and rewound to the start of the section.
+    Stopped,
+    /// Past the end of the section without looping.
+    Finished,
+}
+
+/// Muted and soloed channels. While any channel is soloed, only soloed
+/// channels that are not muted are heard.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    pub muted: [bool; 16],
+    pub soloed: [bool; 16],
+}
+
+impl Mix {
+    pub fn audible(&self, channel: u8) -> bool {
+        let channel = usize::from(channel);
+        !self.muted[channel] && (self.soloed[channel] || !self.soloed.contains(&true))
+    }
+}
+
+/// Channel message of the file with the time it is due, in seconds.
+#[derive(Debug, Clone, Copy)]
+struct Message {
+    seconds: f64,
+    /// Track and index of the event in it, ordering messages due together
+    /// as the file does.
+    order: (usize, usize),
+    channel: u8,
+    command: u8,
+    data1: u8,
+    data2: u8,
+}
+
+impl Message {
+    fn is_note(&self) -> bool {
+        matches!(self.command, 0x80 | 0x90 | 0xA0)
+    }
+}
+
+/// Drives a synthesizer through the messages of a file. Unlike the
+/// sequencer of rustysynth it can jump anywhere in the file, loop over a
+/// section and change speed while playing.
+struct Transport {
+    synthesizer: Synthesizer,
+    messages: Vec<Message>,
+    /// Index of the first message not sent yet.
+    next: usize,
+    /// Position in the file, in seconds at normal speed.
+    position: f64,
+    /// Samples of the current synthesizer block already rendered.
+    block_wrote: usize,
+    state: PlaybackState,
+    speed: f64,
+    /// Section played, in seconds.
+    start: f64,
+    end: f64,
+    looping: bool,
+    mix: Mix,
+}
+
+impl Transport {
+    fn new(synthesizer: Synthesizer, messages: Vec<Message>, duration: f64) -> Self {
+        Self {
+            block_wrote: synthesizer.get_block_size(),
+            synthesizer,
+            messages,
+            next: 0,
+            position: 0.0,
+            state: PlaybackState::Paused,
+            speed: 1.0,
+            start: 0.0,
+            end: duration,
+            looping: false,
+            mix: Mix::default(),
+        }
+    }
+
+    /// Renders the next samples. Outside of playback the synthesizer keeps
+    /// rendering, so released notes fade out instead of being cut.
+    fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let block_size = self.synthesizer.get_block_size();
+        let block_seconds = block_size as f64 / f64::from(self.synthesizer.get_sample_rate());
+
+        let mut wrote = 0;
+        while wrote < left.len() {
+            if self.block_wrote == block_size {
+                if self.state == PlaybackState::Playing {
+                    self.send_due();
+                    self.position += self.speed * block_seconds;
+                }
+                self.block_wrote = 0;
+            }
+
+            let rem = (block_size - self.block_wrote).min(left.len() - wrote);
+            self.synthesizer.render(
+                &mut left[wrote..wrote + rem],
+                &mut right[wrote..wrote + rem],
+            );
+            self.block_wrote += rem;
+            wrote += rem;
+        }
+    }
+
+    /// Sends the messages due at the current position, then wraps around or
+    /// finishes at the end of the section.
+    fn send_due(&mut self) {
+        while let Some(message) = self.messages.get(self.next) {
+            if message.seconds > self.position {
+                break;
+            }
+            self.send(*message);
+            self.next += 1;
+        }
+
+        if self.position >= self.end {
+            if self.looping {
+                self.seek(self.start);
+            } else {
+                self.synthesizer.note_off_all(false);
+                self.state = PlaybackState::Finished;
+            }
+        }
+    }
+
+    fn send(&mut self, message: Message) {
+        if message.command == 0x90 && !self.mix.audible(message.channel) {
+            return;
+        }
+        self.synthesizer.process_midi_message(
+            i32::from(message.channel),
+            i32::from(message.command),
+            i32::from(message.data1),
+            i32::from(message.data2),
+        );
+    }
+
+    /// Moves to `seconds`, silencing every note and replaying the program
+    /// changes, controllers and pitch bends sent before it.
+    fn seek(&mut self, seconds: f64) {
+        let seconds = seconds.max(0.0);
+        self.synthesizer.reset();
+
+        self.next = self
+            .messages
+            .partition_point(|message| message.seconds < seconds);
+        for index in 0..self.next {
+            let message = self.messages[index];
+            if !message.is_note() {
+                self.send(message);
+            }
+        }
+
+        self.position = seconds;
+        if self.state == PlaybackState::Finished {
+            self.state = PlaybackState::Paused;
+        }
+    }
+}
+
+/// Controls the playback of a file from any thread, while the audio thread
+/// renders it. Positions are given in seconds of the file at normal speed,
+/// or in bars and beats counted from zero.
+#[derive(Clone)]
+pub struct PlaybackHandle {
+    transport: Arc<Mutex<Transport>>,
+    tempo_map: Arc<TempoMap>,
+}
+
+impl std::fmt::Debug for PlaybackHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PlaybackHandle")
+            .field("state", &self.state())
+            .field("position", &self.position())
+            .finish()
+    }
+}
+
+impl PlaybackHandle {
+    /// Loads `soundfont` and prepares `smf` for playback, paused at its
+    /// beginning.
+    pub fn new(smf: &Smf, soundfont: &SoundFont, sample_rate: u32) -> Result<Self> {
+        let synthesizer = build_synthesizer(soundfont, sample_rate)?;
+        let tempo_map = TempoMap::new(smf);
+        let messages = Self::messages(smf, &tempo_map);
+        let duration = tempo_map.duration();
+
+        Ok(Self {
+            transport: Arc::new(Mutex::new(Transport::new(synthesizer, messages, duration))),
+            tempo_map: Arc::new(tempo_map),
+        })
+    }
+
+    /// Channel messages of every track, in playing order.
+    fn messages(smf: &Smf, tempo_map: &TempoMap) -> Vec<Message> {
+        let mut messages = Vec::new();
+        for (track, events) in smf.tracks.iter().enumerate() {
+            let mut tick = 0_u64;
+            for (index, event) in events.iter().enumerate() {
+                tick += u64::from(event.delta.as_int());
+                let TrackEventKind::Midi { channel, message } = event.kind else {
+                    continue;
+                };
+
+                let (command, data1, data2) = match message {
+                    MidiMessage::NoteOff { key, vel } => (0x80, key.as_int(), vel.as_int()),
+                    MidiMessage::NoteOn { key, vel } => (0x90, key.as_int(), vel.as_int()),
+                    MidiMessage::Aftertouch { key, vel } => (0xA0, key.as_int(), vel.as_int()),
+                    MidiMessage::Controller { controller, value } => {
+                        (0xB0, controller.as_int(), value.as_int())
+                    }
+                    MidiMessage::ProgramChange { program } => (0xC0, program.as_int(), 0),
+                    MidiMessage::ChannelAftertouch { vel } => (0xD0, vel.as_int(), 0),
+                    MidiMessage::PitchBend { bend } => {
+                        let bend = bend.0.as_int();
+                        (0xE0, (bend & 0x7F) as u8, (bend >> 7) as u8)
+                    }
+                };
+                messages.push(Message {
+                    seconds: tempo_map.seconds(tick),
+                    order: (track, index),
+                    channel: channel.as_int(),
+                    command,
+                    data1,
+                    data2,
+                });
+            }
+        }
+
+        messages.sort_by(|a, b| {
+            a.seconds
+                .total_cmp(&b.seconds)
+                .then_with(|| a.order.cmp(&b.order))
+        });
+        messages
+    }
+
+    /// Locks the transport. A thread panicking while holding it leaves it
+    /// in a usable state, so playback carries on.
+    fn transport(&self) -> MutexGuard<'_, Transport> {
+        self.transport
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Renders the next samples of both channels, which must have the same
+    /// length.
+    pub fn render(&self, left: &mut [f32], right: &mut [f32]) {
+        self.transport().render(left, right);
+    }
+
+    pub fn play(&self) {
+        let mut transport = self.transport();
+        if transport.state == PlaybackState::Finished {
+            let start = transport.start;
+            transport.seek(start);
+        }
+        transport.state = PlaybackState::Playing;
+    }
+
+    pub fn pause(&self) {
+        let mut transport = self.transport();
+        if transport.state == PlaybackState::Playing {
+            transport.synthesizer.note_off_all(false);
+            transport.state = PlaybackState::Paused;
+        }
+    }
+
+    /// Stops and rewinds to the start of the section.
+    pub fn stop(&self) {
+        let mut transport = self.transport();
+        let start = transport.start;
+        transport.seek(start);
+        transport.state = PlaybackState::Stopped;
+    }
+
+    pub fn state(&self) -> PlaybackState {
+        self.transport().state
+    }
+
+    /// Position in seconds of the file at normal speed.
+    pub fn position(&self) -> f64 {
+        self.transport().position
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    /// Length of the file in seconds at normal speed.
+    pub fn duration(&self) -> f64 {
+        self.tempo_map.duration()
+    }
+
+    pub fn seek(&self, seconds: f64) {
+        self.transport().seek(seconds);
+    }
+
+    /// Moves to `beat` of `bar`, both counted from zero.
+    pub fn seek_bar(&self, bar: u32, beat: f64) {
+        self.seek(self.bar_seconds(bar, beat));
+    }
+
+    /// Restricts playback to the bars from `start` up to, but not
+    /// including, `end`, and moves to the start of that section.
+    pub fn set_section(&self, start: u32, end: Option<u32>) {
+        let start = self.bar_seconds(start, 0.0);
+        let end = end.map_or(self.duration(), |end| self.bar_seconds(end, 0.0));
+
+        let mut transport = self.transport();
+        transport.start = start;
+        transport.end = end.max(start);
+        transport.seek(start);
+    }
+
+    /// Loops over the section, an A-B loop, instead of stopping at its end.
+    pub fn set_looping(&self, looping: bool) {
+        self.transport().looping = looping;
+    }
+
+    /// Plays faster or slower by `speed`, `1.0` being the tempo of the file.
+    pub fn set_speed(&self, speed: f64) {
+        self.transport().speed = speed.max(0.0);
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.transport().speed
+    }
+
+    pub fn looping(&self) -> bool {
+        self.transport().looping
+    }
+
+    pub fn mix(&self) -> Mix {
+        self.transport().mix
+    }
+
+    /// Mutes and solos channels, releasing the notes of every channel that
+    /// stops being heard.
+    pub fn set_mix(&self, mix: Mix) {
+        let mut transport = self.transport();
+        for channel in 0..16 {
+            if transport.mix.audible(channel) && !mix.audible(channel) {
+                transport
+                    .synthesizer
+                    .note_off_all_channel(i32::from(channel), false);
+            }
+        }
+        transport.mix = mix;
+    }
+
+    fn bar_seconds(&self, bar: u32, beat: f64) -> f64 {
+        let tick = self.tempo_map.tick_at_position(Position { bar, beat });
+        self.tempo_map.seconds(tick).min(self.duration())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{
+        Format, Header, MetaMessage, Timing, TrackEvent,
+        num::{u4, u7, u15, u28},
+    };
+
+    const SAMPLE_RATE: u32 = 44_100;
+
+    /// Smallest SoundFont rustysynth loads: one preset playing a looped
+    /// sine wave over the whole keyboard.
+    fn soundfont() -> SoundFont {
+        fn chunk(id: &[u8], mut data: Vec<u8>) -> Vec<u8> {
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+            let mut chunk = id.to_vec();
+            chunk.extend((data.len() as u32).to_le_bytes());
+            chunk.extend(data);
+            chunk
+        }
+        fn list(kind: &[u8], chunks: &[Vec<u8>]) -> Vec<u8> {
+            chunk(b"LIST", [kind.to_vec(), chunks.concat()].concat())
+        }
+        fn name(name: &str) -> Vec<u8> {
+            let mut name = name.as_bytes().to_vec();
+            name.resize(20, 0);
+            name
+        }
+        let words =
+            |words: &[u16]| -> Vec<u8> { words.iter().flat_map(|w| w.to_le_bytes()).collect() };
+        let sample = |start: u32, end: u32, loop_start: u32, loop_end: u32, rate: u32, key: u8| {
+            [start, end, loop_start, loop_end, rate]
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .chain([key, 0, 0, 0, 1, 0])
+                .collect::<Vec<u8>>()
+        };
+
+        let samples: Vec<u8> = (0..1000)
+            .map(|index| (12_000.0 * (std::f64::consts::TAU * index as f64 / 100.0).sin()) as i16)
+            .chain([0; 46])
+            .flat_map(i16::to_le_bytes)
+            .collect();
+        let preset =
+            |label: &str, bag: u16| [name(label), words(&[0, 0, bag]), vec![0; 12]].concat();
+        let pdta = [
+            chunk(b"phdr", [preset("Sine", 0), preset("EOP", 1)].concat()),
+            chunk(b"pbag", words(&[0, 0, 1, 0])),
+            chunk(b"pmod", vec![0; 10]),
+            chunk(b"pgen", words(&[41, 0, 0, 0])),
+            chunk(
+                b"inst",
+                [name("Sine"), words(&[0]), name("EOI"), words(&[1])].concat(),
+            ),
+            chunk(b"ibag", words(&[0, 0, 2, 0])),
+            chunk(b"imod", vec![0; 10]),
+            chunk(b"igen", words(&[54, 1, 53, 0, 0, 0])),
+            chunk(
+                b"shdr",
+                [
+                    name("Sine"),
+                    sample(0, 1000, 100, 900, 44_100, 69),
+                    name("EOS"),
+                    vec![0; 26],
+                ]
+                .concat(),
+            ),
+        ];
+        let body = [
+            b"sfbk".to_vec(),
+            list(
+                b"INFO",
+                &[
+                    chunk(b"ifil", words(&[2, 1])),
+                    chunk(b"isng", b"EMU8000\0".to_vec()),
+                    chunk(b"INAM", b"Test\0".to_vec()),
+                ],
+            ),
+            list(b"sdta", &[chunk(b"smpl", samples)]),
+            list(b"pdta", &pdta),
+        ]
+        .concat();
+        SoundFont::FromFile(chunk(b"RIFF", body))
+    }
+
+    fn event(delta: u32, message: MidiMessage) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Midi {
+                channel: u4::new(0),
+                message,
+            },
+        }
+    }
+
+    fn note_on(delta: u32, key: u8) -> TrackEvent<'static> {
+        let (key, vel) = (u7::new(key), u7::new(80));
+        event(delta, MidiMessage::NoteOn { key, vel })
+    }
+
+    fn note_off(delta: u32, key: u8) -> TrackEvent<'static> {
+        let (key, vel) = (u7::new(key), u7::new(0));
+        event(delta, MidiMessage::NoteOff { key, vel })
+    }
+
+    /// Four bars of whole notes at 120 bpm, two seconds each, the second
+    /// track changing program as the first note starts.
+    fn smf() -> Smf<'static> {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(480)));
+        let mut smf = Smf::new(header);
+        let mut melody = Vec::new();
+        for key in [60, 62, 64, 65] {
+            melody.extend([note_on(0, key), note_off(4 * 480, key)]);
+        }
+        let program = MidiMessage::ProgramChange {
+            program: u7::new(5),
+        };
+        smf.tracks = vec![melody, vec![event(0, program)]];
+        for track in &mut smf.tracks {
+            track.push(TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            });
+        }
+        smf
+    }
+
+    fn handle() -> PlaybackHandle {
+        PlaybackHandle::new(&smf(), &soundfont(), SAMPLE_RATE).unwrap()
+    }
+
+    /// Plays `seconds` of audio at the rate of the handle.
+    fn play_for(handle: &PlaybackHandle, seconds: f64) {
+        let frames = (seconds * f64::from(SAMPLE_RATE)) as usize;
+        let (mut left, mut right) = (vec![0.0; frames], vec![0.0; frames]);
+        handle.render(&mut left, &mut right);
+    }
+
+    /// Length of a synthesizer block, the step of the transport, in seconds.
+    fn block(handle: &PlaybackHandle) -> f64 {
+        handle.transport().synthesizer.get_block_size() as f64 / f64::from(SAMPLE_RATE)
+    }
+
+    #[test]
+    fn orders_messages_due_together_by_track_then_event() {
+        let smf = smf();
+        let messages = PlaybackHandle::messages(&smf, &TempoMap::new(&smf));
+        let due: Vec<(usize, usize, u8, u8)> = messages
+            .iter()
+            .take(4)
+            .map(|message| {
+                (
+                    message.order.0,
+                    message.order.1,
+                    message.command,
+                    message.data1,
+                )
+            })
+            .collect();
+        assert_eq!(
+            due,
+            [
+                (0, 0, 0x90, 60),
+                (1, 0, 0xC0, 5),
+                (0, 1, 0x80, 60),
+                (0, 2, 0x90, 62)
+            ]
+        );
+    }
+
+    #[test]
+    fn seeks_to_the_first_message_due() {
+        let handle = handle();
+        handle.seek(4.5);
+        assert_eq!(handle.position(), 4.5);
+        assert_eq!(handle.transport().next, 6);
+
+        handle.seek_bar(1, 2.0);
+        assert_eq!(handle.position(), 3.0);
+        assert_eq!(handle.transport().next, 4);
+
+        handle.seek(-1.0);
+        assert_eq!(handle.position(), 0.0);
+        assert_eq!(handle.transport().next, 0);
+    }
+
+    #[test]
+    fn finishes_or_loops_at_the_end_of_the_section() {
+        let handle = handle();
+        handle.set_section(1, Some(2));
+        assert_eq!(handle.position(), 2.0);
+
+        handle.play();
+        play_for(&handle, 2.5);
+        assert_eq!(handle.state(), PlaybackState::Finished);
+
+        handle.set_looping(true);
+        handle.play();
+        assert_eq!(handle.position(), 2.0);
+        play_for(&handle, 2.5);
+        assert_eq!(handle.state(), PlaybackState::Playing);
+        assert!((2.0..4.0).contains(&handle.position()));
+        assert!((handle.position() - 2.5).abs() <= 2.0 * block(&handle));
+    }
+
+    #[test]
+    fn plays_faster_or_slower_by_the_speed() {
+        for speed in [0.5, 2.0] {
+            let handle = handle();
+            handle.set_speed(speed);
+            handle.play();
+            play_for(&handle, 1.0);
+            assert!(
+                (handle.position() - speed).abs() <= speed * block(&handle),
+                "{}",
+                handle.position()
+            );
+        }
+
+        let handle = handle();
+        handle.pause();
+        play_for(&handle, 1.0);
+        assert_eq!(handle.position(), 0.0);
+    }
+}
@@ -0,0 +1,609 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{Mix, PlaybackHandle, PlaybackState, SampleRing, format_time};
+use crate::{Error, Result, Score, TempoMap, instrument_name, pitch_name};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::Print,
+    terminal::{self, ClearType},
+};
+use midly::{MidiMessage, TrackEventKind};
+use std::{
+    io::{self, Stdout, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Longest wait for a key before the display is refreshed.
+const FRAME: Duration = Duration::from_millis(50);
+/// Most rows of the piano roll, higher pitches sharing rows beyond it.
+const ROLL_ROWS: u8 = 12;
+/// Columns of the piano roll per quarter note.
+const COLUMNS_PER_QUARTER: f64 = 4.0;
+/// Share of the piano roll showing what has already played.
+const ROLL_PAST: f64 = 0.25;
+/// Width of the pitch names left of the piano roll.
+const LABEL_WIDTH: usize = 5;
+const SPEED_STEP: f64 = 0.1;
+const SPEED_RANGE: (f64, f64) = (0.1, 4.0);
+const KEYS: &str = "space pause  \u{2190}/\u{2192} bar  \u{2191}/\u{2193} channel  m mute  s solo  \
+                    l loop  +/- speed  r new seed  q quit";
+
+/// Why the interactive player returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    /// The listener asked for a new piece composed with another seed.
+    Regenerate,
+}
+
+/// Raw mode on the alternate screen, left when dropped so the terminal is
+/// restored even when playback fails.
+#[derive(Debug)]
+pub struct Terminal;
+
+impl Terminal {
+    pub fn enter() -> Result<Self> {
+        terminal::enable_raw_mode().map_err(Error::Terminal)?;
+        let terminal = Self;
+        crossterm::execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)
+            .map_err(Error::Terminal)?;
+        Ok(terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// A channel playing notes in the piece.
+#[derive(Debug)]
+struct Channel {
+    number: u8,
+    instrument: &'static str,
+}
+
+/// Transport settings the keys change, read from the playback before a key
+/// is handled and written back after.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Controls {
+    playing: bool,
+    looping: bool,
+    speed: f64,
+    mix: Mix,
+}
+
+/// What a key asks of the player beyond the controls it changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Request {
+    Leave(Action),
+    /// Moving to the start of the bar this many bars away.
+    SeekBars(i64),
+}
+
+/// The piece as the player shows it: its channels, the one selected, and
+/// the pitches the piano roll spans. Keys and drawing go through it, apart
+/// from the terminal and the playback.
+#[derive(Debug)]
+struct View {
+    score: Score,
+    channels: Vec<Channel>,
+    /// Index of the channel mute and solo apply to.
+    selected: usize,
+    /// Lowest pitch of the piano roll, semitones per row and rows.
+    lowest: u8,
+    row_span: u8,
+    rows: u8,
+}
+
+impl View {
+    fn new(score: Score) -> Self {
+        let mut numbers: Vec<u8> = score.notes.iter().map(|note| note.channel).collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        let channels = numbers
+            .into_iter()
+            .map(|number| {
+                let program = score
+                    .events
+                    .iter()
+                    .find_map(|event| match event.kind() {
+                        TrackEventKind::Midi {
+                            channel,
+                            message: MidiMessage::ProgramChange { program },
+                        } if channel.as_int() == number => Some(program.as_int()),
+                        _ => None,
+                    })
+                    .unwrap_or(0);
+                Channel {
+                    number,
+                    instrument: instrument_name(program, number),
+                }
+            })
+            .collect();
+
+        let pitches = score.pitched_notes().map(|note| note.pitch);
+        let (lowest, highest) = pitches.fold((127, 0), |(lowest, highest), pitch| {
+            (lowest.min(pitch), highest.max(pitch))
+        });
+        let span = highest.saturating_sub(lowest) + 1;
+        let row_span = span.div_ceil(ROLL_ROWS).max(1);
+
+        Self {
+            score,
+            channels,
+            selected: 0,
+            lowest: lowest.min(highest),
+            row_span,
+            rows: span.div_ceil(row_span),
+        }
+    }
+
+    /// Applies `key` to the selection and `controls`, returning what else
+    /// it asks for.
+    fn key(&mut self, key: KeyEvent, controls: &mut Controls) -> Option<Request> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Request::Leave(Action::Quit)),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Some(Request::Leave(Action::Quit));
+            }
+            KeyCode::Char('r') => return Some(Request::Leave(Action::Regenerate)),
+            KeyCode::Left => return Some(Request::SeekBars(-1)),
+            KeyCode::Right => return Some(Request::SeekBars(1)),
+            KeyCode::Char(' ') => controls.playing ^= true,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => {
+                self.selected = (self.selected + 1).min(self.channels.len().saturating_sub(1));
+            }
+            KeyCode::Char('m') => {
+                if let Some(channel) = self.channels.get(self.selected) {
+                    controls.mix.muted[usize::from(channel.number)] ^= true;
+                }
+            }
+            KeyCode::Char('s') => {
+                if let Some(channel) = self.channels.get(self.selected) {
+                    controls.mix.soloed[usize::from(channel.number)] ^= true;
+                }
+            }
+            KeyCode::Char('l') => controls.looping ^= true,
+            KeyCode::Char('+' | '=') => controls.speed = Self::step_speed(controls.speed, 1.0),
+            KeyCode::Char('-') => controls.speed = Self::step_speed(controls.speed, -1.0),
+            _ => {}
+        }
+        None
+    }
+
+    /// `speed` moved by one step in `direction`, rounded to the step and
+    /// kept within range.
+    fn step_speed(speed: f64, direction: f64) -> f64 {
+        let steps = 1.0 / SPEED_STEP;
+        let speed = ((speed + direction * SPEED_STEP) * steps).round() / steps;
+        speed.clamp(SPEED_RANGE.0, SPEED_RANGE.1)
+    }
+
+    /// One line per channel, with the cursor on the selected one and what
+    /// `mix` lets through of the notes sounding at `tick`.
+    fn channel_lines(&self, tick: u64, mix: &Mix) -> Vec<String> {
+        self.channels
+            .iter()
+            .enumerate()
+            .map(|(index, channel)| {
+                let cursor = if index == self.selected { '>' } else { ' ' };
+                let sounding = self.score.notes.iter().any(|note| {
+                    note.channel == channel.number && note.start <= tick && tick < note.end()
+                });
+                let activity = match (mix.audible(channel.number), sounding) {
+                    (false, _) => ' ',
+                    (true, true) => '\u{25cf}',
+                    (true, false) => '\u{00b7}',
+                };
+                let number = usize::from(channel.number);
+                let muted = if mix.muted[number] { 'M' } else { ' ' };
+                let soloed = if mix.soloed[number] { 'S' } else { ' ' };
+                format!(
+                    "{cursor} {:>2}  {activity}  {muted}{soloed}  {}",
+                    channel.number + 1,
+                    channel.instrument
+                )
+            })
+            .collect()
+    }
+
+    fn progress(ratio: f64, width: usize) -> String {
+        let inner = width.saturating_sub(2);
+        let done = ((ratio.clamp(0.0, 1.0) * inner as f64).round() as usize).min(inner);
+        format!("[{}{}]", "=".repeat(done), " ".repeat(inner - done))
+    }
+
+    /// Rows of the piano roll around `tick`, highest pitches first, with the
+    /// current position marked. Notes of channels `mix` silences are shaded.
+    fn piano_roll(
+        &self,
+        tempo_map: &TempoMap,
+        mix: &Mix,
+        tick: u64,
+        columns: usize,
+    ) -> Vec<String> {
+        let column_ticks = (tempo_map.ticks_per_quarter(tick) / COLUMNS_PER_QUARTER).max(1.0);
+        let now = ((columns as f64 * ROLL_PAST) as usize).min(columns.saturating_sub(1));
+        let start = tick as f64 - now as f64 * column_ticks;
+        let end = start + columns as f64 * column_ticks;
+
+        let visible: Vec<_> = self
+            .score
+            .pitched_notes()
+            .filter(|note| (note.start as f64) < end && (note.end() as f64) > start)
+            .collect();
+
+        (0..self.rows)
+            .rev()
+            .map(|row| {
+                let lowest = self.lowest + row * self.row_span;
+                let highest = lowest.saturating_add(self.row_span - 1);
+                let cells: String = (0..columns)
+                    .map(|column| {
+                        let from = start + column as f64 * column_ticks;
+                        let to = from + column_ticks;
+                        let note = visible.iter().find(|note| {
+                            (lowest..=highest).contains(&note.pitch)
+                                && (note.start as f64) < to
+                                && (note.end() as f64) > from
+                        });
+                        match note {
+                            Some(note) if mix.audible(note.channel) => '\u{2588}',
+                            Some(_) => '\u{2591}',
+                            None if column == now => '\u{2502}',
+                            None => ' ',
+                        }
+                    })
+                    .collect();
+                format!("{:>4} {cells}", pitch_name(lowest))
+            })
+            .collect()
+    }
+}
+
+/// Terminal front end of a playback. It shows the position, the channels
+/// and a piano roll scrolling with the music, and turns keys into
+/// transport, mute and solo commands.
+#[derive(Debug)]
+pub struct Tui {
+    handle: PlaybackHandle,
+    /// Ring of the audio output, for its underrun count.
+    ring: Arc<SampleRing>,
+    view: View,
+    seed: u64,
+}
+
+impl Tui {
+    pub fn new(score: Score, handle: PlaybackHandle, ring: Arc<SampleRing>, seed: u64) -> Self {
+        Self {
+            handle,
+            ring,
+            view: View::new(score),
+            seed,
+        }
+    }
+
+    /// Shows the playback and handles keys until the listener quits or asks
+    /// for a new piece.
+    pub fn run(&mut self) -> Result<Action> {
+        let mut stdout = io::stdout();
+        loop {
+            self.draw(&mut stdout).map_err(Error::Terminal)?;
+
+            if !event::poll(FRAME).map_err(Error::Terminal)? {
+                continue;
+            }
+            let Event::Key(key) = event::read().map_err(Error::Terminal)? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+
+            let before = self.controls();
+            let mut controls = before;
+            let request = self.view.key(key, &mut controls);
+            self.apply(before, controls);
+            match request {
+                Some(Request::Leave(action)) => return Ok(action),
+                Some(Request::SeekBars(bars)) => self.seek_bars(bars),
+                None => {}
+            }
+        }
+    }
+
+    fn controls(&self) -> Controls {
+        Controls {
+            playing: self.handle.state() == PlaybackState::Playing,
+            looping: self.handle.looping(),
+            speed: self.handle.speed(),
+            mix: self.handle.mix(),
+        }
+    }
+
+    /// Passes on to the playback the controls changed from `before`.
+    fn apply(&self, before: Controls, after: Controls) {
+        if after.playing != before.playing {
+            if after.playing {
+                self.handle.play();
+            } else {
+                self.handle.pause();
+            }
+        }
+        if after.looping != before.looping {
+            self.handle.set_looping(after.looping);
+        }
+        if after.speed != before.speed {
+            self.handle.set_speed(after.speed);
+        }
+        if after.mix != before.mix {
+            self.handle.set_mix(after.mix);
+        }
+    }
+
+    /// Moves to the start of the bar `bars` away from the current one.
+    fn seek_bars(&self, bars: i64) {
+        let tempo_map = self.handle.tempo_map();
+        let bar = tempo_map
+            .position(tempo_map.tick_at_seconds(self.handle.position()))
+            .bar;
+        let last = tempo_map.bar_count().saturating_sub(1);
+        let bar = (i64::from(bar) + bars).clamp(0, i64::from(last));
+        self.handle.seek_bar(bar as u32, 0.0);
+    }
+
+    fn draw(&self, stdout: &mut Stdout) -> io::Result<()> {
+        let (width, _) = terminal::size()?;
+        let width = usize::from(width).max(LABEL_WIDTH + 2);
+
+        let tempo_map = self.handle.tempo_map();
+        let seconds = self.handle.position();
+        let duration = self.handle.duration();
+        let tick = tempo_map.tick_at_seconds(seconds);
+
+        let state = match self.handle.state() {
+            PlaybackState::Playing => "Playing",
+            PlaybackState::Paused => "Paused",
+            PlaybackState::Stopped => "Stopped",
+            PlaybackState::Finished => "Finished",
+        };
+        let looping = if self.handle.looping() { "on" } else { "off" };
+        let mix = self.handle.mix();
+
+        let mut lines = vec![
+            format!(
+                "{state}    seed {}    speed x{:.1}    loop {looping}",
+                self.seed,
+                self.handle.speed()
+            ),
+            format!(
+                "Bar {} of {}    {} / {}    underruns {}",
+                tempo_map.position(tick),
+                tempo_map.bar_count(),
+                format_time(seconds),
+                format_time(duration),
+                self.ring.underruns()
+            ),
+            View::progress(seconds / duration.max(f64::EPSILON), width),
+            String::new(),
+        ];
+        lines.extend(self.view.channel_lines(tick, &mix));
+        lines.push(String::new());
+        lines.extend(
+            self.view
+                .piano_roll(tempo_map, &mix, tick, width - LABEL_WIDTH),
+        );
+        lines.push(String::new());
+        lines.push(KEYS.to_string());
+
+        for (row, line) in lines.iter().enumerate() {
+            let line: String = line.chars().take(width).collect();
+            queue!(
+                stdout,
+                cursor::MoveTo(0, row as u16),
+                Print(line),
+                terminal::Clear(ClearType::UntilNewLine)
+            )?;
+        }
+        queue!(stdout, terminal::Clear(ClearType::FromCursorDown))?;
+        stdout.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Note, ScoreEvent};
+    use midly::{
+        Format, Header, Timing,
+        num::{u4, u7, u15},
+    };
+
+    /// A violin from C4 for a bar on the first channel and a bass note on
+    /// the third, starting on the second beat.
+    fn view() -> View {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(480)));
+        let mut score = Score::new(header, 1);
+        let note = |start: u64, duration: u64, pitch: u8, channel: u8| Note {
+            start,
+            duration,
+            pitch,
+            velocity: 80,
+            release: None,
+            channel,
+            track: 0,
+        };
+        score.notes = vec![note(0, 1920, 60, 0), note(480, 480, 48, 2)];
+        score.events.push(ScoreEvent::new(
+            0,
+            0,
+            TrackEventKind::Midi {
+                channel: u4::new(0),
+                message: MidiMessage::ProgramChange {
+                    program: u7::new(40),
+                },
+            },
+        ));
+        View::new(score)
+    }
+
+    fn controls() -> Controls {
+        Controls {
+            playing: true,
+            looping: false,
+            speed: 1.0,
+            mix: Mix::default(),
+        }
+    }
+
+    fn press(view: &mut View, controls: &mut Controls, code: KeyCode) -> Option<Request> {
+        view.key(KeyEvent::new(code, KeyModifiers::NONE), controls)
+    }
+
+    #[test]
+    fn lists_the_channels_and_the_pitches_played() {
+        let view = view();
+
+        let channels: Vec<_> = view
+            .channels
+            .iter()
+            .map(|channel| (channel.number, channel.instrument))
+            .collect();
+        assert_eq!(channels, [(0, "Violin"), (2, "Acoustic Grand Piano")]);
+        assert_eq!((view.lowest, view.row_span, view.rows), (48, 2, 7));
+    }
+
+    #[test]
+    fn moves_the_selection_within_the_channels() {
+        let mut view = view();
+        let mut controls = controls();
+
+        press(&mut view, &mut controls, KeyCode::Up);
+        assert_eq!(view.selected, 0);
+        press(&mut view, &mut controls, KeyCode::Down);
+        assert_eq!(view.selected, 1);
+        press(&mut view, &mut controls, KeyCode::Down);
+        assert_eq!(view.selected, 1);
+        press(&mut view, &mut controls, KeyCode::Up);
+        assert_eq!(view.selected, 0);
+        assert_eq!(controls, self::controls());
+    }
+
+    #[test]
+    fn mutes_and_solos_the_selected_channel() {
+        let mut view = view();
+        let mut controls = controls();
+
+        press(&mut view, &mut controls, KeyCode::Down);
+        press(&mut view, &mut controls, KeyCode::Char('m'));
+        assert!(controls.mix.muted[2]);
+        press(&mut view, &mut controls, KeyCode::Up);
+        press(&mut view, &mut controls, KeyCode::Char('s'));
+        assert!(controls.mix.soloed[0]);
+        press(&mut view, &mut controls, KeyCode::Char('s'));
+        assert!(!controls.mix.soloed[0]);
+        assert_eq!(controls.mix.muted.iter().filter(|&&muted| muted).count(), 1);
+    }
+
+    #[test]
+    fn steps_the_speed_within_its_range() {
+        let mut view = view();
+        let mut controls = controls();
+
+        press(&mut view, &mut controls, KeyCode::Char('+'));
+        assert_eq!(controls.speed, 1.1);
+        press(&mut view, &mut controls, KeyCode::Char('-'));
+        press(&mut view, &mut controls, KeyCode::Char('-'));
+        assert_eq!(controls.speed, 0.9);
+
+        controls.speed = 3.95;
+        press(&mut view, &mut controls, KeyCode::Char('='));
+        assert_eq!(controls.speed, SPEED_RANGE.1);
+        press(&mut view, &mut controls, KeyCode::Char('+'));
+        assert_eq!(controls.speed, SPEED_RANGE.1);
+
+        controls.speed = SPEED_RANGE.0;
+        press(&mut view, &mut controls, KeyCode::Char('-'));
+        assert_eq!(controls.speed, SPEED_RANGE.0);
+    }
+
+    #[test]
+    fn turns_keys_into_requests() {
+        let mut view = view();
+        let mut controls = controls();
+
+        assert_eq!(
+            press(&mut view, &mut controls, KeyCode::Char('q')),
+            Some(Request::Leave(Action::Quit))
+        );
+        assert_eq!(
+            view.key(
+                KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
+                &mut controls
+            ),
+            Some(Request::Leave(Action::Quit))
+        );
+        assert_eq!(
+            press(&mut view, &mut controls, KeyCode::Char('r')),
+            Some(Request::Leave(Action::Regenerate))
+        );
+        assert_eq!(
+            press(&mut view, &mut controls, KeyCode::Left),
+            Some(Request::SeekBars(-1))
+        );
+        assert_eq!(press(&mut view, &mut controls, KeyCode::Char(' ')), None);
+        assert!(!controls.playing);
+        assert_eq!(press(&mut view, &mut controls, KeyCode::Char('l')), None);
+        assert!(controls.looping);
+    }
+
+    #[test]
+    fn fills_the_progress_bar_in_proportion() {
+        assert_eq!(View::progress(0.0, 10), "[        ]");
+        assert_eq!(View::progress(0.5, 10), "[====    ]");
+        assert_eq!(View::progress(2.0, 10), "[========]");
+        assert_eq!(View::progress(0.25, 6), "[=   ]");
+        assert_eq!(View::progress(0.5, 1), "[]");
+    }
+
+    #[test]
+    fn draws_the_piano_roll_around_the_position() {
+        let view = view();
+        let tempo_map = TempoMap::new(&view.score.to_smf());
+        let mut mix = Mix::default();
+
+        let roll = view.piano_roll(&tempo_map, &mix, 480, 8);
+        assert_eq!(roll.len(), 7);
+        assert_eq!(
+            roll[0],
+            "  C4 \u{2588}\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}"
+        );
+        assert_eq!(roll[6], "  C3   \u{2588}\u{2588}\u{2588}\u{2588}  ");
+        assert_eq!(roll[3], " F#3   \u{2502}     ");
+
+        mix.muted[2] = true;
+        let roll = view.piano_roll(&tempo_map, &mix, 480, 8);
+        assert_eq!(roll[6], "  C3   \u{2591}\u{2591}\u{2591}\u{2591}  ");
+    }
+}
@@ -0,0 +1,223 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::Audio;
+use crate::{Error, Result};
+use clap::ValueEnum;
+
+const PCM: u16 = 1;
+const IEEE_FLOAT: u16 = 3;
+
+/// Sample encoding of a rendered WAV file.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WavFormat {
+    /// 16-bit signed integer PCM
+    #[default]
+    #[value(name = "16")]
+    Int16,
+    /// 24-bit signed integer PCM
+    #[value(name = "24")]
+    Int24,
+    /// 32-bit IEEE float
+    #[value(name = "32f")]
+    Float32,
+}
+
+impl WavFormat {
+    fn bytes_per_sample(self) -> u16 {
+        match self {
+            Self::Int16 => 2,
+            Self::Int24 => 3,
+            Self::Float32 => 4,
+        }
+    }
+
+    fn format_tag(self) -> u16 {
+        match self {
+            Self::Int16 | Self::Int24 => PCM,
+            Self::Float32 => IEEE_FLOAT,
+        }
+    }
+
+    fn push_sample(self, data: &mut Vec<u8>, sample: f32) {
+        match self {
+            Self::Int16 => {
+                let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            Self::Int24 => {
+                let value = (sample.clamp(-1.0, 1.0) * 8_388_607.0).round() as i32;
+                data.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+            Self::Float32 => data.extend_from_slice(&sample.to_le_bytes()),
+        }
+    }
+}
+
+/// Encodes stereo audio as an interleaved RIFF/WAVE file.
+pub fn encode_wav(audio: &Audio, format: WavFormat) -> Result<Vec<u8>> {
+    const CHANNELS: u16 = 2;
+
+    let frames = audio.left.len().min(audio.right.len());
+    let block_align = CHANNELS * format.bytes_per_sample();
+    let byte_rate = audio.sample_rate * u32::from(block_align);
+
+    // Formats other than PCM extend `fmt ` with the size of their extra
+    // fields, none here, and add a `fact` chunk counting the frames.
+    let pcm = format.format_tag() == PCM;
+    let fmt_len: u32 = if pcm { 16 } else { 18 };
+    let fact_chunk_len: u32 = if pcm { 0 } else { 8 + 4 };
+    let header_len = 4 + (8 + fmt_len) + fact_chunk_len + 8;
+
+    let too_long = || Error::EncodeAudio("Audio is too long for a WAV file".to_string());
+    let data_len = u32::try_from(frames * usize::from(block_align)).map_err(|_| too_long())?;
+    let riff_len = data_len.checked_add(header_len).ok_or_else(too_long)?;
+
+    let mut output = Vec::with_capacity(8 + riff_len as usize);
+
+    output.extend_from_slice(b"RIFF");
+    output.extend_from_slice(&riff_len.to_le_bytes());
+    output.extend_from_slice(b"WAVE");
+
+    output.extend_from_slice(b"fmt ");
+    output.extend_from_slice(&fmt_len.to_le_bytes());
+    output.extend_from_slice(&format.format_tag().to_le_bytes());
+    output.extend_from_slice(&CHANNELS.to_le_bytes());
+    output.extend_from_slice(&audio.sample_rate.to_le_bytes());
+    output.extend_from_slice(&byte_rate.to_le_bytes());
+    output.extend_from_slice(&block_align.to_le_bytes());
+    output.extend_from_slice(&(format.bytes_per_sample() * 8).to_le_bytes());
+
+    if !pcm {
+        output.extend_from_slice(&0_u16.to_le_bytes());
+
+        output.extend_from_slice(b"fact");
+        output.extend_from_slice(&4_u32.to_le_bytes());
+        output.extend_from_slice(&(frames as u32).to_le_bytes());
+    }
+
+    output.extend_from_slice(b"data");
+    output.extend_from_slice(&data_len.to_le_bytes());
+
+    for (left, right) in audio.left.iter().zip(&audio.right) {
+        format.push_sample(&mut output, *left);
+        format.push_sample(&mut output, *right);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audio() -> Audio {
+        Audio {
+            sample_rate: 48_000,
+            left: vec![0.0, 0.5, -1.0],
+            right: vec![1.0, -0.5, 2.0],
+        }
+    }
+
+    /// Splits a RIFF/WAVE file into its chunks, checking the lengths add up.
+    fn chunks(bytes: &[u8]) -> Vec<(&[u8], &[u8])> {
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[8..12], b"WAVE");
+        let riff_len = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        assert_eq!(riff_len, bytes.len() - 8);
+
+        let mut chunks = Vec::new();
+        let mut rest = &bytes[12..];
+        while !rest.is_empty() {
+            let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            chunks.push((&rest[..4], &rest[8..8 + len]));
+            rest = &rest[8 + len..];
+        }
+        chunks
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_a_plain_pcm_header() {
+        let bytes = encode_wav(&audio(), WavFormat::Int16).unwrap();
+        let chunks = chunks(&bytes);
+        let ids: Vec<_> = chunks.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [b"fmt ", b"data"]);
+
+        let fmt = chunks[0].1;
+        assert_eq!(fmt.len(), 16);
+        assert_eq!(u16_at(fmt, 0), PCM);
+        assert_eq!(u16_at(fmt, 2), 2);
+        assert_eq!(u32_at(fmt, 4), 48_000);
+        assert_eq!(u32_at(fmt, 8), 48_000 * 4);
+        assert_eq!(u16_at(fmt, 12), 4);
+        assert_eq!(u16_at(fmt, 14), 16);
+
+        let samples: Vec<i16> = chunks[1]
+            .1
+            .chunks(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        assert_eq!(samples, [0, 32767, 16384, -16384, -32767, 32767]);
+    }
+
+    #[test]
+    fn packs_24_bit_samples() {
+        let bytes = encode_wav(&audio(), WavFormat::Int24).unwrap();
+        let chunks = chunks(&bytes);
+        assert_eq!(u16_at(chunks[0].1, 12), 6);
+        assert_eq!(u16_at(chunks[0].1, 14), 24);
+
+        let samples: Vec<i32> = chunks[1]
+            .1
+            .chunks(3)
+            .map(|sample| i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) >> 8)
+            .collect();
+        assert_eq!(
+            samples,
+            [0, 8_388_607, 4_194_304, -4_194_304, -8_388_607, 8_388_607]
+        );
+    }
+
+    #[test]
+    fn extends_the_float_header() {
+        let bytes = encode_wav(&audio(), WavFormat::Float32).unwrap();
+        let chunks = chunks(&bytes);
+        let ids: Vec<_> = chunks.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [b"fmt ", b"fact", b"data"]);
+
+        let fmt = chunks[0].1;
+        assert_eq!(fmt.len(), 18);
+        assert_eq!(u16_at(fmt, 0), IEEE_FLOAT);
+        assert_eq!(u16_at(fmt, 14), 32);
+        assert_eq!(u16_at(fmt, 16), 0);
+        assert_eq!(u32_at(chunks[1].1, 0), 3);
+
+        let samples: Vec<f32> = chunks[2]
+            .1
+            .chunks(4)
+            .map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+            .collect();
+        assert_eq!(samples, [0.0, 1.0, 0.5, -0.5, -1.0, 2.0]);
+    }
+}
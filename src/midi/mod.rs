@@ -0,0 +1,29 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod general;
+mod reader;
+mod score;
+mod source;
+mod tempo;
+mod writer;
+
+pub use general::{SHARP_NAMES, instrument_name, pitch_name};
+pub use reader::Reader;
+pub use score::{Note, Score, ScoreEvent};
+pub use source::Source;
+pub use tempo::{DEFAULT_TEMPO, Position, TempoMap, TimeSignature};
+pub use writer::{Writer, to_delta_track};
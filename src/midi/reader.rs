@@ -0,0 +1,86 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Error, Result};
+use midly::Smf;
+use std::{fs, path::Path};
+
+#[derive(Debug)]
+pub struct Reader;
+
+impl Reader {
+    /// Reads the contents of the MIDI file at `path`, to be parsed with
+    /// [`Reader::parse`].
+    pub fn read_file(path: &Path) -> Result<Vec<u8>> {
+        fs::read(path).map_err(Error::ReadInputFile)
+    }
+
+    /// Parses the contents of a MIDI file. The events borrow their text and
+    /// system exclusive payloads from `bytes`.
+    pub fn parse(bytes: &[u8]) -> Result<Smf<'_>> {
+        Smf::parse(bytes).map_err(Error::ParseInputFile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Writer;
+    use midly::{
+        Format, Header, MetaMessage, Timing, TrackEvent, TrackEventKind,
+        num::{u15, u28},
+    };
+
+    static PRELUDE: &[u8] = include_bytes!("../../assets/midi/prelude1.mid");
+
+    #[test]
+    fn keeps_text_and_system_exclusive_payloads() {
+        let event = |kind| TrackEvent {
+            delta: u28::new(0),
+            kind,
+        };
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(96)),
+        ));
+        smf.tracks.push(vec![
+            event(TrackEventKind::Meta(MetaMessage::TrackName(b"Melody"))),
+            event(TrackEventKind::Meta(MetaMessage::Text(b"seed: 42"))),
+            event(TrackEventKind::SysEx(&[0x7E, 0x7F, 0x09, 0x01, 0xF7])),
+            event(TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ]);
+
+        let bytes = Writer::new().write(&smf).unwrap();
+        let read = Reader::parse(&bytes).unwrap();
+
+        assert_eq!(read, smf);
+    }
+
+    #[test]
+    fn reads_what_it_parses() {
+        let read = Reader::parse(PRELUDE).unwrap();
+
+        assert_eq!(read, Smf::parse(PRELUDE).unwrap());
+    }
+
+    #[test]
+    fn reports_unparsable_files() {
+        assert!(matches!(
+            Reader::parse(b"MThd"),
+            Err(Error::ParseInputFile(_))
+        ));
+    }
+}
@@ -0,0 +1,566 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::to_delta_track;
+use midly::{
+    Header, MetaMessage, MidiMessage, Smf, TrackEventKind,
+    num::{u4, u7},
+};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// MIDI channel reserved for percussion in General MIDI, counted from zero.
+pub const PERCUSSION_CHANNEL: u8 = 9;
+
+/// A sounding note, with its start and duration in absolute ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    pub start: u64,
+    pub duration: u64,
+    pub pitch: u8,
+    pub velocity: u8,
+    /// Release velocity of the `NoteOff` ending the note, `None` when the
+    /// note is ended by a `NoteOn` with a velocity of zero.
+    pub release: Option<u8>,
+    pub channel: u8,
+    pub track: usize,
+}
+
+impl Note {
+    pub fn end(&self) -> u64 {
+        self.start + self.duration
+    }
+
+    pub fn is_percussion(&self) -> bool {
+        self.channel == PERCUSSION_CHANNEL
+    }
+}
+
+/// Any event that is not part of a note: controllers, program changes,
+/// meta events, system exclusive messages, and note offs without a
+/// matching note on.
+///
+/// The event owns its text or system exclusive payload, so a score outlives
+/// the bytes it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreEvent {
+    pub tick: u64,
+    pub track: usize,
+    /// The event with an empty payload, which `payload` holds instead.
+    kind: TrackEventKind<'static>,
+    payload: Box<[u8]>,
+}
+
+impl ScoreEvent {
+    pub fn new(tick: u64, track: usize, kind: TrackEventKind<'_>) -> Self {
+        let (kind, payload) = swap_payload(kind, &[]);
+        Self {
+            tick,
+            track,
+            kind,
+            payload: payload.into(),
+        }
+    }
+
+    /// The same event on `track`.
+    pub fn on_track(&self, track: usize) -> Self {
+        Self {
+            track,
+            ..self.clone()
+        }
+    }
+
+    pub fn kind(&self) -> TrackEventKind<'_> {
+        swap_payload(self.kind, &self.payload).0
+    }
+
+    pub fn is_end_of_track(&self) -> bool {
+        self.kind == TrackEventKind::Meta(MetaMessage::EndOfTrack)
+    }
+}
+
+/// `kind` carrying `payload` instead of its own text or system exclusive
+/// data, which is returned alongside. Events without a payload are left
+/// as they are.
+fn swap_payload<'a, 'b>(
+    kind: TrackEventKind<'a>,
+    payload: &'b [u8],
+) -> (TrackEventKind<'b>, &'a [u8]) {
+    use MetaMessage as Meta;
+
+    let message = match kind {
+        TrackEventKind::Midi { channel, message } => {
+            return (TrackEventKind::Midi { channel, message }, &[]);
+        }
+        TrackEventKind::SysEx(data) => return (TrackEventKind::SysEx(payload), data),
+        TrackEventKind::Escape(data) => return (TrackEventKind::Escape(payload), data),
+        TrackEventKind::Meta(message) => message,
+    };
+    let (message, data) = match message {
+        Meta::Text(data) => (Meta::Text(payload), data),
+        Meta::Copyright(data) => (Meta::Copyright(payload), data),
+        Meta::TrackName(data) => (Meta::TrackName(payload), data),
+        Meta::InstrumentName(data) => (Meta::InstrumentName(payload), data),
+        Meta::Lyric(data) => (Meta::Lyric(payload), data),
+        Meta::Marker(data) => (Meta::Marker(payload), data),
+        Meta::CuePoint(data) => (Meta::CuePoint(payload), data),
+        Meta::ProgramName(data) => (Meta::ProgramName(payload), data),
+        Meta::DeviceName(data) => (Meta::DeviceName(payload), data),
+        Meta::SequencerSpecific(data) => (Meta::SequencerSpecific(payload), data),
+        Meta::Unknown(kind, data) => (Meta::Unknown(kind, payload), data),
+        Meta::TrackNumber(number) => (Meta::TrackNumber(number), &[][..]),
+        Meta::MidiChannel(channel) => (Meta::MidiChannel(channel), &[][..]),
+        Meta::MidiPort(port) => (Meta::MidiPort(port), &[][..]),
+        Meta::EndOfTrack => (Meta::EndOfTrack, &[][..]),
+        Meta::Tempo(tempo) => (Meta::Tempo(tempo), &[][..]),
+        Meta::SmpteOffset(time) => (Meta::SmpteOffset(time), &[][..]),
+        Meta::TimeSignature(numerator, denominator, clocks, notes) => (
+            Meta::TimeSignature(numerator, denominator, clocks, notes),
+            &[][..],
+        ),
+        Meta::KeySignature(accidentals, minor) => (Meta::KeySignature(accidentals, minor), &[][..]),
+    };
+    (TrackEventKind::Meta(message), data)
+}
+
+/// Note-level view of a MIDI file.
+///
+/// Notes are paired from their `NoteOn`/`NoteOff` events, every other event is
+/// kept aside, so converting back with [`Score::to_smf`] yields a file that
+/// plays exactly like the original.
+#[derive(Debug, Clone)]
+pub struct Score {
+    pub header: Header,
+    pub track_count: usize,
+    /// Notes sorted by start tick.
+    pub notes: Vec<Note>,
+    /// Non-note events in their original order within each track.
+    pub events: Vec<ScoreEvent>,
+}
+
+impl Score {
+    pub fn new(header: Header, track_count: usize) -> Self {
+        Self {
+            header,
+            track_count,
+            notes: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Pairs every note on with the earliest unmatched note off of the same
+    /// pitch and channel. Notes still sounding at the end of their track are
+    /// closed there.
+    pub fn from_smf(smf: &Smf) -> Self {
+        let mut score = Self::new(smf.header, smf.tracks.len());
+
+        for (track, events) in smf.tracks.iter().enumerate() {
+            let mut time = 0_u64;
+            let mut sounding: HashMap<(u8, u8), VecDeque<(u64, u8)>> = HashMap::new();
+
+            for event in events {
+                time += u64::from(event.delta.as_int());
+
+                let release = match event.kind {
+                    TrackEventKind::Midi {
+                        channel,
+                        message: MidiMessage::NoteOn { key, vel },
+                    } if vel.as_int() > 0 => {
+                        sounding
+                            .entry((channel.as_int(), key.as_int()))
+                            .or_default()
+                            .push_back((time, vel.as_int()));
+                        continue;
+                    }
+                    TrackEventKind::Midi {
+                        channel,
+                        message: MidiMessage::NoteOn { key, .. },
+                    } => Some((channel, key, None)),
+                    TrackEventKind::Midi {
+                        channel,
+                        message: MidiMessage::NoteOff { key, vel },
+                    } => Some((channel, key, Some(vel.as_int()))),
+                    _ => None,
+                };
+
+                let started = release.and_then(|(channel, key, _)| {
+                    sounding
+                        .get_mut(&(channel.as_int(), key.as_int()))
+                        .and_then(VecDeque::pop_front)
+                });
+
+                match (release, started) {
+                    (Some((channel, key, release)), Some((start, velocity))) => {
+                        score.notes.push(Note {
+                            start,
+                            duration: time - start,
+                            pitch: key.as_int(),
+                            velocity,
+                            release,
+                            channel: channel.as_int(),
+                            track,
+                        });
+                    }
+                    _ => score.events.push(ScoreEvent::new(time, track, event.kind)),
+                }
+            }
+
+            let end = score
+                .events
+                .iter()
+                .rev()
+                .take_while(|event| event.track == track)
+                .find(|event| event.is_end_of_track())
+                .map_or(time, |event| event.tick);
+
+            for ((channel, pitch), starts) in sounding {
+                score
+                    .notes
+                    .extend(starts.into_iter().map(|(start, velocity)| Note {
+                        start,
+                        duration: end - start,
+                        pitch,
+                        velocity,
+                        release: Some(0),
+                        channel,
+                        track,
+                    }));
+            }
+        }
+
+        score.sort_notes();
+        score
+    }
+
+    /// Rebuilds a MIDI file. At equal ticks, note offs come first, then other
+    /// events, then note ons, and end of track markers are moved after the
+    /// last event of their track.
+    pub fn to_smf(&self) -> Smf<'_> {
+        let mut smf = Smf::new(self.header);
+        let mut tracks: Vec<Vec<(u64, u8, TrackEventKind)>> = vec![Vec::new(); self.track_count];
+
+        for event in &self.events {
+            if !event.is_end_of_track() {
+                tracks[event.track].push((event.tick, 1, event.kind()));
+            }
+        }
+
+        for note in &self.notes {
+            let channel = u4::new(note.channel);
+            let key = u7::new(note.pitch);
+            let on = MidiMessage::NoteOn {
+                key,
+                vel: u7::new(note.velocity.max(1)),
+            };
+            let off = match note.release {
+                Some(vel) => MidiMessage::NoteOff {
+                    key,
+                    vel: u7::new(vel),
+                },
+                None => MidiMessage::NoteOn {
+                    key,
+                    vel: u7::new(0),
+                },
+            };
+            let off_order = if note.duration == 0 { 3 } else { 0 };
+
+            let track = &mut tracks[note.track];
+            track.push((
+                note.start,
+                2,
+                TrackEventKind::Midi {
+                    channel,
+                    message: on,
+                },
+            ));
+            track.push((
+                note.end(),
+                off_order,
+                TrackEventKind::Midi {
+                    channel,
+                    message: off,
+                },
+            ));
+        }
+
+        for (index, mut events) in tracks.into_iter().enumerate() {
+            events.sort_by_key(|(tick, order, _)| (*tick, *order));
+
+            let last = events.last().map_or(0, |(tick, _, _)| *tick);
+            let end = self
+                .events
+                .iter()
+                .filter(|event| event.track == index && event.is_end_of_track())
+                .map(|event| event.tick)
+                .max();
+            if let Some(end) = end {
+                events.push((
+                    end.max(last),
+                    4,
+                    TrackEventKind::Meta(MetaMessage::EndOfTrack),
+                ));
+            }
+
+            smf.tracks.push(to_delta_track(
+                events.into_iter().map(|(tick, _, kind)| (tick, kind)),
+            ));
+        }
+
+        smf
+    }
+
+    /// Tick at which the last note or event ends.
+    pub fn end(&self) -> u64 {
+        let notes = self.notes.iter().map(Note::end).max().unwrap_or(0);
+        let events = self
+            .events
+            .iter()
+            .map(|event| event.tick)
+            .max()
+            .unwrap_or(0);
+        notes.max(events)
+    }
+
+    /// First channel carrying neither notes nor events, skipping the
+    /// percussion channel, or `None` when the score uses all of them.
+    pub fn free_channel(&self) -> Option<u8> {
+        let used = |channel: u8| {
+            self.notes.iter().any(|note| note.channel == channel)
+                || self.events.iter().any(|event| {
+                    matches!(event.kind(), TrackEventKind::Midi { channel: used, .. } if used.as_int() == channel)
+                })
+        };
+
+        (0..16)
+            .filter(|&channel| channel != PERCUSSION_CHANNEL)
+            .find(|&channel| !used(channel))
+    }
+
+    /// Pitched notes, leaving out the percussion channel.
+    pub fn pitched_notes(&self) -> impl Iterator<Item = &Note> {
+        self.notes.iter().filter(|note| !note.is_percussion())
+    }
+
+    /// Meta events of every track, in tick order.
+    pub fn meta_events(&self) -> impl Iterator<Item = (u64, MetaMessage<'_>)> {
+        let mut metas: Vec<_> = self
+            .events
+            .iter()
+            .filter_map(|event| match event.kind() {
+                TrackEventKind::Meta(message) => Some((event.tick, message)),
+                _ => None,
+            })
+            .collect();
+        metas.sort_by_key(|(tick, _)| *tick);
+        metas.into_iter()
+    }
+
+    /// Melodic line of every track and channel: the highest note of each
+    /// onset, onsets being quantized to `grid` ticks.
+    pub fn melodies(&self, grid: f64) -> BTreeMap<(usize, u8), Vec<&Note>> {
+        let quantize = |tick: u64| (tick as f64 / grid).round() as u64;
+
+        let mut melodies: BTreeMap<(usize, u8), Vec<&Note>> = BTreeMap::new();
+        for note in &self.notes {
+            let melody = melodies.entry((note.track, note.channel)).or_default();
+            match melody.last_mut() {
+                Some(last) if quantize(last.start) == quantize(note.start) => {
+                    if note.pitch > last.pitch {
+                        *last = note;
+                    }
+                }
+                _ => melody.push(note),
+            }
+        }
+        melodies
+    }
+
+    /// Keeps notes ordered by start, then pitch, after they were edited.
+    pub fn sort_notes(&mut self) {
+        self.notes
+            .sort_by_key(|note| (note.start, note.track, note.channel, note.pitch));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Reader, Writer};
+    use midly::{TrackEvent, num::u28};
+
+    static PRELUDE: &[u8] = include_bytes!("../../assets/midi/prelude1.mid");
+
+    /// Events of every track in file order. Runs of note ons, or of note
+    /// offs, sharing a tick are sorted, as the score orders notes starting
+    /// together by pitch and ends them in the order they started.
+    fn file_order(smf: &Smf) -> Vec<(usize, u64, String)> {
+        let note_class = |kind: &TrackEventKind| match kind {
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOn { vel, .. },
+                ..
+            } if vel.as_int() > 0 => Some(true),
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOn { .. } | MidiMessage::NoteOff { .. },
+                ..
+            } => Some(false),
+            _ => None,
+        };
+
+        let mut events = Vec::new();
+        for (track, track_events) in smf.tracks.iter().enumerate() {
+            let mut time = 0_u64;
+            let mut run: Vec<(usize, u64, String)> = Vec::new();
+            let mut run_class = None;
+            for event in track_events {
+                time += u64::from(event.delta.as_int());
+                let class = note_class(&event.kind);
+                if class.is_none()
+                    || class != run_class
+                    || run.last().is_some_and(|(_, tick, _)| *tick != time)
+                {
+                    run.sort();
+                    events.append(&mut run);
+                }
+                run_class = class;
+                run.push((track, time, format!("{:?}", event.kind)));
+            }
+            run.sort();
+            events.append(&mut run);
+        }
+        events
+    }
+
+    #[test]
+    fn round_trips_prelude() {
+        let smf = Smf::parse(PRELUDE).unwrap();
+        let score = Score::from_smf(&smf);
+        let rebuilt = score.to_smf();
+
+        assert_eq!(rebuilt.header, smf.header);
+        assert_eq!(file_order(&rebuilt), file_order(&smf));
+        assert_eq!(Score::from_smf(&rebuilt).notes, score.notes);
+        assert!(Writer::new().write(&rebuilt).is_ok());
+    }
+
+    #[test]
+    fn pairs_overlapping_and_orphaned_notes() {
+        let header = Smf::parse(PRELUDE).unwrap().header;
+        let key = u7::new(60);
+        let midi = |delta: u32, message| TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Midi {
+                channel: u4::new(0),
+                message,
+            },
+        };
+
+        let mut smf = Smf::new(header);
+        smf.tracks.push(vec![
+            midi(
+                0,
+                MidiMessage::NoteOff {
+                    key,
+                    vel: u7::new(0),
+                },
+            ),
+            midi(
+                0,
+                MidiMessage::NoteOn {
+                    key,
+                    vel: u7::new(80),
+                },
+            ),
+            midi(
+                10,
+                MidiMessage::NoteOn {
+                    key,
+                    vel: u7::new(90),
+                },
+            ),
+            midi(
+                10,
+                MidiMessage::NoteOn {
+                    key,
+                    vel: u7::new(0),
+                },
+            ),
+            midi(
+                10,
+                MidiMessage::NoteOff {
+                    key,
+                    vel: u7::new(64),
+                },
+            ),
+            TrackEvent {
+                delta: u28::new(5),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            },
+        ]);
+
+        let score = Score::from_smf(&smf);
+        let notes: Vec<_> = score
+            .notes
+            .iter()
+            .map(|note| (note.start, note.duration, note.velocity, note.release))
+            .collect();
+
+        assert_eq!(notes, [(0, 20, 80, None), (10, 20, 90, Some(64))]);
+        assert_eq!(score.events.len(), 2);
+        assert_eq!(file_order(&score.to_smf()), file_order(&smf));
+    }
+
+    #[test]
+    fn finds_free_channels_until_all_are_used() {
+        let header = Smf::parse(PRELUDE).unwrap().header;
+        let mut score = Score::new(header, 1);
+        let note = |channel: u8| Note {
+            start: 0,
+            duration: 1,
+            pitch: 60,
+            velocity: 80,
+            release: None,
+            channel,
+            track: 0,
+        };
+
+        assert_eq!(score.free_channel(), Some(0));
+        score.notes = (0..8).map(note).collect();
+        assert_eq!(score.free_channel(), Some(8));
+        score.notes.extend([8, 10].map(note));
+        assert_eq!(score.free_channel(), Some(11));
+        score.notes.extend((11..16).map(note));
+        assert_eq!(score.free_channel(), None);
+    }
+
+    #[test]
+    fn keeps_notes_after_gaps_longer_than_a_delta() {
+        let header = Smf::parse(PRELUDE).unwrap().header;
+        let mut score = Score::new(header, 1);
+        score.notes.push(Note {
+            start: 0x3000_0000,
+            duration: 240,
+            pitch: 60,
+            velocity: 80,
+            release: None,
+            channel: 0,
+            track: 0,
+        });
+
+        let bytes = Writer::new().write(&score.to_smf()).unwrap();
+        let read = Score::from_smf(&Reader::parse(&bytes).unwrap());
+
+        assert_eq!(read.notes, score.notes);
+    }
+}
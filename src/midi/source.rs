@@ -0,0 +1,127 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Error, Result};
+use std::{fs, path::PathBuf, str::FromStr};
+
+/// Extensions of the files picked up in directories.
+const MIDI_EXTENSIONS: [&str; 2] = ["mid", "midi"];
+
+/// A MIDI file, directory or glob pattern given with `--file`, with the
+/// weight it carries in the corpus, as in `bach/:0.7`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub pattern: String,
+    pub weight: f64,
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(source: &str) -> std::result::Result<Self, Self::Err> {
+        let Some((pattern, weight)) = source
+            .rsplit_once(':')
+            .and_then(|(pattern, weight)| Some((pattern, weight.parse::<f64>().ok()?)))
+        else {
+            return Ok(Self {
+                pattern: source.to_string(),
+                weight: 1.0,
+            });
+        };
+
+        if !weight.is_finite() || weight <= 0.0 {
+            return Err(format!("expected a positive weight, found `{weight}`"));
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            weight,
+        })
+    }
+}
+
+impl Source {
+    /// MIDI files this source stands for, in a stable order. Directories
+    /// are searched recursively and glob patterns expanded.
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let paths: Vec<PathBuf> = if self.pattern.contains(['*', '?', '[']) {
+            glob::glob(&self.pattern)
+                .map_err(Error::InvalidFilePattern)?
+                .map(|entry| entry.map_err(|err| Error::ReadInputFile(err.into())))
+                .collect::<Result<_>>()?
+        } else {
+            vec![PathBuf::from(&self.pattern)]
+        };
+
+        let mut files = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                Self::search(path, &mut files)?;
+            } else {
+                files.push(path);
+            }
+        }
+
+        if files.is_empty() {
+            return Err(Error::NoInputFiles(self.pattern.clone()));
+        }
+        Ok(files)
+    }
+
+    fn search(directory: PathBuf, files: &mut Vec<PathBuf>) -> Result<()> {
+        let mut entries: Vec<PathBuf> = fs::read_dir(directory)
+            .map_err(Error::ReadInputFile)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()
+            .map_err(Error::ReadInputFile)?;
+        entries.sort();
+
+        for path in entries {
+            if path.is_dir() {
+                Self::search(path, files)?;
+            } else if path.extension().is_some_and(|extension| {
+                MIDI_EXTENSIONS
+                    .iter()
+                    .any(|midi| extension.eq_ignore_ascii_case(midi))
+            }) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_an_optional_weight() {
+        let source = |pattern: &str, weight| Source {
+            pattern: pattern.to_string(),
+            weight,
+        };
+
+        assert_eq!("bach/:0.7".parse(), Ok(source("bach/", 0.7)));
+        assert_eq!("songs/*.mid:2".parse(), Ok(source("songs/*.mid", 2.0)));
+        assert_eq!("prelude.mid".parse(), Ok(source("prelude.mid", 1.0)));
+        assert_eq!("C:\\music".parse(), Ok(source("C:\\music", 1.0)));
+        assert_eq!("a:b:3".parse(), Ok(source("a:b", 3.0)));
+
+        for weight in ["0", "-1", "inf", "NaN"] {
+            assert!(format!("prelude.mid:{weight}").parse::<Source>().is_err());
+        }
+    }
+}
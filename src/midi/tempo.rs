@@ -0,0 +1,508 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use midly::{MetaMessage, Smf, Timing, TrackEventKind};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// Tempo assumed until the first `Tempo` event, 120 quarter notes per minute.
+pub const DEFAULT_TEMPO: u32 = 500_000;
+
+const EPSILON: f64 = 1.0e-9;
+
+/// Meter in effect from a given tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub numerator: u8,
+    /// Note value of one beat, `4` for a quarter note.
+    pub denominator: u16,
+}
+
+impl TimeSignature {
+    /// Length of one beat, in quarter notes.
+    pub fn beat_quarters(&self) -> f64 {
+        4.0 / f64::from(self.denominator)
+    }
+
+    /// Length of one bar, in quarter notes.
+    pub fn bar_quarters(&self) -> f64 {
+        f64::from(self.numerator) * self.beat_quarters()
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+impl Display for TimeSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// A musical position, both fields counted from zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub bar: u32,
+    /// Beat inside the bar, with the fractional part giving the offset
+    /// towards the next beat.
+    pub beat: f64,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{:.2}", self.bar + 1, self.beat + 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Resolution {
+    TicksPerQuarter(f64),
+    TicksPerSecond(f64),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TempoSegment {
+    tick: u64,
+    seconds: f64,
+    quarters: f64,
+    /// Microseconds per quarter note.
+    tempo: u32,
+    /// Whether a `Tempo` event sets this segment, rather than the default.
+    stated: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MeterSegment {
+    quarters: f64,
+    bar: u32,
+    signature: TimeSignature,
+    /// Whether a `TimeSignature` event sets this segment, rather than the
+    /// default.
+    stated: bool,
+}
+
+/// Converts between ticks, seconds and bar/beat positions, following every
+/// `Tempo` and `TimeSignature` event of a file.
+///
+/// Metrical files map ticks to quarter notes directly and derive seconds from
+/// the tempo. Timecode files map ticks to seconds directly and derive quarter
+/// notes from the tempo.
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    resolution: Resolution,
+    tempos: Vec<TempoSegment>,
+    meters: Vec<MeterSegment>,
+    end: u64,
+}
+
+impl TempoMap {
+    pub fn new(smf: &Smf) -> Self {
+        let resolution = match smf.header.timing {
+            Timing::Metrical(ticks) => {
+                Resolution::TicksPerQuarter(f64::from(ticks.as_int().max(1)))
+            }
+            Timing::Timecode(fps, ticks) => {
+                Resolution::TicksPerSecond(f64::from(fps.as_f32()) * f64::from(ticks.max(1)))
+            }
+        };
+
+        let mut tempo_events = Vec::new();
+        let mut meter_events = Vec::new();
+        let mut end = 0_u64;
+        for track in &smf.tracks {
+            let mut time = 0_u64;
+            for event in track {
+                time += u64::from(event.delta.as_int());
+                match event.kind {
+                    TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                        tempo_events.push((time, tempo.as_int().max(1)));
+                    }
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(
+                        numerator,
+                        denominator,
+                        ..,
+                    )) => {
+                        let signature = TimeSignature {
+                            numerator: numerator.max(1),
+                            denominator: 1_u16 << denominator.min(15),
+                        };
+                        meter_events.push((time, signature));
+                    }
+                    _ => {}
+                }
+            }
+            end = end.max(time);
+        }
+        tempo_events.sort_by_key(|(time, _)| *time);
+        meter_events.sort_by_key(|(time, _)| *time);
+
+        let mut map = Self {
+            resolution,
+            tempos: vec![TempoSegment {
+                tick: 0,
+                seconds: 0.0,
+                quarters: 0.0,
+                tempo: DEFAULT_TEMPO,
+                stated: false,
+            }],
+            meters: Vec::new(),
+            end,
+        };
+
+        for (tick, tempo) in tempo_events {
+            let seconds = map.seconds(tick);
+            let quarters = map.quarters(tick);
+            let segment = TempoSegment {
+                tick,
+                seconds,
+                quarters,
+                tempo,
+                stated: true,
+            };
+            match map.tempos.last_mut() {
+                Some(last) if last.tick == tick => *last = segment,
+                _ => map.tempos.push(segment),
+            }
+        }
+
+        map.meters.push(MeterSegment {
+            quarters: 0.0,
+            bar: 0,
+            signature: TimeSignature::default(),
+            stated: false,
+        });
+        for (tick, signature) in meter_events {
+            let quarters = map.quarters(tick);
+            let last = map.meters[map.meters.len() - 1];
+            let bars = (quarters - last.quarters) / last.signature.bar_quarters();
+            let segment = MeterSegment {
+                quarters,
+                bar: last.bar + (bars - EPSILON).ceil().max(0.0) as u32,
+                signature,
+                stated: true,
+            };
+            match map.meters.last_mut() {
+                Some(last) if (last.quarters - quarters).abs() < EPSILON => {
+                    *last = MeterSegment {
+                        bar: last.bar,
+                        ..segment
+                    }
+                }
+                _ => map.meters.push(segment),
+            }
+        }
+
+        map
+    }
+
+    /// Length of the whole file, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.seconds(self.end)
+    }
+
+    /// Number of bars the file spans, counting the last one even when it is
+    /// only started.
+    pub fn bar_count(&self) -> u32 {
+        let end = self.position(self.end);
+        if end.beat > EPSILON {
+            end.bar + 1
+        } else {
+            end.bar
+        }
+    }
+
+    /// Every tempo of the file, as its first tick and its speed in quarter
+    /// notes per minute.
+    pub fn tempo_changes(&self) -> impl Iterator<Item = (u64, f64)> + '_ {
+        self.tempos
+            .iter()
+            .map(|segment| (segment.tick, 60.0e6 / f64::from(segment.tempo)))
+    }
+
+    /// Every meter of the file, as its first tick, first bar and signature.
+    pub fn time_signature_changes(&self) -> impl Iterator<Item = (u64, u32, TimeSignature)> + '_ {
+        self.meters.iter().map(|meter| {
+            (
+                self.tick_at_quarters(meter.quarters),
+                meter.bar,
+                meter.signature,
+            )
+        })
+    }
+
+    /// Whether the file sets its opening tempo, rather than leaving the
+    /// MIDI default of 120 bpm.
+    pub fn states_tempo(&self) -> bool {
+        self.tempos[0].stated
+    }
+
+    /// Whether the file sets its opening meter, rather than leaving the
+    /// MIDI default of 4/4.
+    pub fn states_meter(&self) -> bool {
+        self.meters[0].stated
+    }
+
+    pub fn seconds(&self, tick: u64) -> f64 {
+        match self.resolution {
+            Resolution::TicksPerSecond(rate) => tick as f64 / rate,
+            Resolution::TicksPerQuarter(ppq) => {
+                let segment = self.tempo_segment_at_tick(tick);
+                let quarters = (tick - segment.tick) as f64 / ppq;
+                segment.seconds + quarters * f64::from(segment.tempo) / 1.0e6
+            }
+        }
+    }
+
+    pub fn tick_at_seconds(&self, seconds: f64) -> u64 {
+        let seconds = seconds.max(0.0);
+        match self.resolution {
+            Resolution::TicksPerSecond(rate) => (seconds * rate).round() as u64,
+            Resolution::TicksPerQuarter(ppq) => {
+                let index = self
+                    .tempos
+                    .partition_point(|segment| segment.seconds <= seconds)
+                    .saturating_sub(1);
+                let segment = self.tempos[index];
+                let quarters = (seconds - segment.seconds) * 1.0e6 / f64::from(segment.tempo);
+                segment.tick + (quarters * ppq).round() as u64
+            }
+        }
+    }
+
+    /// Position of `tick` counted in quarter notes from the start.
+    pub fn quarters(&self, tick: u64) -> f64 {
+        match self.resolution {
+            Resolution::TicksPerQuarter(ppq) => tick as f64 / ppq,
+            Resolution::TicksPerSecond(_) => {
+                let segment = self.tempo_segment_at_tick(tick);
+                let seconds = self.seconds(tick) - segment.seconds;
+                segment.quarters + seconds * 1.0e6 / f64::from(segment.tempo)
+            }
+        }
+    }
+
+    pub fn tick_at_quarters(&self, quarters: f64) -> u64 {
+        let quarters = quarters.max(0.0);
+        match self.resolution {
+            Resolution::TicksPerQuarter(ppq) => (quarters * ppq).round() as u64,
+            Resolution::TicksPerSecond(rate) => {
+                let index = self
+                    .tempos
+                    .partition_point(|segment| segment.quarters <= quarters)
+                    .saturating_sub(1);
+                let segment = self.tempos[index];
+                let seconds = (quarters - segment.quarters) * f64::from(segment.tempo) / 1.0e6;
+                ((segment.seconds + seconds) * rate).round() as u64
+            }
+        }
+    }
+
+    pub fn position(&self, tick: u64) -> Position {
+        let quarters = self.quarters(tick);
+        let meter = self.meter_segment_at_quarters(quarters);
+        let offset = quarters - meter.quarters;
+        let bar_quarters = meter.signature.bar_quarters();
+        let bars = (offset / bar_quarters + EPSILON).floor();
+
+        Position {
+            bar: meter.bar + bars as u32,
+            beat: ((offset - bars * bar_quarters) / meter.signature.beat_quarters()).max(0.0),
+        }
+    }
+
+    pub fn tick_at_position(&self, position: Position) -> u64 {
+        let index = self
+            .meters
+            .partition_point(|meter| meter.bar <= position.bar)
+            .saturating_sub(1);
+        let meter = self.meters[index];
+        let quarters = meter.quarters
+            + f64::from(position.bar - meter.bar) * meter.signature.bar_quarters()
+            + position.beat * meter.signature.beat_quarters();
+
+        self.tick_at_quarters(quarters)
+    }
+
+    /// First tick of bar number `bar`, counted from zero.
+    pub fn bar_start(&self, bar: u32) -> u64 {
+        self.tick_at_position(Position { bar, beat: 0.0 })
+    }
+
+    /// Meter in effect at `tick`.
+    pub fn time_signature(&self, tick: u64) -> TimeSignature {
+        self.meter_segment_at_quarters(self.quarters(tick))
+            .signature
+    }
+
+    /// First tick of the beat following the one containing `tick`.
+    pub fn next_beat(&self, tick: u64) -> u64 {
+        let position = self.position(tick);
+        let next = self.tick_at_position(Position {
+            bar: position.bar,
+            beat: (position.beat + EPSILON).floor() + 1.0,
+        });
+        next.max(tick + 1)
+    }
+
+    /// Ticks of one quarter note at `tick`.
+    pub fn ticks_per_quarter(&self, tick: u64) -> f64 {
+        match self.resolution {
+            Resolution::TicksPerQuarter(ppq) => ppq,
+            Resolution::TicksPerSecond(rate) => {
+                rate * f64::from(self.tempo_segment_at_tick(tick).tempo) / 1.0e6
+            }
+        }
+    }
+
+    fn tempo_segment_at_tick(&self, tick: u64) -> TempoSegment {
+        let index = self
+            .tempos
+            .partition_point(|segment| segment.tick <= tick)
+            .saturating_sub(1);
+        self.tempos[index]
+    }
+
+    fn meter_segment_at_quarters(&self, quarters: f64) -> MeterSegment {
+        let index = self
+            .meters
+            .partition_point(|meter| meter.quarters <= quarters + EPSILON)
+            .saturating_sub(1);
+        self.meters[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{
+        Format, Fps, Header, TrackEvent,
+        num::{u15, u24, u28},
+    };
+
+    fn smf(timing: Timing, events: Vec<(u32, MetaMessage<'static>)>) -> Smf<'static> {
+        let mut track: Vec<TrackEvent> = events
+            .into_iter()
+            .map(|(delta, message)| TrackEvent {
+                delta: u28::new(delta),
+                kind: TrackEventKind::Meta(message),
+            })
+            .collect();
+        track.push(TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+
+        let mut smf = Smf::new(Header::new(Format::SingleTrack, timing));
+        smf.tracks.push(track);
+        smf
+    }
+
+    fn assert_close(found: f64, expected: f64) {
+        assert!((found - expected).abs() < 1.0e-9, "{found} != {expected}");
+    }
+
+    /// 480 ticks per quarter, 120 bpm then 60 bpm from the third quarter.
+    fn metrical() -> TempoMap {
+        TempoMap::new(&smf(
+            Timing::Metrical(u15::new(480)),
+            vec![
+                (0, MetaMessage::Tempo(u24::new(500_000))),
+                (960, MetaMessage::Tempo(u24::new(1_000_000))),
+                (1920, MetaMessage::Text(b"end")),
+            ],
+        ))
+    }
+
+    /// 1000 ticks per second, 120 bpm then 60 bpm from the second second.
+    fn timecode() -> TempoMap {
+        TempoMap::new(&smf(
+            Timing::Timecode(Fps::Fps25, 40),
+            vec![
+                (1000, MetaMessage::Tempo(u24::new(1_000_000))),
+                (2000, MetaMessage::Text(b"end")),
+            ],
+        ))
+    }
+
+    #[test]
+    fn converts_ticks_and_seconds_across_tempo_changes() {
+        let map = metrical();
+
+        assert_close(map.seconds(480), 0.5);
+        assert_close(map.seconds(960), 1.0);
+        assert_close(map.seconds(1440), 2.0);
+        assert_close(map.duration(), 5.0);
+        for tick in [0, 480, 960, 1200, 1440, 2880] {
+            assert_eq!(map.tick_at_seconds(map.seconds(tick)), tick);
+        }
+        assert_eq!(map.tick_at_seconds(-1.0), 0);
+
+        let tempos: Vec<_> = map.tempo_changes().collect();
+        assert_eq!(tempos, [(0, 120.0), (960, 60.0)]);
+        assert!(map.states_tempo());
+        assert!(!map.states_meter());
+    }
+
+    #[test]
+    fn converts_quarters_of_timecode_files_across_tempo_changes() {
+        let map = timecode();
+
+        assert_close(map.seconds(1500), 1.5);
+        assert_close(map.quarters(1000), 2.0);
+        assert_close(map.quarters(2000), 3.0);
+        assert_eq!(map.tick_at_quarters(1.0), 500);
+        assert_eq!(map.tick_at_quarters(2.5), 1500);
+        assert_eq!(map.tick_at_quarters(3.0), 2000);
+        assert_close(map.ticks_per_quarter(0), 500.0);
+        assert_close(map.ticks_per_quarter(1000), 1000.0);
+        assert!(!map.states_tempo());
+    }
+
+    #[test]
+    fn counts_bars_and_beats_across_meter_changes() {
+        let map = TempoMap::new(&smf(
+            Timing::Metrical(u15::new(480)),
+            vec![
+                (3840, MetaMessage::TimeSignature(3, 2, 24, 8)),
+                (2880, MetaMessage::TimeSignature(6, 3, 24, 8)),
+                (720, MetaMessage::Text(b"end")),
+            ],
+        ));
+
+        assert_eq!(map.position(0), Position { bar: 0, beat: 0.0 });
+        assert_eq!(map.position(2160), Position { bar: 1, beat: 0.5 });
+        assert_eq!(map.position(5280), Position { bar: 3, beat: 0.0 });
+        assert_eq!(map.position(6960), Position { bar: 4, beat: 1.0 });
+        assert_eq!(map.bar_start(2), 3840);
+        assert_eq!(map.bar_start(4), 6720);
+        assert_eq!(map.bar_start(5), 8160);
+        assert_eq!(map.bar_count(), 5);
+        assert_eq!(
+            map.time_signature(7000),
+            TimeSignature {
+                numerator: 6,
+                denominator: 8
+            }
+        );
+        assert_eq!(map.next_beat(6750), 6960);
+    }
+}
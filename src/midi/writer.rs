@@ -0,0 +1,683 @@
+/*
+ * Copyright 2025 Nicolas Spijkerman
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{Error, Result};
+use midly::{
+    Format, Fps, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind, num::u28,
+};
+
+const MAX_VARIABLE_LENGTH: u32 = 0x0FFF_FFFF;
+
+/// Encodes a parsed [`Smf`] back into Standard MIDI File bytes.
+///
+/// Every event kind and meta message understood by `midly` is written back
+/// verbatim, so parsing the output yields the same tracks as the input.
+#[derive(Debug, Clone, Copy)]
+pub struct Writer {
+    running_status: bool,
+    format: Option<Format>,
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Self {
+            running_status: true,
+            format: None,
+        }
+    }
+
+    /// Omit repeated channel status bytes (enabled by default).
+    pub fn running_status(mut self, enabled: bool) -> Self {
+        self.running_status = enabled;
+        self
+    }
+
+    /// Convert the file to `format` before writing instead of keeping the
+    /// format of the source. Only single-track and parallel files can be
+    /// converted into each other.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn write(&self, smf: &Smf) -> Result<Vec<u8>> {
+        let source = smf.header.format;
+        let target = self.format.unwrap_or(source);
+
+        let tracks = match (source, target) {
+            (from, to) if from == to => smf.tracks.clone(),
+            (Format::Parallel, Format::SingleTrack) => merge_tracks(&smf.tracks),
+            (Format::SingleTrack, Format::Parallel) => split_channels(&smf.tracks),
+            (from, to) => {
+                return Err(Error::WriteMidi(format!(
+                    "Cannot convert a {from:?} file to {to:?}"
+                )));
+            }
+        };
+
+        if target == Format::SingleTrack && tracks.len() != 1 {
+            return Err(Error::WriteMidi(format!(
+                "A single-track file must contain exactly one track, found {}",
+                tracks.len()
+            )));
+        }
+
+        let track_count = u16::try_from(tracks.len())
+            .map_err(|_| Error::WriteMidi(format!("Too many tracks: {}", tracks.len())))?;
+
+        let mut output = Vec::new();
+
+        output.extend_from_slice(b"MThd");
+        output.extend_from_slice(&6_u32.to_be_bytes());
+        output.extend_from_slice(&format_code(target).to_be_bytes());
+        output.extend_from_slice(&track_count.to_be_bytes());
+        output.extend_from_slice(&timing_code(smf.header.timing).to_be_bytes());
+
+        for track in &tracks {
+            let data = self.encode_track(track)?;
+            let length = u32::try_from(data.len())
+                .map_err(|_| Error::WriteMidi(format!("Track too long: {} bytes", data.len())))?;
+
+            output.extend_from_slice(b"MTrk");
+            output.extend_from_slice(&length.to_be_bytes());
+            output.extend_from_slice(&data);
+        }
+
+        Ok(output)
+    }
+
+    fn encode_track(&self, track: &[TrackEvent]) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut running_status: Option<u8> = None;
+
+        for event in track {
+            write_variable_length(&mut data, event.delta.as_int())?;
+
+            match event.kind {
+                TrackEventKind::Midi { channel, message } => {
+                    let status = status_byte(&message) | channel.as_int();
+                    if !self.running_status || running_status != Some(status) {
+                        data.push(status);
+                    }
+                    running_status = Some(status);
+                    write_midi_data(&mut data, &message);
+                }
+                TrackEventKind::SysEx(bytes) => {
+                    running_status = None;
+                    data.push(0xF0);
+                    write_bytes(&mut data, bytes)?;
+                }
+                TrackEventKind::Escape(bytes) => {
+                    running_status = None;
+                    data.push(0xF7);
+                    write_bytes(&mut data, bytes)?;
+                }
+                TrackEventKind::Meta(message) => {
+                    running_status = None;
+                    data.push(0xFF);
+                    write_meta(&mut data, &message)?;
+                }
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+fn format_code(format: Format) -> u16 {
+    match format {
+        Format::SingleTrack => 0,
+        Format::Parallel => 1,
+        Format::Sequential => 2,
+    }
+}
+
+fn timing_code(timing: Timing) -> u16 {
+    match timing {
+        Timing::Metrical(ticks) => ticks.as_int(),
+        Timing::Timecode(fps, ticks) => {
+            let frames: i8 = match fps {
+                Fps::Fps24 => -24,
+                Fps::Fps25 => -25,
+                Fps::Fps29 => -29,
+                Fps::Fps30 => -30,
+            };
+            u16::from_be_bytes([frames as u8, ticks])
+        }
+    }
+}
+
+fn smpte_fps_code(fps: Fps) -> u8 {
+    match fps {
+        Fps::Fps24 => 0,
+        Fps::Fps25 => 1,
+        Fps::Fps29 => 2,
+        Fps::Fps30 => 3,
+    }
+}
+
+fn status_byte(message: &MidiMessage) -> u8 {
+    match message {
+        MidiMessage::NoteOff { .. } => 0x80,
+        MidiMessage::NoteOn { .. } => 0x90,
+        MidiMessage::Aftertouch { .. } => 0xA0,
+        MidiMessage::Controller { .. } => 0xB0,
+        MidiMessage::ProgramChange { .. } => 0xC0,
+        MidiMessage::ChannelAftertouch { .. } => 0xD0,
+        MidiMessage::PitchBend { .. } => 0xE0,
+    }
+}
+
+fn write_midi_data(data: &mut Vec<u8>, message: &MidiMessage) {
+    match *message {
+        MidiMessage::NoteOff { key, vel }
+        | MidiMessage::NoteOn { key, vel }
+        | MidiMessage::Aftertouch { key, vel } => {
+            data.push(key.as_int());
+            data.push(vel.as_int());
+        }
+        MidiMessage::Controller { controller, value } => {
+            data.push(controller.as_int());
+            data.push(value.as_int());
+        }
+        MidiMessage::ProgramChange { program } => data.push(program.as_int()),
+        MidiMessage::ChannelAftertouch { vel } => data.push(vel.as_int()),
+        MidiMessage::PitchBend { bend } => {
+            let value = bend.0.as_int();
+            data.push((value & 0x7F) as u8);
+            data.push(((value >> 7) & 0x7F) as u8);
+        }
+    }
+}
+
+fn write_meta(data: &mut Vec<u8>, message: &MetaMessage) -> Result<()> {
+    match *message {
+        MetaMessage::TrackNumber(number) => {
+            data.push(0x00);
+            match number {
+                Some(number) => write_bytes(data, &number.to_be_bytes())?,
+                None => data.push(0x00),
+            }
+        }
+        MetaMessage::Text(text) => write_text(data, 0x01, text)?,
+        MetaMessage::Copyright(text) => write_text(data, 0x02, text)?,
+        MetaMessage::TrackName(text) => write_text(data, 0x03, text)?,
+        MetaMessage::InstrumentName(text) => write_text(data, 0x04, text)?,
+        MetaMessage::Lyric(text) => write_text(data, 0x05, text)?,
+        MetaMessage::Marker(text) => write_text(data, 0x06, text)?,
+        MetaMessage::CuePoint(text) => write_text(data, 0x07, text)?,
+        MetaMessage::ProgramName(text) => write_text(data, 0x08, text)?,
+        MetaMessage::DeviceName(text) => write_text(data, 0x09, text)?,
+        MetaMessage::MidiChannel(channel) => {
+            data.push(0x20);
+            write_bytes(data, &[channel.as_int()])?;
+        }
+        MetaMessage::MidiPort(port) => {
+            data.push(0x21);
+            write_bytes(data, &[port.as_int()])?;
+        }
+        MetaMessage::EndOfTrack => {
+            data.push(0x2F);
+            data.push(0x00);
+        }
+        MetaMessage::Tempo(tempo) => {
+            data.push(0x51);
+            write_bytes(data, &tempo.as_int().to_be_bytes()[1..])?;
+        }
+        MetaMessage::SmpteOffset(time) => {
+            data.push(0x54);
+            write_bytes(
+                data,
+                &[
+                    time.hour() | (smpte_fps_code(time.fps()) << 5),
+                    time.minute(),
+                    time.second(),
+                    time.frame(),
+                    time.subframe(),
+                ],
+            )?;
+        }
+        MetaMessage::TimeSignature(numerator, denominator, clocks, notes) => {
+            data.push(0x58);
+            write_bytes(data, &[numerator, denominator, clocks, notes])?;
+        }
+        MetaMessage::KeySignature(accidentals, minor) => {
+            data.push(0x59);
+            write_bytes(data, &[accidentals as u8, u8::from(minor)])?;
+        }
+        MetaMessage::SequencerSpecific(bytes) => write_text(data, 0x7F, bytes)?,
+        MetaMessage::Unknown(kind, bytes) => write_text(data, kind, bytes)?,
+    }
+
+    Ok(())
+}
+
+fn write_text(data: &mut Vec<u8>, kind: u8, bytes: &[u8]) -> Result<()> {
+    data.push(kind);
+    write_bytes(data, bytes)
+}
+
+fn write_bytes(data: &mut Vec<u8>, bytes: &[u8]) -> Result<()> {
+    let length = u32::try_from(bytes.len()).unwrap_or(u32::MAX);
+    write_variable_length(data, length)?;
+    data.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn write_variable_length(data: &mut Vec<u8>, value: u32) -> Result<()> {
+    if value > MAX_VARIABLE_LENGTH {
+        return Err(Error::WriteMidi(format!(
+            "Value {value} does not fit in a variable-length quantity"
+        )));
+    }
+
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        data.push(((value >> shift) & 0x7F) as u8 | 0x80);
+        shift -= 7;
+    }
+    data.push((value & 0x7F) as u8);
+
+    Ok(())
+}
+
+/// Interleaves every track into one, ordered by absolute time. Events at the
+/// same tick keep the order of their track index, and only the last end of
+/// track marker is kept.
+fn merge_tracks<'a>(tracks: &[Vec<TrackEvent<'a>>]) -> Vec<Vec<TrackEvent<'a>>> {
+    let mut timed = Vec::new();
+    let mut end = 0_u64;
+
+    for track in tracks {
+        let mut time = 0_u64;
+        for event in track {
+            time += u64::from(event.delta.as_int());
+            if event.kind == TrackEventKind::Meta(MetaMessage::EndOfTrack) {
+                end = end.max(time);
+            } else {
+                timed.push((time, event.kind));
+            }
+        }
+    }
+
+    timed.sort_by_key(|(time, _)| *time);
+    end = end.max(timed.last().map_or(0, |(time, _)| *time));
+    timed.push((end, TrackEventKind::Meta(MetaMessage::EndOfTrack)));
+
+    vec![to_delta_track(timed)]
+}
+
+/// Splits a single-track file into a conductor track holding every non-channel
+/// event, followed by one track per MIDI channel in use.
+fn split_channels<'a>(tracks: &[Vec<TrackEvent<'a>>]) -> Vec<Vec<TrackEvent<'a>>> {
+    let mut conductor = Vec::new();
+    let mut channels: [Vec<(u64, TrackEventKind<'a>)>; 16] = Default::default();
+    let mut end = 0_u64;
+
+    for track in tracks {
+        let mut time = 0_u64;
+        for event in track {
+            time += u64::from(event.delta.as_int());
+            match event.kind {
+                TrackEventKind::Midi { channel, .. } => {
+                    channels[usize::from(channel.as_int())].push((time, event.kind));
+                }
+                TrackEventKind::Meta(MetaMessage::EndOfTrack) => end = end.max(time),
+                kind => conductor.push((time, kind)),
+            }
+        }
+    }
+
+    let end_of_track = TrackEventKind::Meta(MetaMessage::EndOfTrack);
+
+    conductor.push((end, end_of_track));
+    let mut split = vec![to_delta_track(conductor)];
+
+    for mut events in channels.into_iter().filter(|events| !events.is_empty()) {
+        events.push((end, end_of_track));
+        split.push(to_delta_track(events));
+    }
+
+    split
+}
+
+/// Track of `events`, given at absolute times in order. Gaps longer than a
+/// delta time can hold are bridged with empty text events, so every event
+/// keeps its time.
+pub fn to_delta_track<'a>(
+    events: impl IntoIterator<Item = (u64, TrackEventKind<'a>)>,
+) -> Vec<TrackEvent<'a>> {
+    let mut track = Vec::new();
+    let mut previous = 0_u64;
+
+    for (time, kind) in events {
+        let mut delta = time - previous;
+        while delta > u64::from(MAX_VARIABLE_LENGTH) {
+            track.push(TrackEvent {
+                delta: u28::new(MAX_VARIABLE_LENGTH),
+                kind: TrackEventKind::Meta(MetaMessage::Text(&[])),
+            });
+            delta -= u64::from(MAX_VARIABLE_LENGTH);
+        }
+        track.push(TrackEvent {
+            delta: u28::new(delta as u32),
+            kind,
+        });
+        previous = time;
+    }
+
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{
+        Header, SmpteTime,
+        num::{u4, u7, u14, u15, u24},
+    };
+
+    static PRELUDE: &[u8] = include_bytes!("../../assets/midi/prelude1.mid");
+
+    fn event(delta: u32, kind: TrackEventKind) -> TrackEvent {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind,
+        }
+    }
+
+    /// Events of `tracks` in file order, one track after the other, without
+    /// their end of track markers.
+    fn absolute_events<'a>(tracks: &[Vec<TrackEvent<'a>>]) -> Vec<(u64, TrackEventKind<'a>)> {
+        tracks
+            .iter()
+            .flat_map(|track| {
+                let mut time = 0_u64;
+                track.iter().map(move |event| {
+                    time += u64::from(event.delta.as_int());
+                    (time, event.kind)
+                })
+            })
+            .filter(|(_, kind)| *kind != TrackEventKind::Meta(MetaMessage::EndOfTrack))
+            .collect()
+    }
+
+    fn every_meta_message() -> Smf<'static> {
+        let smpte = SmpteTime::new(1, 2, 3, 4, 5, Fps::Fps29).unwrap();
+        let metas = [
+            MetaMessage::TrackNumber(Some(7)),
+            MetaMessage::TrackNumber(None),
+            MetaMessage::Text(b"text"),
+            MetaMessage::Copyright(b"copyright"),
+            MetaMessage::TrackName(b"name"),
+            MetaMessage::InstrumentName(b"instrument"),
+            MetaMessage::Lyric(b"lyric"),
+            MetaMessage::Marker(b"marker"),
+            MetaMessage::CuePoint(b"cue"),
+            MetaMessage::ProgramName(b"program"),
+            MetaMessage::DeviceName(b"device"),
+            MetaMessage::MidiChannel(u4::new(9)),
+            MetaMessage::MidiPort(u7::new(3)),
+            MetaMessage::Tempo(u24::new(500_000)),
+            MetaMessage::SmpteOffset(smpte),
+            MetaMessage::TimeSignature(6, 3, 24, 8),
+            MetaMessage::KeySignature(-3, true),
+            MetaMessage::KeySignature(2, false),
+            MetaMessage::SequencerSpecific(&[0x00, 0x41, 0x10]),
+            MetaMessage::Unknown(0x60, b"unknown"),
+        ];
+
+        let mut track: Vec<_> = metas
+            .into_iter()
+            .map(|meta| event(0, TrackEventKind::Meta(meta)))
+            .collect();
+        track.push(event(
+            0,
+            TrackEventKind::SysEx(&[0x7E, 0x7F, 0x09, 0x01, 0xF7]),
+        ));
+        track.push(event(10, TrackEventKind::Escape(&[0xF3, 0x01])));
+        track.push(event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)));
+
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Timecode(Fps::Fps25, 40),
+        ));
+        smf.tracks.push(track);
+        smf
+    }
+
+    fn every_midi_message() -> Vec<TrackEvent<'static>> {
+        let channel = u4::new(2);
+        let messages = [
+            MidiMessage::NoteOn {
+                key: u7::new(60),
+                vel: u7::new(100),
+            },
+            MidiMessage::NoteOn {
+                key: u7::new(64),
+                vel: u7::new(90),
+            },
+            MidiMessage::NoteOff {
+                key: u7::new(60),
+                vel: u7::new(0),
+            },
+            MidiMessage::Aftertouch {
+                key: u7::new(64),
+                vel: u7::new(20),
+            },
+            MidiMessage::Controller {
+                controller: u7::new(64),
+                value: u7::new(127),
+            },
+            MidiMessage::ProgramChange {
+                program: u7::new(40),
+            },
+            MidiMessage::ChannelAftertouch { vel: u7::new(33) },
+            MidiMessage::PitchBend {
+                bend: midly::PitchBend(u14::new(0x1234)),
+            },
+        ];
+
+        let mut track: Vec<_> = messages
+            .into_iter()
+            .map(|message| event(48, TrackEventKind::Midi { channel, message }))
+            .collect();
+        track.push(event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)));
+        track
+    }
+
+    #[test]
+    fn round_trips_prelude() {
+        let smf = Smf::parse(PRELUDE).unwrap();
+        let bytes = Writer::new().write(&smf).unwrap();
+        let parsed = Smf::parse(&bytes).unwrap();
+
+        assert_eq!(parsed.header, smf.header);
+        assert_eq!(parsed.tracks, smf.tracks);
+    }
+
+    #[test]
+    fn round_trips_prelude_without_running_status() {
+        let smf = Smf::parse(PRELUDE).unwrap();
+        let compact = Writer::new().write(&smf).unwrap();
+        let verbose = Writer::new().running_status(false).write(&smf).unwrap();
+        let parsed = Smf::parse(&verbose).unwrap();
+
+        assert!(verbose.len() >= compact.len());
+        assert_eq!(parsed.tracks, smf.tracks);
+    }
+
+    #[test]
+    fn round_trips_every_meta_message() {
+        let smf = every_meta_message();
+        let bytes = Writer::new().write(&smf).unwrap();
+        let parsed = Smf::parse(&bytes).unwrap();
+
+        assert_eq!(parsed.header, smf.header);
+        assert_eq!(parsed.tracks, smf.tracks);
+    }
+
+    #[test]
+    fn round_trips_every_midi_message() {
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(96)),
+        ));
+        smf.tracks.push(every_midi_message());
+
+        for running_status in [true, false] {
+            let bytes = Writer::new()
+                .running_status(running_status)
+                .write(&smf)
+                .unwrap();
+            assert_eq!(Smf::parse(&bytes).unwrap().tracks, smf.tracks);
+        }
+    }
+
+    #[test]
+    fn encodes_minor_key_signature_with_mode_flag_set() {
+        let mut data = Vec::new();
+        write_meta(&mut data, &MetaMessage::KeySignature(-3, true)).unwrap();
+
+        assert_eq!(data, [0x59, 0x02, 0xFD, 0x01]);
+    }
+
+    #[test]
+    fn keeps_copyright_distinct_from_text() {
+        let mut data = Vec::new();
+        write_meta(&mut data, &MetaMessage::Copyright(b"(c)")).unwrap();
+
+        assert_eq!(data, [0x02, 0x03, b'(', b'c', b')']);
+    }
+
+    #[test]
+    fn encodes_variable_length_quantities() {
+        let cases: [(u32, &[u8]); 6] = [
+            (0x00, &[0x00]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x81, 0x00]),
+            (0x3FFF, &[0xFF, 0x7F]),
+            (0x20_0000, &[0x81, 0x80, 0x80, 0x00]),
+            (0x0FFF_FFFF, &[0xFF, 0xFF, 0xFF, 0x7F]),
+        ];
+
+        for (value, expected) in cases {
+            let mut data = Vec::new();
+            write_variable_length(&mut data, value).unwrap();
+            assert_eq!(data, expected, "value {value:#X}");
+        }
+
+        assert!(write_variable_length(&mut Vec::new(), 0x1000_0000).is_err());
+    }
+
+    #[test]
+    fn converts_prelude_to_single_track_and_back() {
+        let smf = Smf::parse(PRELUDE).unwrap();
+
+        let single = Writer::new()
+            .format(Format::SingleTrack)
+            .write(&smf)
+            .unwrap();
+        let single = Smf::parse(&single).unwrap();
+        assert_eq!(single.header.format, Format::SingleTrack);
+        assert_eq!(single.tracks.len(), 1);
+        let mut merged = absolute_events(&smf.tracks);
+        merged.sort_by_key(|(time, _)| *time);
+        assert_eq!(absolute_events(&single.tracks), merged);
+
+        let parallel = Writer::new()
+            .format(Format::Parallel)
+            .write(&single)
+            .unwrap();
+        let parallel = Smf::parse(&parallel).unwrap();
+        assert_eq!(parallel.header.format, Format::Parallel);
+        let channel_of = |kind: &TrackEventKind| match kind {
+            TrackEventKind::Midi { channel, .. } => Some(channel.as_int()),
+            _ => None,
+        };
+        let mut expected = vec![None];
+        expected.extend(
+            (0..16)
+                .map(Some)
+                .filter(|&channel| merged.iter().any(|(_, kind)| channel_of(kind) == channel)),
+        );
+        let expected: Vec<Vec<_>> = expected
+            .into_iter()
+            .map(|channel| {
+                merged
+                    .iter()
+                    .filter(|(_, kind)| channel_of(kind) == channel)
+                    .copied()
+                    .collect()
+            })
+            .collect();
+        let split: Vec<_> = parallel
+            .tracks
+            .iter()
+            .map(|track| absolute_events(std::slice::from_ref(track)))
+            .collect();
+        assert_eq!(split, expected);
+        assert!(parallel.tracks.iter().all(|track| {
+            track.last().map(|event| event.kind)
+                == Some(TrackEventKind::Meta(MetaMessage::EndOfTrack))
+        }));
+    }
+
+    #[test]
+    fn rejects_sequential_conversion() {
+        let mut smf = Smf::new(Header::new(
+            Format::Sequential,
+            Timing::Metrical(u15::new(96)),
+        ));
+        smf.tracks.push(every_midi_message());
+
+        assert!(Writer::new().format(Format::Parallel).write(&smf).is_err());
+    }
+
+    #[test]
+    fn bridges_gaps_longer_than_a_delta() {
+        let on = TrackEventKind::Midi {
+            channel: u4::new(0),
+            message: MidiMessage::NoteOn {
+                key: u7::new(60),
+                vel: u7::new(80),
+            },
+        };
+        let pad = TrackEventKind::Meta(MetaMessage::Text(&[]));
+        let gap = 2 * u64::from(MAX_VARIABLE_LENGTH) + 5;
+
+        let track = to_delta_track([(0, on), (gap, on)]);
+
+        assert_eq!(
+            track,
+            [
+                event(0, on),
+                event(MAX_VARIABLE_LENGTH, pad),
+                event(MAX_VARIABLE_LENGTH, pad),
+                event(5, on),
+            ]
+        );
+    }
+}